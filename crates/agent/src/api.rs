@@ -1,37 +1,58 @@
-use std::str::FromStr;
+use std::{path::PathBuf, str::FromStr, sync::Arc, time::Duration};
 
 use axum::{
     Json, Router,
-    body::Bytes,
+    body::{Body, Bytes},
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
+};
+use nimble_core::{
+    config::{BuilderType, DeployConfig, parse_duration_str, validate_app_name},
+    deployers::{LogLine, LogOptions, PublishedPort, select_deployer},
 };
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::error::TrySendError;
+use tokio::{
+    fs::File,
+    io::AsyncReadExt,
+    sync::mpsc::{self, error::TrySendError},
+};
 use uuid::Uuid;
 
 use crate::{
     db,
     state::ApiState,
-    workers::build::{BuildJob, BuildStatus},
+    workers::{
+        build::{BuildFailure, BuildJob, BuildStatus},
+        deploy::{DeployJob, DeploymentStatus, ReleaseStatus},
+        pool::WorkerStatus,
+    },
 };
 
-// TODO: move this into AgentConfig
-const PORT: u16 = 7080;
-
-pub async fn start_api(state: ApiState) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn start_api(port: u16, state: ApiState) -> Result<(), Box<dyn std::error::Error>> {
     // Define routes
     let app = Router::new()
+        .route("/apps", get(list_apps).post(create_app))
+        .route("/apps/:name", get(get_app).delete(delete_app))
+        .route("/apps/:name/builds", get(list_app_builds))
+        .route("/apps/:name/env", get(list_env).patch(update_env))
+        .route("/apps/:name/logs", get(get_app_logs))
+        .route("/apps/:name/releases", get(list_releases))
+        .route("/apps/:name/rollback", post(rollback_app))
         .route("/builds", get(list_builds).post(create_build))
         .route("/builds/:id", get(get_build))
+        .route("/builds/:id/cancel", post(cancel_build))
+        .route("/builds/:id/logs", get(get_build_logs))
+        .route("/builds/:id/rebuild", post(rebuild_build))
+        .route("/deployments", get(list_deployments))
+        .route("/workers", get(list_workers))
         .with_state(state);
 
-    let addr = format!("0.0.0.0:{PORT}");
+    let addr = format!("0.0.0.0:{port}");
     let listener = tokio::net::TcpListener::bind(addr).await?;
 
-    println!("nimbled listening on port {PORT}");
+    println!("nimbled listening on port {port}");
     axum::serve(listener, app).await?;
     Ok(())
 }
@@ -45,16 +66,45 @@ struct ListBuildsQuery {
 #[derive(Serialize)]
 struct BuildResponse {
     id: String,
+    app: Option<String>,
     status: BuildStatus,
+    rebuild_of: Option<String>,
+    no_cache: bool,
+    builder: Option<BuilderType>,
+    builder_reason: Option<String>,
+    image: Option<BuildImageResponse>,
+    duration_ms: Option<u64>,
+    failure_reason: Option<BuildFailure>,
     created_at: String,
     updated_at: String,
 }
 
+/// The image a build produced.
+#[derive(Serialize)]
+struct BuildImageResponse {
+    reference: String,
+    digest: Option<String>,
+    /// Size in bytes, if the container runtime reported it
+    size: Option<u64>,
+}
+
 impl From<db::BuildRecord> for BuildResponse {
     fn from(record: db::BuildRecord) -> Self {
         BuildResponse {
             id: record.id.to_string(),
+            app: record.app,
             status: record.status,
+            rebuild_of: record.rebuild_of.map(|id| id.to_string()),
+            no_cache: record.no_cache,
+            builder: record.builder,
+            builder_reason: record.builder_reason,
+            image: record.image.map(|image| BuildImageResponse {
+                reference: image.reference,
+                digest: image.digest,
+                size: record.image_size,
+            }),
+            duration_ms: record.duration.map(|duration| duration.as_millis() as u64),
+            failure_reason: record.failure_reason,
             created_at: record.created_at,
             updated_at: record.updated_at,
         }
@@ -77,7 +127,7 @@ async fn list_builds(
 
     let builds = state
         .db
-        .list_builds(params.limit, status_filter)
+        .list_builds(params.limit, status_filter, None)
         .await
         .map_err(ApiError::Internal)?;
 
@@ -85,18 +135,37 @@ async fn list_builds(
     Ok(Json(responses))
 }
 
+#[derive(Deserialize)]
+struct CreateBuildQuery {
+    app: Option<String>,
+}
+
 #[derive(Serialize)]
 struct CreateBuildResponse {
     build_id: String,
+    app: String,
     status: BuildStatus,
 }
 
 async fn create_build(
     State(state): State<ApiState>,
+    Query(params): Query<CreateBuildQuery>,
     body: Bytes,
 ) -> Result<Json<CreateBuildResponse>, ApiError> {
     // TODO: check Content-Type header
 
+    let app = params
+        .app
+        .ok_or_else(|| ApiError::BadRequest("missing required query parameter: app".into()))?;
+    validate_app_name(&app).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    // Apps are created on their first deploy
+    state
+        .db
+        .create_app(&app)
+        .await
+        .map_err(ApiError::Internal)?;
+
     let build_id = Uuid::new_v4();
 
     // Body contains zipped source code - need to save this to disk
@@ -107,27 +176,95 @@ async fn create_build(
         .await
         .map_err(ApiError::Internal)?;
 
-    // Add build to queue
-    let job = BuildJob { build_id };
-    state.build_queue.try_send(job).map_err(|e| match e {
-        TrySendError::Full(_) => {
-            ApiError::ServiceUnavailable("build queue is full, please try again later".to_string())
-        }
-        TrySendError::Closed(_) => ApiError::Internal(anyhow::anyhow!("build queue is closed")),
-    })?;
+    queue_build(&state, build_id, &app, None, false).await?;
 
-    // Record build in database as queued
+    let resp = CreateBuildResponse {
+        build_id: build_id.to_string(),
+        app,
+        status: BuildStatus::Queued,
+    };
+    Ok(Json(resp))
+}
+
+/// Records a new build and adds it to the build queue.
+async fn queue_build(
+    state: &ApiState,
+    build_id: Uuid,
+    app: &str,
+    rebuild_of: Option<Uuid>,
+    no_cache: bool,
+) -> Result<(), ApiError> {
+    // Record build in database as queued. This has to happen before it is
+    // queued, since the worker only picks up builds that are still queued.
     state
         .db
-        .create_build(build_id, BuildStatus::Queued)
+        .create_build(build_id, app, BuildStatus::Queued, rebuild_of, no_cache)
         .await
         .map_err(ApiError::Internal)?;
 
-    let resp = CreateBuildResponse {
+    // Add build to queue
+    let job = BuildJob {
+        build_id,
+        app: app.to_string(),
+        no_cache,
+    };
+    if let Err(e) = state.build_queue.try_send(job) {
+        let _ = state
+            .db
+            .update_build_status(build_id, BuildStatus::Failed)
+            .await;
+        return Err(match e {
+            TrySendError::Full(_) => ApiError::ServiceUnavailable(
+                "build queue is full, please try again later".to_string(),
+            ),
+            TrySendError::Closed(_) => ApiError::Internal(anyhow::anyhow!("build queue is closed")),
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct RebuildQuery {
+    #[serde(default)]
+    no_cache: bool,
+}
+
+async fn rebuild_build(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Query(params): Query<RebuildQuery>,
+) -> Result<Json<CreateBuildResponse>, ApiError> {
+    let source_id = Uuid::parse_str(&id)
+        .map_err(|_| ApiError::BadRequest(format!("Invalid build ID: {id}")))?;
+
+    let source = state
+        .db
+        .get_build(source_id)
+        .await
+        .map_err(ApiError::Internal)?
+        .ok_or(ApiError::NotFound)?;
+
+    let app = source.app.ok_or_else(|| {
+        ApiError::BadRequest(format!("Build {source_id} doesn't belong to an app"))
+    })?;
+
+    // Each build gets its own copy of the source, so that the original can
+    // be deleted independently
+    let build_id = Uuid::new_v4();
+    state.copy_archive(source_id, build_id).await.map_err(|_| {
+        ApiError::Conflict(format!(
+            "Source archive of build {source_id} no longer exists"
+        ))
+    })?;
+
+    queue_build(&state, build_id, &app, Some(source_id), params.no_cache).await?;
+
+    Ok(Json(CreateBuildResponse {
         build_id: build_id.to_string(),
+        app,
         status: BuildStatus::Queued,
-    };
-    Ok(Json(resp))
+    }))
 }
 
 async fn get_build(
@@ -149,6 +286,963 @@ async fn get_build(
     }
 }
 
+async fn cancel_build(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<BuildResponse>), ApiError> {
+    let build_id = Uuid::parse_str(&id)
+        .map_err(|_| ApiError::BadRequest(format!("Invalid build ID: {id}")))?;
+
+    let build = state
+        .db
+        .get_build(build_id)
+        .await
+        .map_err(ApiError::Internal)?
+        .ok_or(ApiError::NotFound)?;
+    let finished = |status: BuildStatus| {
+        ApiError::Conflict(format!("Build {build_id} has already finished ({status})"))
+    };
+    if build.status.is_finished() {
+        return Err(finished(build.status));
+    }
+
+    // A queued build is skipped by the worker once it sees the new status
+    let cancelled = state
+        .db
+        .transition_build_status(build_id, BuildStatus::Queued, BuildStatus::Cancelled)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let status = if cancelled {
+        StatusCode::OK
+    } else if state.build_pool.cancel(build_id) {
+        // The worker stops the build and marks it cancelled
+        StatusCode::ACCEPTED
+    } else if state
+        .db
+        .transition_build_status(build_id, BuildStatus::Building, BuildStatus::Cancelled)
+        .await
+        .map_err(ApiError::Internal)?
+    {
+        // Not actually running, e.g. the agent restarted mid-build
+        StatusCode::OK
+    } else {
+        // It finished since it was looked up
+        let build = state
+            .db
+            .get_build(build_id)
+            .await
+            .map_err(ApiError::Internal)?
+            .ok_or(ApiError::NotFound)?;
+        return Err(finished(build.status));
+    };
+
+    let build = state
+        .db
+        .get_build(build_id)
+        .await
+        .map_err(ApiError::Internal)?
+        .ok_or(ApiError::NotFound)?;
+
+    Ok((status, Json(BuildResponse::from(build))))
+}
+
+#[derive(Deserialize)]
+struct BuildLogsQuery {
+    #[serde(default)]
+    follow: bool,
+}
+
+/// How often a followed build log is checked for new output.
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+async fn get_build_logs(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Query(query): Query<BuildLogsQuery>,
+) -> Result<Response, ApiError> {
+    let build_id = Uuid::parse_str(&id)
+        .map_err(|_| ApiError::BadRequest(format!("Invalid build ID: {id}")))?;
+
+    let build = state
+        .db
+        .get_build(build_id)
+        .await
+        .map_err(ApiError::Internal)?
+        .ok_or(ApiError::NotFound)?;
+
+    let path = state.build_log_path(build_id);
+    let file = match File::open(&path).await {
+        Ok(file) => Some(file),
+        // Queued builds don't have a log yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(ApiError::Internal(e.into())),
+    };
+
+    let tail = LogTail {
+        db: state.db.clone(),
+        build_id,
+        path,
+        file,
+        finished: !query.follow || build.status.is_finished(),
+    };
+    let stream = futures_util::stream::unfold(tail, |mut tail| async move {
+        let chunk = tail.next_chunk().await?;
+        Some((Ok::<_, std::io::Error>(chunk), tail))
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Reads a build log, optionally waiting for more output until the build
+/// has finished.
+struct LogTail {
+    db: db::Database,
+    build_id: Uuid,
+    path: PathBuf,
+    file: Option<File>,
+    /// Set once no more output is expected; the rest of the file is still
+    /// read before the stream ends.
+    finished: bool,
+}
+
+impl LogTail {
+    async fn next_chunk(&mut self) -> Option<Bytes> {
+        let mut buf = vec![0; 8192];
+
+        loop {
+            if let Some(file) = &mut self.file {
+                match file.read(&mut buf).await {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        buf.truncate(n);
+                        return Some(Bytes::from(buf));
+                    }
+                    Err(_) => return None,
+                }
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            tokio::time::sleep(LOG_POLL_INTERVAL).await;
+
+            if self.file.is_none() {
+                self.file = File::open(&self.path).await.ok();
+            }
+
+            // The worker writes its last line before updating the status,
+            // so one more read after this picks up everything
+            self.finished = match self.db.get_build(self.build_id).await {
+                Ok(Some(build)) => build.status.is_finished(),
+                _ => true,
+            };
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct WorkerResponse {
+    id: usize,
+    /// `idle` or `building`
+    status: &'static str,
+    build_id: Option<String>,
+    app: Option<String>,
+    restarts: u32,
+}
+
+impl From<WorkerStatus> for WorkerResponse {
+    fn from(worker: WorkerStatus) -> Self {
+        Self {
+            id: worker.id,
+            status: if worker.build.is_some() {
+                "building"
+            } else {
+                "idle"
+            },
+            build_id: worker.build.as_ref().map(|b| b.build_id.to_string()),
+            app: worker.build.map(|b| b.app),
+            restarts: worker.restarts,
+        }
+    }
+}
+
+async fn list_workers(State(state): State<ApiState>) -> Json<Vec<WorkerResponse>> {
+    Json(
+        state
+            .build_pool
+            .workers()
+            .into_iter()
+            .map(WorkerResponse::from)
+            .collect(),
+    )
+}
+
+#[derive(Deserialize)]
+struct ListDeploymentsQuery {
+    status: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DeploymentResponse {
+    id: String,
+    app: Option<String>,
+    release: Option<i64>,
+    build_id: String,
+    image: String,
+    container_id: Option<String>,
+    ports: Vec<PublishedPort>,
+    status: DeploymentStatus,
+    created_at: String,
+    updated_at: String,
+}
+
+impl From<db::DeploymentRecord> for DeploymentResponse {
+    fn from(record: db::DeploymentRecord) -> Self {
+        DeploymentResponse {
+            id: record.id.to_string(),
+            app: record.app,
+            release: record.release_version,
+            build_id: record.build_id.to_string(),
+            image: record.image,
+            container_id: record.container_id,
+            ports: record.ports,
+            status: record.status,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+async fn list_deployments(
+    State(state): State<ApiState>,
+    Query(params): Query<ListDeploymentsQuery>,
+) -> Result<Json<Vec<DeploymentResponse>>, ApiError> {
+    // Parse status filter if provided
+    let status_filter = if let Some(status_str) = params.status {
+        Some(
+            DeploymentStatus::from_str(&status_str)
+                .map_err(|e| ApiError::BadRequest(format!("Invalid status: {e}")))?,
+        )
+    } else {
+        None
+    };
+
+    let deployments = state
+        .db
+        .list_deployments(status_filter, None)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let responses: Vec<DeploymentResponse> = deployments
+        .into_iter()
+        .map(DeploymentResponse::from)
+        .collect();
+    Ok(Json(responses))
+}
+
+#[derive(Serialize)]
+struct AppResponse {
+    name: String,
+    created_at: String,
+    updated_at: String,
+}
+
+impl From<db::AppRecord> for AppResponse {
+    fn from(record: db::AppRecord) -> Self {
+        AppResponse {
+            name: record.name,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AppInfoResponse {
+    #[serde(flatten)]
+    app: AppResponse,
+    /// The app's currently running deployment, if any
+    deployment: Option<DeploymentResponse>,
+}
+
+#[derive(Deserialize)]
+struct CreateAppRequest {
+    name: String,
+}
+
+async fn list_apps(State(state): State<ApiState>) -> Result<Json<Vec<AppResponse>>, ApiError> {
+    let apps = state.db.list_apps().await.map_err(ApiError::Internal)?;
+
+    let responses: Vec<AppResponse> = apps.into_iter().map(AppResponse::from).collect();
+    Ok(Json(responses))
+}
+
+async fn create_app(
+    State(state): State<ApiState>,
+    Json(req): Json<CreateAppRequest>,
+) -> Result<Json<AppResponse>, ApiError> {
+    validate_app_name(&req.name).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let created = state
+        .db
+        .create_app(&req.name)
+        .await
+        .map_err(ApiError::Internal)?;
+    if !created {
+        return Err(ApiError::Conflict(format!(
+            "app already exists: {}",
+            req.name
+        )));
+    }
+
+    let app = state
+        .db
+        .get_app(&req.name)
+        .await
+        .map_err(ApiError::Internal)?
+        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("app missing after insert")))?;
+
+    Ok(Json(AppResponse::from(app)))
+}
+
+async fn get_app(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+) -> Result<Json<AppInfoResponse>, ApiError> {
+    let app = state
+        .db
+        .get_app(&name)
+        .await
+        .map_err(ApiError::Internal)?
+        .ok_or(ApiError::NotFound)?;
+
+    let deployment = state
+        .db
+        .list_deployments(Some(DeploymentStatus::Running), Some(&name))
+        .await
+        .map_err(ApiError::Internal)?
+        .into_iter()
+        .next()
+        .map(DeploymentResponse::from);
+
+    Ok(Json(AppInfoResponse {
+        app: AppResponse::from(app),
+        deployment,
+    }))
+}
+
+#[derive(Deserialize)]
+struct AppLogsQuery {
+    /// Only show lines newer than this, e.g. `10m`
+    since: Option<String>,
+    /// Only show this many of the most recent lines
+    tail: Option<u64>,
+    #[serde(default)]
+    follow: bool,
+}
+
+async fn get_app_logs(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    Query(query): Query<AppLogsQuery>,
+) -> Result<Response, ApiError> {
+    if state
+        .db
+        .get_app(&name)
+        .await
+        .map_err(ApiError::Internal)?
+        .is_none()
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    let since = query
+        .since
+        .as_deref()
+        .map(parse_duration_str)
+        .transpose()
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let options = LogOptions {
+        since,
+        tail: query.tail,
+        follow: query.follow,
+    };
+
+    let running: Vec<_> = state
+        .db
+        .list_deployments(Some(DeploymentStatus::Running), Some(&name))
+        .await
+        .map_err(ApiError::Internal)?
+        .into_iter()
+        .filter(|d| d.container_id.is_some())
+        .collect();
+
+    if running.is_empty() {
+        return Err(ApiError::Conflict(format!("App {name} is not running")));
+    }
+
+    // Tag lines with their deployment when there is more than one replica
+    let tagged = running.len() > 1;
+    let mut sources = Vec::new();
+    for deployment in running {
+        let container_id = deployment.container_id.unwrap_or_default();
+        let lines = select_deployer(deployment.target, Arc::clone(&state.runtime))
+            .logs(&container_id, &options)
+            .await
+            .map_err(ApiError::Internal)?;
+        let tag = tagged.then(|| deployment.id.to_string()[..8].to_string());
+        sources.push((tag, lines));
+    }
+
+    let body = if query.follow {
+        Body::from_stream(merge_log_streams(sources))
+    } else {
+        Body::from(collect_logs(sources, query.tail).await)
+    };
+
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response())
+}
+
+type LogSource = (Option<String>, mpsc::Receiver<LogLine>);
+
+/// Formats a log line as `<timestamp> [<tag>] <message>`.
+fn format_log_line(tag: Option<&str>, line: &LogLine) -> String {
+    let mut out = String::new();
+    if let Some(timestamp) = &line.timestamp {
+        out.push_str(timestamp);
+        out.push(' ');
+    }
+    if let Some(tag) = tag {
+        out.push_str(&format!("[{tag}] "));
+    }
+    out.push_str(&line.message);
+    out.push('\n');
+    out
+}
+
+/// Reads every source to the end and returns their lines in time order,
+/// keeping only the last `tail` lines overall.
+async fn collect_logs(sources: Vec<LogSource>, tail: Option<u64>) -> String {
+    let mut lines = Vec::new();
+    for (tag, mut rx) in sources {
+        while let Some(line) = rx.recv().await {
+            lines.push((tag.clone(), line));
+        }
+    }
+
+    // Sorting is stable, so untimestamped lines stay next to their neighbours
+    lines.sort_by(|(_, a), (_, b)| match (&a.timestamp, &b.timestamp) {
+        (Some(a), Some(b)) => a.cmp(b),
+        _ => std::cmp::Ordering::Equal,
+    });
+
+    let skip = tail.map_or(0, |tail| lines.len().saturating_sub(tail as usize));
+    lines
+        .iter()
+        .skip(skip)
+        .map(|(tag, line)| format_log_line(tag.as_deref(), line))
+        .collect()
+}
+
+/// Interleaves lines from every source as they arrive. The stream ends once
+/// all sources have ended.
+fn merge_log_streams(
+    sources: Vec<LogSource>,
+) -> impl futures_util::Stream<Item = Result<Bytes, std::io::Error>> {
+    let (tx, rx) = mpsc::channel::<Bytes>(256);
+
+    for (tag, mut lines) in sources {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let line = tokio::select! {
+                    line = lines.recv() => line,
+                    // The client went away
+                    _ = tx.closed() => break,
+                };
+                let Some(line) = line else {
+                    break;
+                };
+                let formatted = format_log_line(tag.as_deref(), &line);
+                if tx.send(Bytes::from(formatted)).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    futures_util::stream::unfold(rx, |mut rx| async move {
+        let chunk = rx.recv().await?;
+        Some((Ok(chunk), rx))
+    })
+}
+
+async fn delete_app(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if state
+        .db
+        .get_app(&name)
+        .await
+        .map_err(ApiError::Internal)?
+        .is_none()
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    // Stop the app's builds first, so that none of them deploys it again
+    // once it's gone. Queued builds are marked cancelled before they're
+    // dropped from the pool, so that a worker that already took one skips it.
+    let builds = state
+        .db
+        .list_builds(None, None, Some(&name))
+        .await
+        .map_err(ApiError::Internal)?;
+    for build in &builds {
+        match build.status {
+            BuildStatus::Queued => {
+                state
+                    .db
+                    .transition_build_status(build.id, BuildStatus::Queued, BuildStatus::Cancelled)
+                    .await
+                    .map_err(ApiError::Internal)?;
+            }
+            BuildStatus::Building => {
+                state.build_pool.cancel(build.id);
+            }
+            _ => {}
+        }
+    }
+    let purged = state.build_pool.purge_app(&name);
+    if purged > 0 {
+        tracing::info!(app = %name, builds = purged, "Dropped queued builds of deleted app");
+    }
+
+    // Deleting the app's deployments hands their containers to us. A deploy
+    // that's still starting its container removes it itself once it finds
+    // its deployment gone.
+    let deployments = state
+        .db
+        .delete_app(&name)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    for deployment in deployments {
+        let live = matches!(
+            deployment.status,
+            DeploymentStatus::Running | DeploymentStatus::Deploying
+        );
+        let Some(container_id) = deployment.container_id.filter(|_| live) else {
+            continue;
+        };
+        // The container may already be gone, e.g. removed outside Nimble,
+        // which mustn't keep the app from being deleted
+        if let Err(e) = select_deployer(deployment.target, Arc::clone(&state.runtime))
+            .remove(&container_id)
+            .await
+        {
+            tracing::warn!(
+                app = %name,
+                container_id = %container_id,
+                error = %e,
+                "Failed to remove container of deleted app"
+            );
+        }
+    }
+
+    // Build files are only useful while the builds exist
+    for build in builds {
+        state.remove_archive(build.id).await;
+        state.remove_build_files(build.id).await;
+    }
+
+    if let Err(e) = state.routes.reload().await {
+        tracing::warn!(app = %name, error = %e, "Failed to reload proxy routes");
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_app_builds(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    Query(params): Query<ListBuildsQuery>,
+) -> Result<Json<Vec<BuildResponse>>, ApiError> {
+    // Parse status filter if provided
+    let status_filter = if let Some(status_str) = params.status {
+        Some(
+            BuildStatus::from_str(&status_str)
+                .map_err(|e| ApiError::BadRequest(format!("Invalid status: {e}")))?,
+        )
+    } else {
+        None
+    };
+
+    if state
+        .db
+        .get_app(&name)
+        .await
+        .map_err(ApiError::Internal)?
+        .is_none()
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    let builds = state
+        .db
+        .list_builds(params.limit, status_filter, Some(&name))
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let responses: Vec<BuildResponse> = builds.into_iter().map(BuildResponse::from).collect();
+    Ok(Json(responses))
+}
+
+#[derive(Serialize)]
+struct ReleaseResponse {
+    app: String,
+    version: i64,
+    build_id: String,
+    image: String,
+    config: DeployConfig,
+    /// Names of the env vars set on the release
+    env: Vec<String>,
+    description: String,
+    status: ReleaseStatus,
+    created_at: String,
+    updated_at: String,
+}
+
+impl From<db::ReleaseRecord> for ReleaseResponse {
+    fn from(record: db::ReleaseRecord) -> Self {
+        ReleaseResponse {
+            app: record.app,
+            version: record.version,
+            build_id: record.build_id.to_string(),
+            image: record.image,
+            config: record.config,
+            env: record.env.into_iter().map(|var| var.name).collect(),
+            description: record.description,
+            status: record.status,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+async fn list_releases(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<ReleaseResponse>>, ApiError> {
+    if state
+        .db
+        .get_app(&name)
+        .await
+        .map_err(ApiError::Internal)?
+        .is_none()
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    let releases = state
+        .db
+        .list_releases(&name, None)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let responses: Vec<ReleaseResponse> = releases.into_iter().map(ReleaseResponse::from).collect();
+    Ok(Json(responses))
+}
+
+#[derive(Deserialize, Default)]
+struct RollbackRequest {
+    /// Release version to roll back to; defaults to the release before the active one
+    to: Option<i64>,
+}
+
+async fn rollback_app(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    req: Option<Json<RollbackRequest>>,
+) -> Result<Json<ReleaseResponse>, ApiError> {
+    let req = req.map(|Json(req)| req).unwrap_or_default();
+
+    if state
+        .db
+        .get_app(&name)
+        .await
+        .map_err(ApiError::Internal)?
+        .is_none()
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    let releases = state
+        .db
+        .list_releases(&name, None)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let target = match req.to {
+        Some(version) => releases
+            .iter()
+            .find(|r| r.version == version)
+            .ok_or(ApiError::NotFound)?,
+        None => {
+            let active = releases
+                .iter()
+                .find(|r| r.status == ReleaseStatus::Active)
+                .ok_or_else(|| ApiError::BadRequest("app has no active release".into()))?;
+            releases
+                .iter()
+                .find(|r| r.version < active.version && r.status == ReleaseStatus::Superseded)
+                .ok_or_else(|| ApiError::BadRequest("no previous release to roll back to".into()))?
+        }
+    };
+
+    match target.status {
+        ReleaseStatus::Active => {
+            return Err(ApiError::BadRequest(format!(
+                "release v{} is already active",
+                target.version
+            )));
+        }
+        ReleaseStatus::Failed => {
+            return Err(ApiError::BadRequest(format!(
+                "release v{} failed to deploy",
+                target.version
+            )));
+        }
+        ReleaseStatus::Pending | ReleaseStatus::Superseded => {}
+    }
+
+    // A rollback is a new release that reuses the target release's image, config and env
+    let release = deploy_release(
+        &state,
+        target,
+        &target.env,
+        &format!("Rollback to v{}", target.version),
+    )
+    .await?;
+
+    Ok(Json(ReleaseResponse::from(release)))
+}
+
+/// Creates a new release of `base`'s image and config with the given env
+/// vars, and queues it for deployment.
+async fn deploy_release(
+    state: &ApiState,
+    base: &db::ReleaseRecord,
+    env: &[db::EnvVar],
+    description: &str,
+) -> Result<db::ReleaseRecord, ApiError> {
+    let app = &base.app;
+    let version = state
+        .db
+        .create_release(
+            app,
+            base.build_id,
+            &base.image,
+            &base.config,
+            env,
+            description,
+        )
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let job = DeployJob {
+        app: app.clone(),
+        version,
+    };
+    if let Err(e) = state.deploy_queue.try_send(job) {
+        let _ = state
+            .db
+            .update_release_status(app, version, ReleaseStatus::Failed)
+            .await;
+        return Err(match e {
+            TrySendError::Full(_) => ApiError::ServiceUnavailable(
+                "deploy queue is full, please try again later".to_string(),
+            ),
+            TrySendError::Closed(_) => {
+                ApiError::Internal(anyhow::anyhow!("deploy queue is closed"))
+            }
+        });
+    }
+
+    state
+        .db
+        .get_release(app, version)
+        .await
+        .map_err(ApiError::Internal)?
+        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("release missing after insert")))
+}
+
+#[derive(Serialize)]
+struct EnvVarResponse {
+    name: String,
+    /// The value, or `None` for secrets
+    value: Option<String>,
+    secret: bool,
+}
+
+impl From<db::EnvVar> for EnvVarResponse {
+    fn from(var: db::EnvVar) -> Self {
+        EnvVarResponse {
+            name: var.name,
+            value: (!var.secret).then_some(var.value),
+            secret: var.secret,
+        }
+    }
+}
+
+async fn list_env(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<EnvVarResponse>>, ApiError> {
+    if state
+        .db
+        .get_app(&name)
+        .await
+        .map_err(ApiError::Internal)?
+        .is_none()
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    let vars = state
+        .db
+        .list_app_env(&name)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let responses: Vec<EnvVarResponse> = vars.into_iter().map(EnvVarResponse::from).collect();
+    Ok(Json(responses))
+}
+
+#[derive(Deserialize)]
+struct SetEnvVar {
+    name: String,
+    value: String,
+    #[serde(default)]
+    secret: bool,
+}
+
+#[derive(Deserialize)]
+struct UpdateEnvRequest {
+    #[serde(default)]
+    set: Vec<SetEnvVar>,
+    #[serde(default)]
+    unset: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct UpdateEnvResponse {
+    /// The release rolling out the change, if the app is deployed
+    release: Option<ReleaseResponse>,
+}
+
+/// Checks that an env var name is a valid shell identifier.
+fn validate_env_name(name: &str) -> Result<(), ApiError> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::BadRequest(format!(
+            "Invalid env var name: {name}. Names must be letters, digits or underscores, not starting with a digit"
+        )))
+    }
+}
+
+async fn update_env(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    Json(req): Json<UpdateEnvRequest>,
+) -> Result<Json<UpdateEnvResponse>, ApiError> {
+    if state
+        .db
+        .get_app(&name)
+        .await
+        .map_err(ApiError::Internal)?
+        .is_none()
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    if req.set.is_empty() && req.unset.is_empty() {
+        return Err(ApiError::BadRequest("no env vars to set or unset".into()));
+    }
+
+    let mut set = Vec::with_capacity(req.set.len());
+    for var in req.set {
+        validate_env_name(&var.name)?;
+        let value = if var.secret {
+            state
+                .secrets
+                .encrypt(&var.value)
+                .map_err(ApiError::Internal)?
+        } else {
+            var.value
+        };
+        set.push(db::EnvVar {
+            name: var.name,
+            value,
+            secret: var.secret,
+        });
+    }
+    for var in &req.unset {
+        validate_env_name(var)?;
+    }
+
+    state
+        .db
+        .update_app_env(&name, &set, &req.unset)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    // Roll the change out by redeploying the active release with the new env
+    let active = state
+        .db
+        .list_releases(&name, Some(ReleaseStatus::Active))
+        .await
+        .map_err(ApiError::Internal)?
+        .into_iter()
+        .next();
+
+    let Some(active) = active else {
+        return Ok(Json(UpdateEnvResponse { release: None }));
+    };
+
+    let env = state
+        .db
+        .list_app_env(&name)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let mut changes = Vec::new();
+    if !set.is_empty() {
+        let names: Vec<&str> = set.iter().map(|v| v.name.as_str()).collect();
+        changes.push(format!("Set {}", names.join(", ")));
+    }
+    if !req.unset.is_empty() {
+        changes.push(format!("Unset {}", req.unset.join(", ")));
+    }
+
+    let release = deploy_release(&state, &active, &env, &changes.join("; ")).await?;
+
+    Ok(Json(UpdateEnvResponse {
+        release: Some(ReleaseResponse::from(release)),
+    }))
+}
+
 // Errors
 
 // ApiError represents errors returned by the API.
@@ -156,6 +1250,7 @@ async fn get_build(
 pub enum ApiError {
     NotFound,
     BadRequest(String),
+    Conflict(String),
     Internal(anyhow::Error),
     ServiceUnavailable(String),
 }
@@ -180,6 +1275,10 @@ impl IntoResponse for ApiError {
                 (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: msg })).into_response()
             }
 
+            ApiError::Conflict(msg) => {
+                (StatusCode::CONFLICT, Json(ErrorResponse { error: msg })).into_response()
+            }
+
             ApiError::Internal(err) => {
                 tracing::error!(?err, "internal error");
                 (
@@ -199,3 +1298,291 @@ impl IntoResponse for ApiError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nimble_core::runtime::ContainerRuntime;
+
+    use super::*;
+    use crate::{db::DeploymentRecord, testing::TestEnv};
+
+    async fn rollback(state: &ApiState, to: Option<i64>) -> Result<ReleaseResponse, ApiError> {
+        let req = to.map(|to| Json(RollbackRequest { to: Some(to) }));
+        rollback_app(State(state.clone()), Path("web".to_string()), req)
+            .await
+            .map(|Json(release)| release)
+    }
+
+    async fn cancel(
+        state: &ApiState,
+        build_id: Uuid,
+    ) -> Result<(StatusCode, BuildStatus), ApiError> {
+        cancel_build(State(state.clone()), Path(build_id.to_string()))
+            .await
+            .map(|(status, Json(build))| (status, build.status))
+    }
+
+    async fn build_status(env: &TestEnv, build_id: Uuid) -> BuildStatus {
+        env.db.get_build(build_id).await.unwrap().unwrap().status
+    }
+
+    async fn release_status(env: &TestEnv, version: i64) -> ReleaseStatus {
+        env.db
+            .get_release("web", version)
+            .await
+            .unwrap()
+            .unwrap()
+            .status
+    }
+
+    #[tokio::test]
+    async fn rollback_redeploys_previous_release() {
+        let env = TestEnv::new().await;
+        let v1 = env.release("web", "target: local:docker").await;
+        let v2 = env.release("web", "target: local:docker").await;
+        env.deploy("web", &[v1, v2]).await;
+        let (state, mut deploy_rx) = env.api_state().await;
+
+        let release = rollback(&state, None).await.unwrap();
+        assert_eq!(release.version, 3);
+        assert_eq!(release.description, "Rollback to v1");
+        env.deploy_queued(&mut deploy_rx).await;
+
+        let v1_image = env.db.get_release("web", v1).await.unwrap().unwrap().image;
+        assert_eq!(release.image, v1_image);
+        assert_eq!(release_status(&env, v2).await, ReleaseStatus::Superseded);
+        assert_eq!(release_status(&env, 3).await, ReleaseStatus::Active);
+
+        let deployments = env
+            .db
+            .list_deployments(Some(DeploymentStatus::Running), Some("web"))
+            .await
+            .unwrap();
+        assert_eq!(deployments.len(), 1);
+        let container = env
+            .runtime
+            .container(deployments[0].container_id.as_deref().unwrap())
+            .unwrap();
+        assert_eq!(container.image, v1_image);
+    }
+
+    #[tokio::test]
+    async fn rollback_to_a_given_version() {
+        let env = TestEnv::new().await;
+        let versions = [
+            env.release("web", "target: local:docker").await,
+            env.release("web", "target: local:docker").await,
+            env.release("web", "target: local:docker").await,
+        ];
+        env.deploy("web", &versions).await;
+        let (state, mut deploy_rx) = env.api_state().await;
+
+        let release = rollback(&state, Some(versions[0])).await.unwrap();
+        assert_eq!(release.description, "Rollback to v1");
+        env.deploy_queued(&mut deploy_rx).await;
+        assert_eq!(
+            release_status(&env, release.version).await,
+            ReleaseStatus::Active
+        );
+    }
+
+    #[tokio::test]
+    async fn rollback_rejects_active_and_failed_releases() {
+        let env = TestEnv::new().await;
+        let v1 = env.release("web", "target: local:docker").await;
+        env.deploy("web", &[v1]).await;
+        let failed = env.release("web", "target: local:docker").await;
+        env.db
+            .update_release_status("web", failed, ReleaseStatus::Failed)
+            .await
+            .unwrap();
+        let (state, _deploy_rx) = env.api_state().await;
+
+        // There's nothing before v1 to go back to
+        assert!(matches!(
+            rollback(&state, None).await,
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            rollback(&state, Some(v1)).await,
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            rollback(&state, Some(failed)).await,
+            Err(ApiError::BadRequest(_))
+        ));
+        assert_eq!(release_status(&env, v1).await, ReleaseStatus::Active);
+    }
+
+    #[tokio::test]
+    async fn rollback_to_missing_release_is_not_found() {
+        let env = TestEnv::new().await;
+        let v1 = env.release("web", "target: local:docker").await;
+        env.deploy("web", &[v1]).await;
+        let (state, _deploy_rx) = env.api_state().await;
+
+        assert!(matches!(
+            rollback(&state, Some(v1 + 1)).await,
+            Err(ApiError::NotFound)
+        ));
+    }
+
+    const DOCKERFILE: (&str, &str) = ("Dockerfile", "FROM scratch\n");
+
+    #[tokio::test]
+    async fn cancel_queued_build() {
+        let env = TestEnv::new().await;
+        let job = env.queue_build("web", &[DOCKERFILE]).await;
+        let (state, _deploy_rx) = env.api_state().await;
+
+        let cancelled = cancel(&state, job.build_id).await.unwrap();
+
+        assert_eq!(cancelled, (StatusCode::OK, BuildStatus::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn cancel_running_build() {
+        let env = TestEnv::new().await;
+        env.runtime.set_build_time(Duration::from_secs(60));
+        let job = env.queue_build("web", &[DOCKERFILE]).await;
+        let build_id = job.build_id;
+        let (state, _deploy_rx) = env.api_state().await;
+        state.build_queue.send(job).await.unwrap();
+        while build_status(&env, build_id).await != BuildStatus::Building {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let (status, _) = cancel(&state, build_id).await.unwrap();
+
+        assert_eq!(status, StatusCode::ACCEPTED);
+        while build_status(&env, build_id).await != BuildStatus::Cancelled {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_build_left_building_by_a_restart() {
+        let env = TestEnv::new().await;
+        let job = env.queue_build("web", &[DOCKERFILE]).await;
+        env.db
+            .update_build_status(job.build_id, BuildStatus::Building)
+            .await
+            .unwrap();
+        let (state, _deploy_rx) = env.api_state().await;
+
+        let cancelled = cancel(&state, job.build_id).await.unwrap();
+
+        assert_eq!(cancelled, (StatusCode::OK, BuildStatus::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn cancel_finished_build_is_a_conflict() {
+        let env = TestEnv::new().await;
+        let (state, _deploy_rx) = env.api_state().await;
+
+        for finished in [
+            BuildStatus::Success,
+            BuildStatus::Failed,
+            BuildStatus::Cancelled,
+            BuildStatus::Interrupted,
+        ] {
+            let job = env.queue_build("web", &[DOCKERFILE]).await;
+            env.db
+                .update_build_status(job.build_id, finished)
+                .await
+                .unwrap();
+
+            assert!(
+                matches!(
+                    cancel(&state, job.build_id).await,
+                    Err(ApiError::Conflict(_))
+                ),
+                "{finished}"
+            );
+            assert_eq!(build_status(&env, job.build_id).await, finished);
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_app_stops_its_builds() {
+        let env = TestEnv::new().await;
+        env.runtime.set_build_time(Duration::from_secs(60));
+        let running = env.queue_build("web", &[DOCKERFILE]).await;
+        let queued = env.queue_build("web", &[DOCKERFILE]).await;
+        let ids = [running.build_id, queued.build_id];
+        let (state, mut deploy_rx) = env.api_state().await;
+        state.build_queue.send(running).await.unwrap();
+        // Waits behind the running build of the same app
+        state.build_queue.send(queued).await.unwrap();
+        while build_status(&env, ids[0]).await != BuildStatus::Building {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let status = delete_app(State(state.clone()), Path("web".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        while state
+            .build_pool
+            .workers()
+            .iter()
+            .any(|worker| worker.build.is_some())
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(state.build_pool.purge_app("web"), 0);
+        for build_id in ids {
+            assert!(env.db.get_build(build_id).await.unwrap().is_none());
+            let image = format!("nimble-build-{build_id}:latest");
+            assert_eq!(env.runtime.image(&image), None);
+            let paths = env.config.paths();
+            assert!(!paths.source_archive(build_id).exists());
+            assert!(!paths.build_log(build_id).parent().unwrap().exists());
+        }
+        assert!(env.db.get_app("web").await.unwrap().is_none());
+        assert!(deploy_rx.try_recv().is_err());
+    }
+
+    async fn running_deployment(env: &TestEnv, app: &str) -> DeploymentRecord {
+        env.db
+            .list_deployments(Some(DeploymentStatus::Running), Some(app))
+            .await
+            .unwrap()
+            .remove(0)
+    }
+
+    #[tokio::test]
+    async fn delete_app_removes_deploying_containers_and_tolerates_missing_ones() {
+        let env = TestEnv::new().await;
+        let web = env.release("web", "target: local:docker").await;
+        env.deploy("web", &[web]).await;
+        let api = env.release("api", "target: local:docker").await;
+        env.deploy("api", &[api]).await;
+        // Caught while its deploy is still starting the container
+        let deploying = running_deployment(&env, "web").await;
+        env.db
+            .update_deployment_status(deploying.id, DeploymentStatus::Deploying)
+            .await
+            .unwrap();
+        // Removed behind the agent's back
+        let gone = running_deployment(&env, "api").await;
+        let gone_container = gone.container_id.as_deref().unwrap();
+        env.runtime.stop(gone_container).await.unwrap();
+        env.runtime.remove(gone_container).await.unwrap();
+        let (state, _deploy_rx) = env.api_state().await;
+
+        for app in ["web", "api"] {
+            let status = delete_app(State(state.clone()), Path(app.to_string()))
+                .await
+                .unwrap();
+            assert_eq!(status, StatusCode::NO_CONTENT);
+            assert!(env.db.get_app(app).await.unwrap().is_none());
+        }
+        assert!(
+            env.runtime
+                .container(deploying.container_id.as_deref().unwrap())
+                .is_none()
+        );
+    }
+}
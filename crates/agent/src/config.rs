@@ -1,5 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use nimble_core::{
+    config::registry::{parse_registry_url, validate_repository},
+    runtime::RuntimeKind,
+};
 use uuid::Uuid;
 
 /// RunMode tells the agent whether it is running in a development or production environment.
@@ -16,34 +20,160 @@ impl RunMode {
     /// `"1"`, `"true"`, `"yes"`, `"on"` (case-insensitive).
     /// Any other value or if the variable is unset defaults to production mode.
     pub fn from_env() -> Self {
-        match std::env::var("NIMBLE_DEV_MODE") {
-            Ok(val) => {
-                let val = val.to_lowercase();
-                match val.as_str() {
-                    "1" | "true" | "yes" | "on" => RunMode::Dev,
-                    _ => RunMode::Prod,
-                }
-            }
-            Err(_) => RunMode::Prod,
+        if flag_from_env("NIMBLE_DEV_MODE") {
+            RunMode::Dev
+        } else {
+            RunMode::Prod
         }
     }
 }
 
+/// Default port of the management API.
+const DEFAULT_API_PORT: u16 = 7080;
+
+/// Default number of builds that run at the same time.
+const DEFAULT_BUILD_WORKERS: usize = 2;
+
+/// Default domain that apps are served under by the proxy.
+const DEFAULT_PROXY_DOMAIN: &str = "localhost";
+
 /// AgentConfig holds the config for the agent.
 pub struct AgentConfig {
     // run_mode determines if the agent is running in dev or prod mode.
     run_mode: RunMode,
     // data_dir determines where the agent stores its data.
     data_dir: Option<PathBuf>,
+    // api_port is the port the management API listens on.
+    api_port: Option<u16>,
+    // proxy_port is the port the app proxy listens on.
+    proxy_port: Option<u16>,
+    // proxy_domain is the domain that apps are served under, as `<app>.<domain>`.
+    proxy_domain: Option<String>,
+    // build_workers is the number of builds that can run at the same time.
+    build_workers: Option<usize>,
+    // retry_interrupted_builds makes the agent rebuild builds that were
+    // running when it last stopped, instead of marking them interrupted.
+    retry_interrupted_builds: bool,
+    // plugin_path is the list of directories searched for builder plugins.
+    plugin_path: Option<Vec<PathBuf>>,
+    // container_runtime is the container engine that builds and runs images.
+    container_runtime: Option<RuntimeKind>,
+    // registry_url is the registry built images are pushed to.
+    registry_url: Option<String>,
+    // registry_repository is the namespace apps' repositories are created
+    // under in the registry.
+    registry_repository: Option<String>,
+    // registry_credentials is the file holding the registry credentials.
+    registry_credentials: Option<PathBuf>,
 }
 
 impl AgentConfig {
-    pub fn new() -> Self {
+    /// Reads the config from the environment. Fails if a setting that the
+    /// agent can't sensibly run without is invalid.
+    pub fn new() -> anyhow::Result<Self> {
         let data_dir = std::env::var("NIMBLE_DATA_DIR").ok().map(PathBuf::from);
+        let api_port = port_from_env("NIMBLE_API_PORT");
+        let proxy_port = port_from_env("NIMBLE_PROXY_PORT");
+        let proxy_domain = std::env::var("NIMBLE_PROXY_DOMAIN")
+            .ok()
+            .map(|domain| domain.trim_matches('.').to_lowercase())
+            .filter(|domain| !domain.is_empty());
+        let plugin_path = std::env::var_os("NIMBLE_PLUGIN_PATH")
+            .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+            .filter(|dirs| !dirs.is_empty());
+        let registry_credentials = std::env::var_os("NIMBLE_REGISTRY_CREDENTIALS")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
 
-        Self {
+        Ok(Self {
             run_mode: RunMode::from_env(),
             data_dir,
+            api_port,
+            proxy_port,
+            proxy_domain,
+            build_workers: build_workers_from_env(),
+            retry_interrupted_builds: flag_from_env("NIMBLE_RETRY_INTERRUPTED_BUILDS"),
+            plugin_path,
+            container_runtime: container_runtime_from_env()?,
+            registry_url: registry_url_from_env()?,
+            registry_repository: registry_repository_from_env(),
+            registry_credentials,
+        })
+    }
+
+    /// Returns the port the management API listens on (`NIMBLE_API_PORT`, default 7080).
+    pub fn api_port(&self) -> u16 {
+        self.api_port.unwrap_or(DEFAULT_API_PORT)
+    }
+
+    /// Returns the port the app proxy listens on.
+    ///
+    /// Resolution order:
+    /// 1. `NIMBLE_PROXY_PORT`, if set.
+    /// 2. Otherwise, defaults based on `run_mode`:
+    ///    - Dev: `7081`
+    ///    - Prod: `80`
+    pub fn proxy_port(&self) -> u16 {
+        self.proxy_port.unwrap_or(match self.run_mode {
+            RunMode::Dev => 7081,
+            RunMode::Prod => 80,
+        })
+    }
+
+    /// Returns the domain that apps are served under (`NIMBLE_PROXY_DOMAIN`,
+    /// default `localhost`).
+    pub fn proxy_domain(&self) -> &str {
+        self.proxy_domain.as_deref().unwrap_or(DEFAULT_PROXY_DOMAIN)
+    }
+
+    /// Returns the number of builds that can run at the same time
+    /// (`NIMBLE_BUILD_WORKERS`, default 2).
+    pub fn build_workers(&self) -> usize {
+        self.build_workers.unwrap_or(DEFAULT_BUILD_WORKERS)
+    }
+
+    /// Returns whether builds interrupted by an agent restart are retried
+    /// (`NIMBLE_RETRY_INTERRUPTED_BUILDS`, default false).
+    pub fn retry_interrupted_builds(&self) -> bool {
+        self.retry_interrupted_builds
+    }
+
+    /// Returns the container engine that builds and runs images
+    /// (`NIMBLE_CONTAINER_RUNTIME`, default `docker`).
+    pub fn container_runtime(&self) -> RuntimeKind {
+        self.container_runtime.unwrap_or_default()
+    }
+
+    /// Returns the registry built images are pushed to (`NIMBLE_REGISTRY_URL`),
+    /// unless nimble.yaml names another. Images aren't pushed if neither
+    /// does.
+    pub fn registry_url(&self) -> Option<&str> {
+        self.registry_url.as_deref()
+    }
+
+    /// Returns the namespace apps' images are pushed under in the agent's
+    /// registry (`NIMBLE_REGISTRY_REPOSITORY`), as `<namespace>/<app>`.
+    /// Without one, the repository is just the app name.
+    pub fn registry_repository(&self) -> Option<&str> {
+        self.registry_repository.as_deref()
+    }
+
+    /// Returns the file holding the credentials for the agent's registry, as
+    /// `<username>:<password>` (`NIMBLE_REGISTRY_CREDENTIALS`). It's read
+    /// for each push, so it can be rotated without restarting the agent.
+    pub fn registry_credentials(&self) -> Option<&Path> {
+        self.registry_credentials.as_deref()
+    }
+
+    /// Returns the directories searched for builder plugins, in order.
+    ///
+    /// Resolution order:
+    /// 1. `NIMBLE_PLUGIN_PATH`, a list of directories separated like `PATH`.
+    /// 2. Otherwise, the `plugins` directory in the data directory.
+    pub fn plugin_path(&self) -> Vec<PathBuf> {
+        match &self.plugin_path {
+            Some(dirs) => dirs.clone(),
+            None => vec![self.paths().plugins()],
         }
     }
 
@@ -73,6 +203,122 @@ impl AgentConfig {
     }
 }
 
+#[cfg(test)]
+impl AgentConfig {
+    /// Returns a config that keeps its data in `data_dir`, leaving everything
+    /// else at its default whatever the environment says.
+    pub fn with_data_dir(data_dir: &Path) -> Self {
+        Self {
+            run_mode: RunMode::Dev,
+            data_dir: Some(data_dir.to_path_buf()),
+            api_port: None,
+            proxy_port: None,
+            proxy_domain: None,
+            build_workers: None,
+            retry_interrupted_builds: false,
+            plugin_path: None,
+            container_runtime: Some(RuntimeKind::Fake),
+            registry_url: None,
+            registry_repository: None,
+            registry_credentials: None,
+        }
+    }
+
+    pub fn with_retry_interrupted_builds(mut self) -> Self {
+        self.retry_interrupted_builds = true;
+        self
+    }
+
+    pub fn with_registry(
+        mut self,
+        url: &str,
+        repository: Option<&str>,
+        credentials: Option<&Path>,
+    ) -> Self {
+        self.registry_url = Some(url.to_string());
+        self.registry_repository = repository.map(str::to_string);
+        self.registry_credentials = credentials.map(Path::to_path_buf);
+        self
+    }
+}
+
+/// Reads a boolean flag from an environment variable.
+///
+/// The flag is set if the variable is a "truthy" value: `"1"`, `"true"`,
+/// `"yes"`, `"on"` (case-insensitive).
+fn flag_from_env(name: &str) -> bool {
+    std::env::var(name)
+        .is_ok_and(|val| matches!(val.to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+}
+
+/// Reads the number of build workers from `NIMBLE_BUILD_WORKERS`, ignoring
+/// invalid values.
+fn build_workers_from_env() -> Option<usize> {
+    let value = std::env::var("NIMBLE_BUILD_WORKERS").ok()?;
+    match value.parse() {
+        Ok(workers) if workers > 0 => Some(workers),
+        _ => {
+            tracing::warn!(value, "Ignoring invalid NIMBLE_BUILD_WORKERS");
+            None
+        }
+    }
+}
+
+/// Reads the container runtime from `NIMBLE_CONTAINER_RUNTIME`. An invalid
+/// value is an error rather than falling back to the default, which could
+/// be a runtime the host doesn't have.
+fn container_runtime_from_env() -> anyhow::Result<Option<RuntimeKind>> {
+    let Ok(value) = std::env::var("NIMBLE_CONTAINER_RUNTIME") else {
+        return Ok(None);
+    };
+    value
+        .parse()
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("Invalid NIMBLE_CONTAINER_RUNTIME: {e}"))
+}
+
+/// Reads the registry address from `NIMBLE_REGISTRY_URL`. An invalid value
+/// is an error rather than being ignored, which would quietly stop images
+/// from being pushed.
+fn registry_url_from_env() -> anyhow::Result<Option<String>> {
+    // Left empty, it's as good as unset
+    let Some(value) = std::env::var("NIMBLE_REGISTRY_URL")
+        .ok()
+        .filter(|value| !value.is_empty())
+    else {
+        return Ok(None);
+    };
+    parse_registry_url(&value)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("Invalid NIMBLE_REGISTRY_URL: {e}"))
+}
+
+/// Reads the registry namespace from `NIMBLE_REGISTRY_REPOSITORY`, ignoring
+/// invalid values.
+fn registry_repository_from_env() -> Option<String> {
+    let value = std::env::var("NIMBLE_REGISTRY_REPOSITORY").ok()?;
+    let value = value.trim_matches('/').to_string();
+    match validate_repository(&value) {
+        Ok(()) => Some(value),
+        Err(e) => {
+            tracing::warn!(value, error = %e, "Ignoring invalid NIMBLE_REGISTRY_REPOSITORY");
+            None
+        }
+    }
+}
+
+/// Reads a port number from an environment variable, ignoring invalid values.
+fn port_from_env(name: &str) -> Option<u16> {
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(port) => Some(port),
+        Err(_) => {
+            tracing::warn!(name, value, "Ignoring invalid port");
+            None
+        }
+    }
+}
+
 // Paths contains convenience methods to generate paths to certain artifacts.
 pub struct Paths {
     base_dir: PathBuf,
@@ -95,6 +341,24 @@ impl Paths {
             .join("workspace")
     }
 
+    // Returns the path to a build's log file.
+    pub fn build_log(&self, build_id: Uuid) -> PathBuf {
+        self.base_dir
+            .join("build")
+            .join(build_id.to_string())
+            .join("logs.txt")
+    }
+
+    // Returns the path to the key used to encrypt secrets in the database.
+    pub fn secret_key(&self) -> PathBuf {
+        self.base_dir.join("keys").join("secrets.key")
+    }
+
+    // Returns the default directory for builder plugins.
+    pub fn plugins(&self) -> PathBuf {
+        self.base_dir.join("plugins")
+    }
+
     // Returns the path to the SQLite database file.
     pub fn database(&self) -> PathBuf {
         self.base_dir.join("db").join("nimble.db")
@@ -1,13 +1,22 @@
 use std::{str::FromStr, time::Duration};
 
 use anyhow::{Context, Result};
+use nimble_core::{
+    builders::Image,
+    config::{BuilderType, DeployConfig, DeployTarget},
+    deployers::{Container, PublishedPort},
+};
+use serde::{Deserialize, Serialize};
 use sqlx::{
-    ConnectOptions,
+    ConnectOptions, QueryBuilder, Sqlite,
     sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
 };
 use uuid::Uuid;
 
-use crate::workers::build::BuildStatus;
+use crate::workers::{
+    build::{BuildFailure, BuildStatus},
+    deploy::{DeploymentStatus, ReleaseStatus},
+};
 
 /// Lightweight wrapper around the SQLx pool to encapsulate DB access.
 #[derive(Clone)]
@@ -39,16 +48,130 @@ impl Database {
         Ok(db)
     }
 
+    /// Insert a new app record into the database.
+    ///
+    /// Returns `false` if an app with the same name already exists.
+    pub async fn create_app(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO apps (name)
+            VALUES (?1)
+            ON CONFLICT (name) DO NOTHING
+            "#,
+        )
+        .bind(name)
+        .execute(&self.pool)
+        .await
+        .context("Failed to insert app record")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_app(&self, name: &str) -> Result<Option<AppRecord>> {
+        sqlx::query_as::<_, AppRecord>(
+            r#"
+            SELECT name, created_at, updated_at
+            FROM apps
+            WHERE name = ?1
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch app record")
+    }
+
+    /// List all apps, ordered by name.
+    pub async fn list_apps(&self) -> Result<Vec<AppRecord>> {
+        sqlx::query_as::<_, AppRecord>(
+            r#"
+            SELECT name, created_at, updated_at
+            FROM apps
+            ORDER BY name
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch app records")
+    }
+
+    /// Delete an app along with its builds, deployments, releases and env vars.
+    ///
+    /// Returns the deployments that were deleted, as they were at that
+    /// moment, so that their containers can be removed. A deploy that records
+    /// its container afterwards finds its deployment gone.
+    pub async fn delete_app(&self, name: &str) -> Result<Vec<DeploymentRecord>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to begin transaction")?;
+
+        let deployments = sqlx::query_as::<_, DeploymentRecordRow>(
+            r#"
+            DELETE FROM deployments
+            WHERE app = ?1
+            RETURNING id, app, release_version, build_id, target, image, container_id, ports,
+                      status, created_at, updated_at
+            "#,
+        )
+        .bind(name)
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to delete app deployments")?;
+
+        sqlx::query("DELETE FROM app_env WHERE app = ?1")
+            .bind(name)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete app env vars")?;
+
+        sqlx::query("DELETE FROM releases WHERE app = ?1")
+            .bind(name)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete app releases")?;
+
+        sqlx::query("DELETE FROM builds WHERE app = ?1")
+            .bind(name)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete app builds")?;
+
+        sqlx::query("DELETE FROM apps WHERE name = ?1")
+            .bind(name)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete app record")?;
+
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        deployments
+            .into_iter()
+            .map(DeploymentRecord::try_from)
+            .collect()
+    }
+
     /// Insert a new build record into the database.
-    pub async fn create_build(&self, build_id: Uuid, status: BuildStatus) -> Result<()> {
+    pub async fn create_build(
+        &self,
+        build_id: Uuid,
+        app: &str,
+        status: BuildStatus,
+        rebuild_of: Option<Uuid>,
+        no_cache: bool,
+    ) -> Result<()> {
         sqlx::query(
             r#"
-            INSERT INTO builds (id, status)
-            VALUES (?1, ?2)
+            INSERT INTO builds (id, app, status, rebuild_of, no_cache)
+            VALUES (?1, ?2, ?3, ?4, ?5)
             "#,
         )
         .bind(build_id.to_string())
+        .bind(app)
         .bind(status.as_str())
+        .bind(rebuild_of.map(|id| id.to_string()))
+        .bind(no_cache)
         .execute(&self.pool)
         .await
         .context("Failed to insert build record")?;
@@ -74,10 +197,109 @@ impl Database {
         Ok(())
     }
 
+    /// Record the builder a build uses, and why it was chosen.
+    pub async fn set_build_builder(
+        &self,
+        build_id: Uuid,
+        builder: &BuilderType,
+        reason: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE builds
+            SET builder = ?1, builder_reason = ?2, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?3
+            "#,
+        )
+        .bind(builder.to_string())
+        .bind(reason)
+        .bind(build_id.to_string())
+        .execute(&self.pool)
+        .await
+        .context("Failed to update build builder")?;
+
+        Ok(())
+    }
+
+    /// Mark a build as failed, recording why.
+    pub async fn fail_build(&self, build_id: Uuid, failure: BuildFailure) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE builds
+            SET status = ?1, failure_reason = ?2, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?3
+            "#,
+        )
+        .bind(BuildStatus::Failed.as_str())
+        .bind(failure.as_str())
+        .bind(build_id.to_string())
+        .execute(&self.pool)
+        .await
+        .context("Failed to update build status")?;
+
+        Ok(())
+    }
+
+    /// Record the image a build produced, its size if known, and how long
+    /// the build took.
+    pub async fn set_build_image(
+        &self,
+        build_id: Uuid,
+        image: &Image,
+        size: Option<u64>,
+        duration: Duration,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE builds
+            SET image_reference = ?1, image_digest = ?2, image_size = ?3, duration_ms = ?4,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?5
+            "#,
+        )
+        .bind(&image.reference)
+        .bind(&image.digest)
+        .bind(size.map(|size| size as i64))
+        .bind(duration.as_millis() as i64)
+        .bind(build_id.to_string())
+        .execute(&self.pool)
+        .await
+        .context("Failed to update build image")?;
+
+        Ok(())
+    }
+
+    /// Update the status of a build, but only if it currently has status `from`.
+    ///
+    /// Returns whether the build was updated.
+    pub async fn transition_build_status(
+        &self,
+        build_id: Uuid,
+        from: BuildStatus,
+        to: BuildStatus,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE builds
+            SET status = ?1, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?2 AND status = ?3
+            "#,
+        )
+        .bind(to.as_str())
+        .bind(build_id.to_string())
+        .bind(from.as_str())
+        .execute(&self.pool)
+        .await
+        .context("Failed to update build status")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn get_build(&self, build_id: Uuid) -> Result<Option<BuildRecord>> {
         let build = sqlx::query_as::<_, BuildRecordRow>(
             r#"
-            SELECT id, status, created_at, updated_at
+            SELECT id, app, status, rebuild_of, no_cache, builder, builder_reason, image_reference,
+                image_digest, image_size, duration_ms, failure_reason, created_at, updated_at
             FROM builds
             WHERE id = ?1
             "#,
@@ -90,40 +312,41 @@ impl Database {
         build.map(BuildRecord::try_from).transpose()
     }
 
-    /// List all builds, optionally filtered by status.
+    /// List all builds, optionally filtered by app and status.
     pub async fn list_builds(
         &self,
         limit: Option<i64>,
         status: Option<BuildStatus>,
+        app: Option<&str>,
     ) -> Result<Vec<BuildRecord>> {
-        let mut query = String::from(
+        let mut query = QueryBuilder::<Sqlite>::new(
             r#"
-            SELECT id, status, created_at, updated_at
+            SELECT id, app, status, rebuild_of, no_cache, builder, builder_reason, image_reference,
+                image_digest, image_size, duration_ms, failure_reason, created_at, updated_at
             FROM builds
+            WHERE 1 = 1
             "#,
         );
 
-        if status.is_some() {
-            query.push_str(" WHERE status = ?1");
+        if let Some(status) = status {
+            query.push(" AND status = ").push_bind(status.as_str());
         }
 
-        query.push_str(" ORDER BY created_at DESC");
+        if let Some(app) = app {
+            query.push(" AND app = ").push_bind(app);
+        }
+
+        query.push(" ORDER BY created_at DESC, rowid DESC");
 
         if let Some(limit) = limit {
-            query.push_str(&format!(" LIMIT {limit}"));
+            query.push(" LIMIT ").push_bind(limit);
         }
 
-        let builds = if let Some(status) = status {
-            sqlx::query_as::<_, BuildRecordRow>(&query)
-                .bind(status.as_str())
-                .fetch_all(&self.pool)
-                .await
-        } else {
-            sqlx::query_as::<_, BuildRecordRow>(&query)
-                .fetch_all(&self.pool)
-                .await
-        }
-        .context("Failed to fetch build records")?;
+        let builds = query
+            .build_query_as::<BuildRecordRow>()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch build records")?;
 
         builds
             .into_iter()
@@ -131,8 +354,374 @@ impl Database {
             .collect::<Result<Vec<_>>>()
     }
 
+    /// Insert a new deployment record into the database.
+    pub async fn create_deployment(
+        &self,
+        deployment_id: Uuid,
+        release: &ReleaseRecord,
+        target: DeployTarget,
+        image: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO deployments (id, app, release_version, build_id, target, image, status)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(deployment_id.to_string())
+        .bind(&release.app)
+        .bind(release.version)
+        .bind(release.build_id.to_string())
+        .bind(target.as_str())
+        .bind(image)
+        .bind(DeploymentStatus::Deploying.as_str())
+        .execute(&self.pool)
+        .await
+        .context("Failed to insert deployment record")?;
+
+        Ok(())
+    }
+
+    /// Update a deployment's status.
+    pub async fn update_deployment_status(
+        &self,
+        deployment_id: Uuid,
+        status: DeploymentStatus,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE deployments
+            SET status = ?1, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?2
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(deployment_id.to_string())
+        .execute(&self.pool)
+        .await
+        .context("Failed to update deployment status")?;
+
+        Ok(())
+    }
+
+    /// Update a deployment's status, but only if it currently has status `from`.
+    ///
+    /// Returns whether the deployment was updated.
+    pub async fn transition_deployment_status(
+        &self,
+        deployment_id: Uuid,
+        from: DeploymentStatus,
+        to: DeploymentStatus,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE deployments
+            SET status = ?1, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?2 AND status = ?3
+            "#,
+        )
+        .bind(to.as_str())
+        .bind(deployment_id.to_string())
+        .bind(from.as_str())
+        .execute(&self.pool)
+        .await
+        .context("Failed to update deployment status")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record the container started for a deployment, and its published ports.
+    ///
+    /// Returns `false` if the deployment no longer exists, e.g. because its
+    /// app was deleted.
+    pub async fn set_deployment_container(
+        &self,
+        deployment_id: Uuid,
+        container: &Container,
+    ) -> Result<bool> {
+        let ports = serde_json::to_string(&container.ports).context("Failed to serialize ports")?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE deployments
+            SET container_id = ?1, ports = ?2, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?3
+            "#,
+        )
+        .bind(&container.id)
+        .bind(ports)
+        .bind(deployment_id.to_string())
+        .execute(&self.pool)
+        .await
+        .context("Failed to update deployment container")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// List all deployments, optionally filtered by app and status.
+    pub async fn list_deployments(
+        &self,
+        status: Option<DeploymentStatus>,
+        app: Option<&str>,
+    ) -> Result<Vec<DeploymentRecord>> {
+        let mut query = QueryBuilder::<Sqlite>::new(
+            r#"
+            SELECT id, app, release_version, build_id, target, image, container_id, ports,
+                   status, created_at, updated_at
+            FROM deployments
+            WHERE 1 = 1
+            "#,
+        );
+
+        if let Some(status) = status {
+            query.push(" AND status = ").push_bind(status.as_str());
+        }
+
+        if let Some(app) = app {
+            query.push(" AND app = ").push_bind(app);
+        }
+
+        query.push(" ORDER BY created_at DESC, rowid DESC");
+
+        let deployments = query
+            .build_query_as::<DeploymentRecordRow>()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch deployment records")?;
+
+        deployments
+            .into_iter()
+            .map(DeploymentRecord::try_from)
+            .collect::<Result<Vec<_>>>()
+    }
+
+    /// Insert a new release for an app, numbered one higher than its latest release.
+    ///
+    /// Returns the new release's version number.
+    pub async fn create_release(
+        &self,
+        app: &str,
+        build_id: Uuid,
+        image: &str,
+        config: &DeployConfig,
+        env: &[EnvVar],
+        description: &str,
+    ) -> Result<i64> {
+        let config = serde_json::to_string(config).context("Failed to serialize deploy config")?;
+        let env = serde_json::to_string(env).context("Failed to serialize env vars")?;
+
+        let (version,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO releases (app, version, build_id, image, config, env, description, status)
+            SELECT ?1, COALESCE(MAX(version), 0) + 1, ?2, ?3, ?4, ?5, ?6, ?7
+            FROM releases
+            WHERE app = ?1
+            RETURNING version
+            "#,
+        )
+        .bind(app)
+        .bind(build_id.to_string())
+        .bind(image)
+        .bind(config)
+        .bind(env)
+        .bind(description)
+        .bind(ReleaseStatus::Pending.as_str())
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to insert release record")?;
+
+        Ok(version)
+    }
+
+    pub async fn get_release(&self, app: &str, version: i64) -> Result<Option<ReleaseRecord>> {
+        let release = sqlx::query_as::<_, ReleaseRecordRow>(
+            r#"
+            SELECT app, version, build_id, image, config, env, description, status, created_at,
+                   updated_at
+            FROM releases
+            WHERE app = ?1 AND version = ?2
+            "#,
+        )
+        .bind(app)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch release record")?;
+
+        release.map(ReleaseRecord::try_from).transpose()
+    }
+
+    /// List an app's releases, newest first, optionally filtered by status.
+    pub async fn list_releases(
+        &self,
+        app: &str,
+        status: Option<ReleaseStatus>,
+    ) -> Result<Vec<ReleaseRecord>> {
+        let mut query = QueryBuilder::<Sqlite>::new(
+            r#"
+            SELECT app, version, build_id, image, config, env, description, status, created_at,
+                   updated_at
+            FROM releases
+            WHERE app = "#,
+        );
+        query.push_bind(app);
+
+        if let Some(status) = status {
+            query.push(" AND status = ").push_bind(status.as_str());
+        }
+
+        query.push(" ORDER BY version DESC");
+
+        let releases = query
+            .build_query_as::<ReleaseRecordRow>()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch release records")?;
+
+        releases
+            .into_iter()
+            .map(ReleaseRecord::try_from)
+            .collect::<Result<Vec<_>>>()
+    }
+
+    /// Update a release's status.
+    pub async fn update_release_status(
+        &self,
+        app: &str,
+        version: i64,
+        status: ReleaseStatus,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE releases
+            SET status = ?1, updated_at = CURRENT_TIMESTAMP
+            WHERE app = ?2 AND version = ?3
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(app)
+        .bind(version)
+        .execute(&self.pool)
+        .await
+        .context("Failed to update release status")?;
+
+        Ok(())
+    }
+
+    /// List an app's env vars, ordered by name.
+    ///
+    /// Secret values are returned encrypted.
+    pub async fn list_app_env(&self, app: &str) -> Result<Vec<EnvVar>> {
+        sqlx::query_as::<_, EnvVar>(
+            r#"
+            SELECT name, value, secret
+            FROM app_env
+            WHERE app = ?1
+            ORDER BY name
+            "#,
+        )
+        .bind(app)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch app env vars")
+    }
+
+    /// Set and unset an app's env vars in a single transaction.
+    pub async fn update_app_env(&self, app: &str, set: &[EnvVar], unset: &[String]) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to begin transaction")?;
+
+        for var in set {
+            sqlx::query(
+                r#"
+                INSERT INTO app_env (app, name, value, secret)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (app, name) DO UPDATE
+                SET value = excluded.value, secret = excluded.secret, updated_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(app)
+            .bind(&var.name)
+            .bind(&var.value)
+            .bind(var.secret)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to set app env var")?;
+        }
+
+        for name in unset {
+            sqlx::query("DELETE FROM app_env WHERE app = ?1 AND name = ?2")
+                .bind(app)
+                .bind(name)
+                .execute(&mut *tx)
+                .await
+                .context("Failed to unset app env var")?;
+        }
+
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        Ok(())
+    }
+
+    /// Mark a release as the app's active release, superseding the previous one.
+    pub async fn activate_release(&self, app: &str, version: i64) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to begin transaction")?;
+
+        sqlx::query(
+            r#"
+            UPDATE releases
+            SET status = ?1, updated_at = CURRENT_TIMESTAMP
+            WHERE app = ?2 AND status = ?3
+            "#,
+        )
+        .bind(ReleaseStatus::Superseded.as_str())
+        .bind(app)
+        .bind(ReleaseStatus::Active.as_str())
+        .execute(&mut *tx)
+        .await
+        .context("Failed to supersede active release")?;
+
+        sqlx::query(
+            r#"
+            UPDATE releases
+            SET status = ?1, updated_at = CURRENT_TIMESTAMP
+            WHERE app = ?2 AND version = ?3
+            "#,
+        )
+        .bind(ReleaseStatus::Active.as_str())
+        .bind(app)
+        .bind(version)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to activate release")?;
+
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        Ok(())
+    }
+
     /// Run database migrations to create necessary tables.
     async fn migrate(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS apps (
+                name TEXT PRIMARY KEY,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to create apps table")?;
+
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS builds (
@@ -147,6 +736,36 @@ impl Database {
         .await
         .context("Failed to create builds table")?;
 
+        self.add_column_if_missing("builds", "app", "TEXT REFERENCES apps(name)")
+            .await?;
+        self.add_column_if_missing("builds", "rebuild_of", "TEXT REFERENCES builds(id)")
+            .await?;
+        self.add_column_if_missing("builds", "no_cache", "BOOLEAN NOT NULL DEFAULT 0")
+            .await?;
+        self.add_column_if_missing("builds", "builder", "TEXT")
+            .await?;
+        self.add_column_if_missing("builds", "builder_reason", "TEXT")
+            .await?;
+        self.add_column_if_missing("builds", "image_reference", "TEXT")
+            .await?;
+        self.add_column_if_missing("builds", "image_digest", "TEXT")
+            .await?;
+        self.add_column_if_missing("builds", "image_size", "INTEGER")
+            .await?;
+        self.add_column_if_missing("builds", "duration_ms", "INTEGER")
+            .await?;
+        self.add_column_if_missing("builds", "failure_reason", "TEXT")
+            .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_builds_app ON builds(app)
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to create builds app index")?;
+
         sqlx::query(
             r#"
             CREATE INDEX IF NOT EXISTS idx_builds_status ON builds(status)
@@ -165,15 +784,150 @@ impl Database {
         .await
         .context("Failed to create builds created_at index")?;
 
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS deployments (
+                id TEXT PRIMARY KEY,
+                build_id TEXT NOT NULL REFERENCES builds(id),
+                image TEXT NOT NULL,
+                container_id TEXT,
+                status TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to create deployments table")?;
+
+        self.add_column_if_missing("deployments", "app", "TEXT REFERENCES apps(name)")
+            .await?;
+        self.add_column_if_missing(
+            "deployments",
+            "target",
+            "TEXT NOT NULL DEFAULT 'local:docker'",
+        )
+        .await?;
+        self.add_column_if_missing("deployments", "release_version", "INTEGER")
+            .await?;
+        self.add_column_if_missing("deployments", "ports", "TEXT NOT NULL DEFAULT '[]'")
+            .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_deployments_status ON deployments(status)
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to create deployments status index")?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS releases (
+                app TEXT NOT NULL REFERENCES apps(name),
+                version INTEGER NOT NULL,
+                build_id TEXT NOT NULL REFERENCES builds(id),
+                image TEXT NOT NULL,
+                config TEXT NOT NULL,
+                description TEXT NOT NULL,
+                status TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (app, version)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to create releases table")?;
+
+        self.add_column_if_missing("releases", "env", "TEXT NOT NULL DEFAULT '[]'")
+            .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS app_env (
+                app TEXT NOT NULL REFERENCES apps(name),
+                name TEXT NOT NULL,
+                value TEXT NOT NULL,
+                secret BOOLEAN NOT NULL DEFAULT FALSE,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (app, name)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to create app_env table")?;
+
+        Ok(())
+    }
+
+    /// Add a column to an existing table, unless it already has it.
+    ///
+    /// SQLite has no `ADD COLUMN IF NOT EXISTS`, so this lets `migrate`
+    /// extend tables created by older versions of the agent.
+    async fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<()> {
+        let columns: Vec<(String,)> =
+            sqlx::query_as(&format!("SELECT name FROM pragma_table_info('{table}')"))
+                .fetch_all(&self.pool)
+                .await
+                .with_context(|| format!("Failed to fetch columns of {table} table"))?;
+
+        if columns.iter().any(|(name,)| name == column) {
+            return Ok(());
+        }
+
+        sqlx::query(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("Failed to add {column} column to {table} table"))?;
+
         Ok(())
     }
 }
 
+/// An application that builds are deployed as.
+#[derive(Debug, sqlx::FromRow)]
+pub struct AppRecord {
+    pub name: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
 /// Get a build by ID.
 #[derive(Debug)]
 pub struct BuildRecord {
     pub id: Uuid,
+    /// App the build belongs to (unset for builds from before apps existed)
+    pub app: Option<String>,
     pub status: BuildStatus,
+    /// The build this build was rebuilt from
+    pub rebuild_of: Option<Uuid>,
+    /// Whether the build bypasses the Docker layer cache
+    pub no_cache: bool,
+    /// The builder used, once the build has chosen one
+    pub builder: Option<BuilderType>,
+    /// Why the builder was chosen
+    pub builder_reason: Option<String>,
+    /// The image the build produced, once it has succeeded
+    pub image: Option<Image>,
+    /// Size of the image in bytes, if the runtime reported it
+    pub image_size: Option<u64>,
+    /// How long the build took, once it has succeeded
+    pub duration: Option<Duration>,
+    /// Why the build failed, if it did
+    pub failure_reason: Option<BuildFailure>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -182,7 +936,17 @@ pub struct BuildRecord {
 #[derive(Debug, sqlx::FromRow)]
 struct BuildRecordRow {
     id: String,
+    app: Option<String>,
     status: String,
+    rebuild_of: Option<String>,
+    no_cache: bool,
+    builder: Option<String>,
+    builder_reason: Option<String>,
+    image_reference: Option<String>,
+    image_digest: Option<String>,
+    image_size: Option<i64>,
+    duration_ms: Option<i64>,
+    failure_reason: Option<String>,
     created_at: String,
     updated_at: String,
 }
@@ -193,8 +957,146 @@ impl TryFrom<BuildRecordRow> for BuildRecord {
     fn try_from(row: BuildRecordRow) -> Result<Self> {
         Ok(BuildRecord {
             id: Uuid::parse_str(&row.id).context("Failed to parse build ID as UUID")?,
+            app: row.app,
             status: BuildStatus::from_str(&row.status)
                 .map_err(|e| anyhow::anyhow!("Failed to parse build status: {e}"))?,
+            rebuild_of: row
+                .rebuild_of
+                .map(|id| Uuid::parse_str(&id))
+                .transpose()
+                .context("Failed to parse rebuilt build ID as UUID")?,
+            no_cache: row.no_cache,
+            builder: row
+                .builder
+                .map(|builder| BuilderType::from_str(&builder))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("Failed to parse build builder: {e}"))?,
+            builder_reason: row.builder_reason,
+            image: row.image_reference.map(|reference| Image {
+                reference,
+                digest: row.image_digest,
+            }),
+            image_size: row.image_size.map(|size| size as u64),
+            duration: row.duration_ms.map(|ms| Duration::from_millis(ms as u64)),
+            failure_reason: row
+                .failure_reason
+                .map(|reason| BuildFailure::from_str(&reason))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("Failed to parse build failure reason: {e}"))?,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+/// A deployment of a build's image to a deploy target.
+#[derive(Debug)]
+pub struct DeploymentRecord {
+    pub id: Uuid,
+    pub app: Option<String>,
+    pub release_version: Option<i64>,
+    pub build_id: Uuid,
+    pub target: DeployTarget,
+    pub image: String,
+    pub container_id: Option<String>,
+    pub ports: Vec<PublishedPort>,
+    pub status: DeploymentStatus,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// Internal struct for SQLite row deserialization
+#[derive(Debug, sqlx::FromRow)]
+struct DeploymentRecordRow {
+    id: String,
+    app: Option<String>,
+    release_version: Option<i64>,
+    build_id: String,
+    target: String,
+    image: String,
+    container_id: Option<String>,
+    ports: String,
+    status: String,
+    created_at: String,
+    updated_at: String,
+}
+
+impl TryFrom<DeploymentRecordRow> for DeploymentRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(row: DeploymentRecordRow) -> Result<Self> {
+        Ok(DeploymentRecord {
+            id: Uuid::parse_str(&row.id).context("Failed to parse deployment ID as UUID")?,
+            app: row.app,
+            release_version: row.release_version,
+            build_id: Uuid::parse_str(&row.build_id).context("Failed to parse build ID as UUID")?,
+            target: DeployTarget::from_str(&row.target)
+                .map_err(|e| anyhow::anyhow!("Failed to parse deploy target: {e}"))?,
+            image: row.image,
+            container_id: row.container_id,
+            ports: serde_json::from_str(&row.ports).context("Failed to parse deployment ports")?,
+            status: DeploymentStatus::from_str(&row.status)
+                .map_err(|e| anyhow::anyhow!("Failed to parse deployment status: {e}"))?,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+/// An environment variable set on an app.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EnvVar {
+    pub name: String,
+    /// The value, encrypted if `secret` is set
+    pub value: String,
+    pub secret: bool,
+}
+
+/// A numbered snapshot of the image, deploy config and env vars that an app runs.
+#[derive(Debug)]
+pub struct ReleaseRecord {
+    pub app: String,
+    pub version: i64,
+    pub build_id: Uuid,
+    pub image: String,
+    pub config: DeployConfig,
+    pub env: Vec<EnvVar>,
+    pub description: String,
+    pub status: ReleaseStatus,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// Internal struct for SQLite row deserialization
+#[derive(Debug, sqlx::FromRow)]
+struct ReleaseRecordRow {
+    app: String,
+    version: i64,
+    build_id: String,
+    image: String,
+    config: String,
+    env: String,
+    description: String,
+    status: String,
+    created_at: String,
+    updated_at: String,
+}
+
+impl TryFrom<ReleaseRecordRow> for ReleaseRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(row: ReleaseRecordRow) -> Result<Self> {
+        Ok(ReleaseRecord {
+            app: row.app,
+            version: row.version,
+            build_id: Uuid::parse_str(&row.build_id).context("Failed to parse build ID as UUID")?,
+            image: row.image,
+            config: serde_json::from_str(&row.config)
+                .context("Failed to parse release deploy config")?,
+            env: serde_json::from_str(&row.env).context("Failed to parse release env vars")?,
+            description: row.description,
+            status: ReleaseStatus::from_str(&row.status)
+                .map_err(|e| anyhow::anyhow!("Failed to parse release status: {e}"))?,
             created_at: row.created_at,
             updated_at: row.updated_at,
         })
@@ -2,22 +2,34 @@
 mod api;
 mod config;
 mod db;
+mod proxy;
+mod secrets;
 mod state;
+#[cfg(test)]
+mod testing;
 mod workers;
 
 use std::sync::Arc;
 
+use nimble_core::runtime::select_runtime;
+
 use crate::{
     api::start_api,
     config::AgentConfig,
     db::Database,
+    proxy::{routes::Routes, start_proxy},
+    secrets::Secrets,
     state::ApiState,
-    workers::build::{BuildJob, BuildWorker},
+    workers::{
+        build::{BuildJob, BuildWorker, reconcile_builds},
+        deploy::{DeployJob, DeployWorker},
+        pool::BuildPool,
+    },
 };
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Arc::new(AgentConfig::new());
+    let config = Arc::new(AgentConfig::new().map_err(|e| format!("Failed to load config: {e}"))?);
 
     // Make sure data dir exists
     let data_dir = config.get_data_dir();
@@ -37,18 +49,75 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .await
         .map_err(|e| format!("Failed to initialize database: {e}"))?;
 
-    // Create build queue
+    // Load the key used to encrypt app secrets
+    let secrets = Secrets::load_or_create(&config.paths().secret_key())
+        .map_err(|e| format!("Failed to load secret key: {e}"))?;
+
+    // Load the proxy's routes to deployed apps
+    let routes = Routes::new(db.clone(), config.proxy_domain())
+        .await
+        .map_err(|e| format!("Failed to load proxy routes: {e}"))?;
+
+    // The container runtime is shared, so deploys see the images builds made
+    let runtime = select_runtime(config.container_runtime())
+        .map_err(|e| format!("Failed to set up container runtime: {e}"))?;
+
+    // Create build and deploy queues
     let (build_sender, build_receiver) = tokio::sync::mpsc::channel::<BuildJob>(100);
+    let (deploy_sender, deploy_receiver) = tokio::sync::mpsc::channel::<DeployJob>(100);
+
+    // Create and spawn deploy worker
+    let deployer = DeployWorker::new(
+        db.clone(),
+        routes.clone(),
+        secrets.clone(),
+        Arc::clone(&runtime),
+    );
+    tokio::spawn(async move {
+        if let Err(e) = deployer.run(deploy_receiver).await {
+            eprintln!("Deploy worker error: {e}");
+        }
+    });
+
+    // Create and start the build workers
+    let worker = BuildWorker::new(
+        Arc::clone(&config),
+        db.clone(),
+        secrets.clone(),
+        Arc::clone(&runtime),
+        deploy_sender.clone(),
+    );
+    let build_pool = BuildPool::start(worker, config.build_workers(), build_receiver);
+
+    // Pick up builds that the previous run didn't get to finish
+    reconcile_builds(&config, &db, &build_sender)
+        .await
+        .map_err(|e| format!("Failed to recover builds: {e}"))?;
+
+    let api_state = ApiState::new(
+        Arc::clone(&config),
+        build_sender,
+        deploy_sender,
+        build_pool,
+        db.clone(),
+        routes.clone(),
+        secrets,
+        runtime,
+    )
+    .await;
 
-    // Create and spawn build worker
-    let worker = BuildWorker::new(Arc::clone(&config), db.clone());
+    // The proxy's default port is privileged, so don't let a proxy that
+    // can't bind take the management API down with it
+    let proxy_port = config.proxy_port();
     tokio::spawn(async move {
-        if let Err(e) = worker.run(build_receiver).await {
-            eprintln!("Build worker error: {e}");
+        if let Err(e) = start_proxy(proxy_port, routes).await {
+            eprintln!(
+                "Proxy error on port {proxy_port}: {e}. Apps won't be reachable through nimbled; \
+                 set NIMBLE_PROXY_PORT to an unprivileged port or grant CAP_NET_BIND_SERVICE"
+            );
         }
     });
 
-    let api_state = ApiState::new(Arc::clone(&config), build_sender, db.clone()).await;
-    start_api(api_state).await?;
+    start_api(config.api_port(), api_state).await?;
     Ok(())
 }
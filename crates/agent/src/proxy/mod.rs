@@ -0,0 +1,242 @@
+pub mod routes;
+
+use std::net::SocketAddr;
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{
+        HeaderMap, HeaderName, HeaderValue, StatusCode, Uri, Version,
+        header::{CONNECTION, HOST, UPGRADE},
+    },
+    response::{IntoResponse, Response},
+};
+use hyper_util::{
+    client::legacy::{Client, connect::HttpConnector},
+    rt::{TokioExecutor, TokioIo},
+};
+use tracing::{debug, warn};
+
+use crate::proxy::routes::Routes;
+
+/// Headers that only apply to a single connection, and must not be forwarded.
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Clone)]
+struct ProxyState {
+    routes: Routes,
+    client: Client<HttpConnector, Body>,
+}
+
+/// Starts the reverse proxy that routes requests to deployed apps by `Host`
+/// header and path prefix.
+pub async fn start_proxy(port: u16, routes: Routes) -> Result<(), Box<dyn std::error::Error>> {
+    let state = ProxyState {
+        routes,
+        client: Client::builder(TokioExecutor::new()).build_http(),
+    };
+
+    let app = Router::new().fallback(proxy).with_state(state);
+
+    let addr = format!("0.0.0.0:{port}");
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+
+    println!("nimbled proxy listening on port {port}");
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn proxy(
+    State(state): State<ProxyState>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    mut req: Request,
+) -> Response {
+    let Some(host) = request_host(&req) else {
+        return (StatusCode::BAD_REQUEST, "Missing Host header").into_response();
+    };
+
+    let table = state.routes.current();
+    let Some(backend) = table.resolve(&host, req.uri().path()) else {
+        return (StatusCode::NOT_FOUND, format!("No app is routed at {host}")).into_response();
+    };
+    debug!(host = %host, app = %backend.app, port = backend.port, "Proxying request");
+
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let uri = match Uri::try_from(format!("http://127.0.0.1:{}{path_and_query}", backend.port)) {
+        Ok(uri) => uri,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    // Requests asking to switch protocols (e.g. WebSockets) keep their
+    // Upgrade headers, and are tunnelled once the backend agrees.
+    let upgrade = req.headers().get(UPGRADE).cloned();
+    let client_upgrade = upgrade.is_some().then(|| hyper::upgrade::on(&mut req));
+
+    *req.uri_mut() = uri;
+    *req.version_mut() = Version::HTTP_11;
+
+    let headers = req.headers_mut();
+    remove_hop_by_hop_headers(headers);
+    if let Some(upgrade) = upgrade {
+        headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(UPGRADE, upgrade);
+    }
+    set_forwarded_headers(headers, client_addr, &host);
+
+    let mut response = match state.client.request(req).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!(host = %host, error = %e, "Failed to proxy request");
+            return (StatusCode::BAD_GATEWAY, "App is not responding").into_response();
+        }
+    };
+
+    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        if let Some(client_upgrade) = client_upgrade {
+            let backend_upgrade = hyper::upgrade::on(&mut response);
+            tokio::spawn(async move {
+                match tokio::try_join!(client_upgrade, backend_upgrade) {
+                    Ok((client, backend)) => {
+                        let _ = tokio::io::copy_bidirectional(
+                            &mut TokioIo::new(client),
+                            &mut TokioIo::new(backend),
+                        )
+                        .await;
+                    }
+                    Err(e) => warn!(error = %e, "Failed to upgrade proxied connection"),
+                }
+            });
+        }
+    } else {
+        remove_hop_by_hop_headers(response.headers_mut());
+    }
+
+    response.map(Body::new)
+}
+
+/// Returns the request's hostname, without a port, in lowercase.
+fn request_host(req: &Request) -> Option<String> {
+    let host = req
+        .headers()
+        .get(HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| req.uri().host())?;
+
+    // Strip the port, taking care not to split IPv6 literals like [::1]:80
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !name.ends_with(':') && port.chars().all(|c| c.is_ascii_digit()) => {
+            name
+        }
+        _ => host,
+    };
+
+    Some(host.trim_end_matches('.').to_lowercase())
+}
+
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    // Connection can name further headers that are specific to the connection
+    let listed: Vec<HeaderName> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::try_from(name.trim()).ok())
+        .collect();
+
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(*name);
+    }
+}
+
+fn set_forwarded_headers(headers: &mut HeaderMap, client_addr: SocketAddr, host: &str) {
+    let client_ip = client_addr.ip().to_string();
+    let forwarded_for = match headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
+        Some(existing) => format!("{existing}, {client_ip}"),
+        None => client_ip,
+    };
+
+    if let Ok(value) = HeaderValue::try_from(forwarded_for) {
+        headers.insert("x-forwarded-for", value);
+    }
+    if let Ok(value) = HeaderValue::try_from(host) {
+        headers.insert("x-forwarded-host", value);
+    }
+    headers.insert("x-forwarded-proto", HeaderValue::from_static("http"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_of(host: &str) -> Option<String> {
+        let req = Request::builder()
+            .header(HOST, host)
+            .body(Body::empty())
+            .unwrap();
+        request_host(&req)
+    }
+
+    #[test]
+    fn host_is_lowercased_without_its_port() {
+        assert_eq!(
+            host_of("App.Example.com").as_deref(),
+            Some("app.example.com")
+        );
+        assert_eq!(
+            host_of("app.example.com:8080").as_deref(),
+            Some("app.example.com")
+        );
+    }
+
+    #[test]
+    fn trailing_dot_is_dropped() {
+        assert_eq!(
+            host_of("app.example.com.").as_deref(),
+            Some("app.example.com")
+        );
+        assert_eq!(
+            host_of("app.example.com.:80").as_deref(),
+            Some("app.example.com")
+        );
+    }
+
+    #[test]
+    fn ipv6_literals_keep_their_brackets() {
+        assert_eq!(host_of("[::1]:8080").as_deref(), Some("[::1]"));
+        assert_eq!(host_of("[::1]").as_deref(), Some("[::1]"));
+    }
+
+    #[test]
+    fn host_falls_back_to_the_request_uri() {
+        let req = Request::builder()
+            .uri("http://App.Example.com:8080/path")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_host(&req).as_deref(), Some("app.example.com"));
+
+        let req = Request::builder().uri("/path").body(Body::empty()).unwrap();
+        assert_eq!(request_host(&req), None);
+    }
+}
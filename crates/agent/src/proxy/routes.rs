@@ -0,0 +1,230 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use nimble_core::config::Protocol;
+use tokio::sync::{Mutex, watch};
+use tracing::{info, warn};
+
+use crate::{db::Database, workers::deploy::DeploymentStatus};
+
+/// A destination for proxied requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backend {
+    /// App the backend belongs to
+    pub app: String,
+    /// Path prefix the backend serves; empty for the whole host
+    pub path: String,
+    /// Host port the app's container is published on
+    pub port: u16,
+}
+
+/// Maps hostnames and path prefixes to the app containers serving them.
+#[derive(Debug, Default)]
+pub struct RouteTable {
+    /// Backends by hostname, longest path prefix first
+    hosts: HashMap<String, Vec<Backend>>,
+}
+
+impl RouteTable {
+    /// Finds the backend for a request, matching the longest path prefix.
+    pub fn resolve(&self, host: &str, path: &str) -> Option<&Backend> {
+        self.hosts.get(host)?.iter().find(|backend| {
+            backend.path.is_empty()
+                || path
+                    .strip_prefix(&backend.path)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    fn insert(&mut self, host: String, backend: Backend) {
+        let backends = self.hosts.entry(host.clone()).or_default();
+        if let Some(existing) = backends.iter().find(|b| b.path == backend.path) {
+            warn!(
+                host = %host,
+                path = %backend.path,
+                app = %backend.app,
+                existing_app = %existing.app,
+                "Route is already taken by another app, ignoring"
+            );
+            return;
+        }
+        backends.push(backend);
+        backends.sort_by_key(|b| std::cmp::Reverse(b.path.len()));
+    }
+}
+
+/// The proxy's live route table, rebuilt from the database whenever an
+/// app's deployments change.
+#[derive(Clone)]
+pub struct Routes {
+    db: Database,
+    domain: String,
+    table: watch::Sender<Arc<RouteTable>>,
+    // Serializes reloads, so a slow reload can't overwrite a newer table
+    reload_lock: Arc<Mutex<()>>,
+}
+
+impl Routes {
+    /// Creates the route table and loads the initial routes.
+    pub async fn new(db: Database, domain: &str) -> Result<Self> {
+        let routes = Self {
+            db,
+            domain: domain.to_string(),
+            table: watch::Sender::new(Arc::new(RouteTable::default())),
+            reload_lock: Arc::new(Mutex::new(())),
+        };
+        routes.reload().await?;
+        Ok(routes)
+    }
+
+    /// Returns the current route table.
+    pub fn current(&self) -> Arc<RouteTable> {
+        self.table.borrow().clone()
+    }
+
+    /// Rebuilds the route table from the apps' running deployments.
+    ///
+    /// Each app is reachable at `<app>.<domain>`, plus any routes listed in
+    /// the `deploy.routes` of its live release.
+    pub async fn reload(&self) -> Result<()> {
+        let _guard = self.reload_lock.lock().await;
+
+        let deployments = self
+            .db
+            .list_deployments(Some(DeploymentStatus::Running), None)
+            .await
+            .context("Failed to fetch running deployments")?;
+
+        let mut table = RouteTable::default();
+        let mut routed_apps = HashSet::new();
+
+        // Deployments are listed newest first, so during a blue/green switch
+        // the new container wins over the one it replaces.
+        for deployment in deployments {
+            let (Some(app), Some(version)) = (deployment.app, deployment.release_version) else {
+                continue;
+            };
+            if !routed_apps.insert(app.clone()) {
+                continue;
+            }
+
+            let Some(release) = self
+                .db
+                .get_release(&app, version)
+                .await
+                .context("Failed to fetch release")?
+            else {
+                continue;
+            };
+            let config = &release.config;
+
+            // Resolves a container port to the host port it's published on
+            let host_port = |container_port: Option<u16>| {
+                let container_port =
+                    container_port.or(config.healthcheck.as_ref().and_then(|h| h.port));
+                deployment
+                    .ports
+                    .iter()
+                    .filter(|p| p.protocol == Protocol::Tcp)
+                    .find(|p| container_port.is_none_or(|port| p.container == port))
+                    .map(|p| p.host)
+            };
+
+            match host_port(None) {
+                Some(port) => table.insert(
+                    format!("{app}.{}", self.domain),
+                    Backend {
+                        app: app.clone(),
+                        path: String::new(),
+                        port,
+                    },
+                ),
+                None => warn!(app = %app, "App has no published TCP port, not routing to it"),
+            }
+
+            for route in &config.routes {
+                let Some(port) = host_port(route.port) else {
+                    warn!(app = %app, host = %route.host, "Route port is not published, ignoring");
+                    continue;
+                };
+                table.insert(
+                    route.host.clone(),
+                    Backend {
+                        app: app.clone(),
+                        path: route.path.clone().unwrap_or_default(),
+                        port,
+                    },
+                );
+            }
+        }
+
+        info!(hosts = table.hosts.len(), "Reloaded proxy routes");
+        self.table.send_replace(Arc::new(table));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend(app: &str, path: &str) -> Backend {
+        Backend {
+            app: app.to_string(),
+            path: path.to_string(),
+            port: 8080,
+        }
+    }
+
+    fn table() -> RouteTable {
+        let mut table = RouteTable::default();
+        table.insert("example.com".to_string(), backend("web", ""));
+        table.insert("example.com".to_string(), backend("api", "/api"));
+        table.insert("example.com".to_string(), backend("admin", "/api/admin"));
+        table
+    }
+
+    fn resolve<'a>(table: &'a RouteTable, host: &str, path: &str) -> Option<&'a str> {
+        table
+            .resolve(host, path)
+            .map(|backend| backend.app.as_str())
+    }
+
+    #[test]
+    fn longest_path_prefix_wins() {
+        let table = table();
+        assert_eq!(resolve(&table, "example.com", "/"), Some("web"));
+        assert_eq!(resolve(&table, "example.com", "/api"), Some("api"));
+        assert_eq!(resolve(&table, "example.com", "/api/users"), Some("api"));
+        assert_eq!(
+            resolve(&table, "example.com", "/api/admin/x"),
+            Some("admin")
+        );
+    }
+
+    #[test]
+    fn prefixes_only_match_whole_segments() {
+        let table = table();
+        assert_eq!(resolve(&table, "example.com", "/apix"), Some("web"));
+        assert_eq!(resolve(&table, "example.com", "/api/adminx"), Some("api"));
+
+        let mut table = RouteTable::default();
+        table.insert("example.com".to_string(), backend("api", "/api"));
+        assert_eq!(resolve(&table, "example.com", "/apix"), None);
+    }
+
+    #[test]
+    fn unknown_hosts_are_not_routed() {
+        assert_eq!(resolve(&table(), "other.com", "/"), None);
+    }
+
+    #[test]
+    fn taken_routes_keep_their_first_app() {
+        let mut table = table();
+        table.insert("example.com".to_string(), backend("intruder", "/api"));
+        assert_eq!(resolve(&table, "example.com", "/api"), Some("api"));
+    }
+}
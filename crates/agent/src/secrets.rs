@@ -0,0 +1,183 @@
+use std::{io::Write, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng},
+};
+
+/// Length of a ChaCha20-Poly1305 nonce in bytes.
+const NONCE_LEN: usize = 12;
+
+/// Secrets encrypts secret values (such as app env vars) before they are
+/// stored in the database, using a key file kept in the data dir.
+#[derive(Clone)]
+pub struct Secrets {
+    cipher: Arc<ChaCha20Poly1305>,
+}
+
+impl Secrets {
+    /// Loads the key from `path`, generating a new key file if there isn't one.
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        let key = match std::fs::read(path) {
+            Ok(key) => key,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::create_key(path)?,
+            Err(e) => {
+                return Err(e).with_context(|| format!("reading secret key {}", path.display()));
+            }
+        };
+
+        if key.len() != 32 {
+            anyhow::bail!(
+                "secret key {} is {} bytes, expected 32",
+                path.display(),
+                key.len()
+            );
+        }
+
+        Ok(Self {
+            cipher: Arc::new(ChaCha20Poly1305::new(Key::from_slice(&key))),
+        })
+    }
+
+    fn create_key(path: &Path) -> Result<Vec<u8>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating key directory {}", parent.display()))?;
+        }
+
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        // Only the agent's user may read the key
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("creating secret key {}", path.display()))?;
+        file.write_all(&key)
+            .with_context(|| format!("writing secret key {}", path.display()))?;
+
+        Ok(key.to_vec())
+    }
+
+    /// Encrypts a value, returning the base64-encoded nonce and ciphertext.
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow::anyhow!("failed to encrypt secret"))?;
+
+        let mut encoded = nonce.to_vec();
+        encoded.extend_from_slice(&ciphertext);
+        Ok(BASE64.encode(encoded))
+    }
+
+    /// Decrypts a value produced by [`Secrets::encrypt`].
+    pub fn decrypt(&self, encoded: &str) -> Result<String> {
+        let bytes = BASE64
+            .decode(encoded)
+            .context("secret is not valid base64")?;
+        if bytes.len() < NONCE_LEN {
+            anyhow::bail!("secret is too short");
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("failed to decrypt secret, was the key file replaced?"))?;
+
+        String::from_utf8(plaintext).context("secret is not valid UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = Secrets::load_or_create(&dir.path().join("secret.key")).unwrap();
+
+        let encrypted = secrets.encrypt("s3cr3t value").unwrap();
+        assert!(!encrypted.contains("s3cr3t"));
+        assert_eq!(secrets.decrypt(&encrypted).unwrap(), "s3cr3t value");
+
+        // Each encryption uses a fresh nonce
+        assert_ne!(secrets.encrypt("s3cr3t value").unwrap(), encrypted);
+    }
+
+    #[test]
+    fn key_is_reused_once_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys/secret.key");
+        let encrypted = Secrets::load_or_create(&path)
+            .unwrap()
+            .encrypt("value")
+            .unwrap();
+
+        let reloaded = Secrets::load_or_create(&path).unwrap();
+        assert_eq!(reloaded.decrypt(&encrypted).unwrap(), "value");
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = Secrets::load_or_create(&dir.path().join("a.key")).unwrap();
+        let other = Secrets::load_or_create(&dir.path().join("b.key")).unwrap();
+
+        let encrypted = secrets.encrypt("value").unwrap();
+        let err = other.decrypt(&encrypted).unwrap_err();
+        assert!(
+            err.to_string().contains("failed to decrypt secret"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = Secrets::load_or_create(&dir.path().join("secret.key")).unwrap();
+
+        assert!(secrets.decrypt("not base64!").is_err());
+        assert!(secrets.decrypt(&BASE64.encode([0; 4])).is_err());
+
+        // Tampering with the ciphertext is detected
+        let mut bytes = BASE64.decode(secrets.encrypt("value").unwrap()).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(secrets.decrypt(&BASE64.encode(bytes)).is_err());
+    }
+
+    #[test]
+    fn key_of_the_wrong_length_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret.key");
+        std::fs::write(&path, [0; 16]).unwrap();
+
+        let err = Secrets::load_or_create(&path).err().unwrap();
+        assert!(
+            err.to_string().contains("is 16 bytes, expected 32"),
+            "{err}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_only_readable_by_its_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret.key");
+        Secrets::load_or_create(&path).unwrap();
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(metadata.len(), 32);
+    }
+}
@@ -1,29 +1,52 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use anyhow::{Context, Result};
 use axum::body::Bytes;
+use nimble_core::runtime::ContainerRuntime;
 use tokio::{fs::File, io::AsyncWriteExt, sync::mpsc::Sender};
 use uuid::Uuid;
 
-use crate::{config::AgentConfig, db::Database, workers::build::BuildJob};
+use crate::{
+    config::AgentConfig,
+    db::Database,
+    proxy::routes::Routes,
+    secrets::Secrets,
+    workers::{build::BuildJob, deploy::DeployJob, pool::BuildPool},
+};
 
 #[derive(Clone)]
 pub struct ApiState {
     config: Arc<AgentConfig>,
     pub build_queue: Sender<BuildJob>,
+    pub deploy_queue: Sender<DeployJob>,
+    pub build_pool: BuildPool,
     pub db: Database,
+    pub routes: Routes,
+    pub secrets: Secrets,
+    pub runtime: Arc<dyn ContainerRuntime>,
 }
 
 impl ApiState {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         config: Arc<AgentConfig>,
         build_queue: Sender<BuildJob>,
+        deploy_queue: Sender<DeployJob>,
+        build_pool: BuildPool,
         db: Database,
+        routes: Routes,
+        secrets: Secrets,
+        runtime: Arc<dyn ContainerRuntime>,
     ) -> Self {
         Self {
             config,
             build_queue,
+            deploy_queue,
+            build_pool,
             db,
+            routes,
+            secrets,
+            runtime,
         }
     }
 
@@ -56,4 +79,52 @@ impl ApiState {
 
         Ok(())
     }
+
+    // Copy a build's source archive to use for another build
+    pub async fn copy_archive(&self, from: Uuid, to: Uuid) -> Result<()> {
+        let from = self.config.paths().source_archive(from);
+        let to = self.config.paths().source_archive(to);
+
+        tokio::fs::copy(&from, &to)
+            .await
+            .with_context(|| format!("copying source archive {}", from.display()))?;
+
+        Ok(())
+    }
+
+    // Returns the path to a build's log file
+    pub fn build_log_path(&self, build_id: Uuid) -> PathBuf {
+        self.config.paths().build_log(build_id)
+    }
+
+    // Remove a build's source archive from disk, if it exists
+    pub async fn remove_archive(&self, build_id: Uuid) {
+        let path = self.config.paths().source_archive(build_id);
+        if let Err(e) = tokio::fs::remove_file(&path).await
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to remove source archive");
+        }
+    }
+
+    // Remove a build's workspace and log file from disk, if they exist
+    pub async fn remove_build_files(&self, build_id: Uuid) {
+        let paths = self.config.paths();
+        let build_dir = paths.build_dir(build_id);
+        if let Err(e) = tokio::fs::remove_dir_all(&build_dir).await
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!(path = %build_dir.display(), error = %e, "Failed to remove build directory");
+        }
+        let log = paths.build_log(build_id);
+        if let Err(e) = tokio::fs::remove_file(&log).await
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!(path = %log.display(), error = %e, "Failed to remove build log");
+        }
+        // Both live in a directory of their own, which is left empty
+        if let Some(parent) = log.parent() {
+            let _ = tokio::fs::remove_dir(parent).await;
+        }
+    }
 }
@@ -0,0 +1,215 @@
+//! Shared setup for the agent's unit tests, which run against a database in
+//! a temporary directory and the fake container runtime.
+
+use std::{str::FromStr, sync::Arc};
+
+use nimble_core::{
+    builders::BuildContext,
+    config::{DeployConfig, NimbleConfig},
+    runtime::{BuildOptions, ContainerRuntime, fake::FakeRuntime},
+};
+use tempfile::TempDir;
+use tokio::sync::mpsc::{self, Receiver};
+use uuid::Uuid;
+
+use crate::{
+    config::AgentConfig,
+    db::Database,
+    proxy::routes::Routes,
+    secrets::Secrets,
+    state::ApiState,
+    workers::{
+        build::{BuildJob, BuildStatus, BuildWorker},
+        deploy::{DeployJob, DeployWorker},
+        pool::BuildPool,
+    },
+};
+
+pub struct TestEnv {
+    pub dir: TempDir,
+    pub config: Arc<AgentConfig>,
+    pub db: Database,
+    pub secrets: Secrets,
+    pub routes: Routes,
+    pub runtime: Arc<FakeRuntime>,
+}
+
+impl TestEnv {
+    pub async fn new() -> Self {
+        Self::with_config(|config| config).await
+    }
+
+    /// Sets up an agent whose config is changed by `configure`.
+    pub async fn with_config(configure: impl FnOnce(AgentConfig) -> AgentConfig) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(configure(AgentConfig::with_data_dir(
+            &dir.path().join("data"),
+        )));
+        std::fs::create_dir_all(config.get_data_dir()).unwrap();
+
+        let db = Database::connect(&format!(
+            "sqlite:{}",
+            dir.path().join("nimble.db").display()
+        ))
+        .await
+        .unwrap();
+        let secrets = Secrets::load_or_create(&dir.path().join("secret.key")).unwrap();
+        let routes = Routes::new(db.clone(), "localhost").await.unwrap();
+
+        TestEnv {
+            dir,
+            config,
+            db,
+            secrets,
+            routes,
+            runtime: Arc::new(FakeRuntime::new()),
+        }
+    }
+
+    pub fn deploy_worker(&self) -> DeployWorker {
+        DeployWorker::new(
+            self.db.clone(),
+            self.routes.clone(),
+            self.secrets.clone(),
+            self.runtime.clone(),
+        )
+    }
+
+    pub fn build_worker(&self, deploy_queue: mpsc::Sender<DeployJob>) -> BuildWorker {
+        BuildWorker::new(
+            Arc::clone(&self.config),
+            self.db.clone(),
+            self.secrets.clone(),
+            self.runtime.clone(),
+            deploy_queue,
+        )
+    }
+
+    /// Returns the API's state, with a single build worker running behind
+    /// it, and the queue deploys are sent to.
+    pub async fn api_state(&self) -> (ApiState, Receiver<DeployJob>) {
+        let (build_tx, build_rx) = mpsc::channel::<BuildJob>(16);
+        let (deploy_tx, deploy_rx) = mpsc::channel(16);
+        let pool = BuildPool::start(self.build_worker(deploy_tx.clone()), 1, build_rx);
+
+        let state = ApiState::new(
+            Arc::clone(&self.config),
+            build_tx,
+            deploy_tx,
+            pool,
+            self.db.clone(),
+            self.routes.clone(),
+            self.secrets.clone(),
+            self.runtime.clone(),
+        )
+        .await;
+        (state, deploy_rx)
+    }
+
+    /// Records a queued build of an app, with a source archive holding the
+    /// given files. Returns the build's job.
+    pub async fn queue_build(&self, app: &str, files: &[(&str, &str)]) -> BuildJob {
+        self.db.create_app(app).await.unwrap();
+        let build_id = Uuid::new_v4();
+        self.write_source(build_id, files);
+        self.db
+            .create_build(build_id, app, BuildStatus::Queued, None, false)
+            .await
+            .unwrap();
+
+        BuildJob {
+            build_id,
+            app: app.to_string(),
+            no_cache: false,
+        }
+    }
+
+    /// Writes a build's source archive, a gzipped tar of the given files.
+    pub fn write_source(&self, build_id: Uuid, files: &[(&str, &str)]) {
+        let path = self.config.paths().source_archive(build_id);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        let file = std::fs::File::create(path).unwrap();
+        let gz = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+        let mut archive = tar::Builder::new(gz);
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        archive.into_inner().unwrap().finish().unwrap();
+    }
+
+    /// Records a successful build of an app, with an image in the fake
+    /// runtime, and a pending release of it using the given `deploy`
+    /// section. Returns the release's version.
+    pub async fn release(&self, app: &str, deploy: &str) -> i64 {
+        self.db.create_app(app).await.unwrap();
+        let build_id = Uuid::new_v4();
+        self.db
+            .create_build(build_id, app, BuildStatus::Success, None, false)
+            .await
+            .unwrap();
+
+        let dockerfile = self.dir.path().join("Dockerfile");
+        std::fs::write(&dockerfile, "FROM scratch\n").unwrap();
+        let tag = format!("nimble-build-{build_id}:latest");
+        let options = BuildOptions {
+            context: self.dir.path().to_path_buf(),
+            dockerfile,
+            tag: tag.clone(),
+            no_cache: false,
+            target: None,
+            build_args: Default::default(),
+            secrets: Vec::new(),
+        };
+        self.runtime
+            .build(&options, &BuildContext::default())
+            .await
+            .unwrap();
+
+        self.db
+            .create_release(app, build_id, &tag, &deploy_config(deploy), &[], "test")
+            .await
+            .unwrap()
+    }
+
+    /// Runs the deploy worker until it has processed the given releases.
+    pub async fn deploy(&self, app: &str, versions: &[i64]) {
+        let (tx, rx) = mpsc::channel(versions.len().max(1));
+        for &version in versions {
+            tx.send(DeployJob {
+                app: app.to_string(),
+                version,
+            })
+            .await
+            .unwrap();
+        }
+        drop(tx);
+        self.deploy_worker().run(rx).await.unwrap();
+    }
+
+    /// Runs the deploy worker on the jobs already in `queue`.
+    pub async fn deploy_queued(&self, queue: &mut Receiver<DeployJob>) {
+        let (tx, rx) = mpsc::channel(16);
+        while let Ok(job) = queue.try_recv() {
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+        self.deploy_worker().run(rx).await.unwrap();
+    }
+}
+
+/// Parses the `deploy` section of a nimble.yaml.
+pub fn deploy_config(deploy: &str) -> DeployConfig {
+    let yaml = format!("deploy:\n{}", indent(deploy));
+    NimbleConfig::from_str(&yaml).unwrap().deploy.unwrap()
+}
+
+fn indent(yaml: &str) -> String {
+    yaml.lines().map(|line| format!("  {line}\n")).collect()
+}
@@ -1,22 +1,55 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     fmt, fs,
     path::{Path, PathBuf},
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Instant,
 };
 
 use anyhow::{Context, Result};
-use nimble_core::{builders::select_builder, config::NimbleConfig};
+use nimble_core::{
+    builders::{BuildContext, Image, detect::detect_builder, log::BuildLog, select_builder},
+    config::{BuilderConfig, NimbleConfig, RegistryConfig},
+    runtime::{ContainerRuntime, RegistryAuth},
+};
 use serde::{Deserialize, Serialize};
 use tar::Archive;
-use tokio::{fs::create_dir_all, sync::mpsc::Receiver, task::spawn_blocking};
-use tracing::{error, info};
+use tokio::{fs::create_dir_all, sync::mpsc::Sender, task::spawn_blocking};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
-use crate::{config::AgentConfig, db::Database};
+use crate::{
+    config::AgentConfig,
+    db::Database,
+    secrets::Secrets,
+    workers::{deploy::DeployJob, pool::BuildPool},
+};
+
+/// Where a build's image is pushed.
+struct RegistryTarget {
+    /// Registry host, with an optional port
+    url: String,
+    repository: String,
+    credentials: RegistryCredentials,
+}
+
+/// Where the credentials for a registry come from.
+enum RegistryCredentials {
+    None,
+    /// A file holding `<username>:<password>`, from the agent's config
+    File(PathBuf),
+    /// The app's secret env var with this name, from nimble.yaml
+    Secret(String),
+}
 
 pub struct BuildJob {
     pub build_id: Uuid,
+    /// Name of the app the build belongs to
+    pub app: String,
+    /// Build without using the Docker layer cache
+    pub no_cache: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -26,15 +59,31 @@ pub enum BuildStatus {
     Building,
     Success,
     Failed,
+    Cancelled,
+    /// The agent stopped while the build was running
+    Interrupted,
 }
 
 impl BuildStatus {
+    /// Returns true once the build has stopped, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            BuildStatus::Success
+                | BuildStatus::Failed
+                | BuildStatus::Cancelled
+                | BuildStatus::Interrupted
+        )
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             BuildStatus::Queued => "queued",
             BuildStatus::Building => "building",
             BuildStatus::Success => "success",
             BuildStatus::Failed => "failed",
+            BuildStatus::Cancelled => "cancelled",
+            BuildStatus::Interrupted => "interrupted",
         }
     }
 }
@@ -54,46 +103,316 @@ impl FromStr for BuildStatus {
             "building" => Ok(BuildStatus::Building),
             "success" => Ok(BuildStatus::Success),
             "failed" => Ok(BuildStatus::Failed),
+            "cancelled" => Ok(BuildStatus::Cancelled),
+            "interrupted" => Ok(BuildStatus::Interrupted),
             _ => Err(format!("Unknown build status: {s}")),
         }
     }
 }
 
+/// Why a build failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildFailure {
+    /// The image couldn't be built
+    Build,
+    /// The image was built, but couldn't be pushed to the registry
+    Push,
+}
+
+impl BuildFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BuildFailure::Build => "build",
+            BuildFailure::Push => "push",
+        }
+    }
+}
+
+impl FromStr for BuildFailure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "build" => Ok(BuildFailure::Build),
+            "push" => Ok(BuildFailure::Push),
+            _ => Err(format!("Unknown build failure reason: {s}")),
+        }
+    }
+}
+
+/// RunningBuilds tracks the builds that are currently running, so that they
+/// can be cancelled from the API.
+#[derive(Clone, Default)]
+pub struct RunningBuilds {
+    builds: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+}
+
+impl RunningBuilds {
+    /// Registers a build as running, returning the token that cancels it.
+    fn start(&self, build_id: Uuid) -> CancellationToken {
+        let token = CancellationToken::new();
+        self.lock().insert(build_id, token.clone());
+        token
+    }
+
+    fn finish(&self, build_id: Uuid) {
+        self.lock().remove(&build_id);
+    }
+
+    /// Stops a build from being cancelled through its token. Returns false if
+    /// it was cancelled already. A cancel that comes later finds it no
+    /// longer running and moves its status itself.
+    fn settle(&self, build_id: Uuid, token: &CancellationToken) -> bool {
+        let mut builds = self.lock();
+        builds.remove(&build_id);
+        !token.is_cancelled()
+    }
+
+    /// Cancels a running build. Returns false if the build isn't running.
+    pub fn cancel(&self, build_id: Uuid) -> bool {
+        match self.lock().get(&build_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Uuid, CancellationToken>> {
+        self.builds.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Clone)]
 pub struct BuildWorker {
     config: Arc<AgentConfig>,
     db: Database,
+    secrets: Secrets,
+    runtime: Arc<dyn ContainerRuntime>,
+    deploy_queue: Sender<DeployJob>,
+    running: RunningBuilds,
 }
 
 impl BuildWorker {
-    pub fn new(config: Arc<AgentConfig>, db: Database) -> Self {
-        Self { config, db }
+    pub fn new(
+        config: Arc<AgentConfig>,
+        db: Database,
+        secrets: Secrets,
+        runtime: Arc<dyn ContainerRuntime>,
+        deploy_queue: Sender<DeployJob>,
+    ) -> Self {
+        Self {
+            config,
+            db,
+            secrets,
+            runtime,
+            deploy_queue,
+            running: RunningBuilds::default(),
+        }
     }
 
-    /// Runs the build worker, processing build jobs from the channel.
-    pub async fn run(&self, mut build_queue: Receiver<BuildJob>) -> Result<()> {
-        info!("Build worker started");
+    /// Returns the builds this worker (and its clones) are running.
+    pub fn running_builds(&self) -> RunningBuilds {
+        self.running.clone()
+    }
 
-        while let Some(job) = build_queue.recv().await {
+    /// Runs the build worker, processing build jobs from the pool.
+    pub async fn run(&self, id: usize, pool: &BuildPool) {
+        info!(worker = id, "Build worker started");
+
+        while let Some(job) = pool.next_job(id).await {
             let build_id = job.build_id;
-            info!(build_id = %build_id, "Processing build job");
+            info!(worker = id, build_id = %build_id, "Processing build job");
 
             if let Err(e) = self.process_build(job).await {
                 error!(build_id = %build_id, error = %e, "Build failed");
                 // Continue processing other jobs even if one fails
             }
+            pool.finish_job(id);
         }
 
-        info!("Build worker stopped (channel closed)");
-        Ok(())
+        info!(worker = id, "Build worker stopped (queue closed)");
+    }
+
+    /// Marks a build as failed after its worker panicked while running it.
+    pub async fn abandon(&self, build_id: Uuid) {
+        self.running.finish(build_id);
+
+        let log = open_build_log(&self.config, build_id).await;
+        log.line("==> Build failed: the build worker crashed").await;
+        if let Err(e) = self
+            .db
+            .update_build_status(build_id, BuildStatus::Failed)
+            .await
+        {
+            error!(build_id = %build_id, error = %e, "Failed to mark abandoned build failed");
+        }
     }
 
     async fn process_build(&self, job: BuildJob) -> Result<()> {
-        // Update status to Building
-        self.db
-            .update_build_status(job.build_id, BuildStatus::Building)
+        // Register the build as running before its status leaves Queued, so
+        // that a cancel request that no longer finds it queued can reach it
+        let build_id = job.build_id;
+        let cancel = self.running.start(build_id);
+        let result = self.run_build(job, cancel).await;
+        self.running.finish(build_id);
+        result
+    }
+
+    async fn run_build(&self, job: BuildJob, cancel: CancellationToken) -> Result<()> {
+        // Update status to Building, unless the build was cancelled while queued
+        let started = self
+            .db
+            .transition_build_status(job.build_id, BuildStatus::Queued, BuildStatus::Building)
             .await
             .context("Failed to update build status to building")?;
+        if !started {
+            info!(build_id = %job.build_id, "Skipping build that is no longer queued");
+            return Ok(());
+        }
+        let started_at = Instant::now();
+
+        let log = open_build_log(&self.config, job.build_id).await;
+        let ctx = BuildContext {
+            log: log.clone(),
+            cancel,
+            no_cache: job.no_cache,
+            runtime: Arc::clone(&self.runtime),
+            secrets: BTreeMap::new(),
+        };
+        let built = match self.build_image(&job, &ctx).await {
+            Ok((cfg, image)) => {
+                log.line(&format!("==> Built image {}", image.reference))
+                    .await;
+                self.push_image(&job, &cfg, image, &ctx)
+                    .await
+                    .map(|image| (cfg, image))
+                    .map_err(|e| (BuildFailure::Push, e))
+            }
+            Err(e) => Err((BuildFailure::Build, e)),
+        };
+        let (cfg, image) = match built {
+            Ok(built) => built,
+            Err(_) if ctx.cancel.is_cancelled() => {
+                return self.mark_cancelled(job.build_id, &log).await;
+            }
+            Err((failure, e)) => {
+                let step = match failure {
+                    BuildFailure::Build => "Build",
+                    BuildFailure::Push => "Push",
+                };
+                log.line(&format!("==> {step} failed: {e:#}")).await;
+                let _ = self.db.fail_build(job.build_id, failure).await;
+                return Err(e);
+            }
+        };
+
+        info!(
+            build_id = %job.build_id,
+            image_reference = %image.reference,
+            image_digest = ?image.digest,
+            "Build completed successfully"
+        );
+
+        // A cancel that arrived once the image was built still stops the
+        // build from being released, and leaves it without an image
+        if ctx.cancel.is_cancelled() {
+            return self.mark_cancelled(job.build_id, &log).await;
+        }
 
+        // Record the image before the build shows as successful, so that
+        // successful builds always have one
+        let size = match self.runtime.inspect_image(&image.reference).await {
+            Ok(info) => Some(info.size),
+            Err(e) => {
+                warn!(build_id = %job.build_id, error = %e, "Failed to get image size");
+                None
+            }
+        };
+        self.db
+            .set_build_image(job.build_id, &image, size, started_at.elapsed())
+            .await
+            .context("Failed to record build image")?;
+
+        // A cancel either reached the token before it's settled, or races
+        // the cancel endpoint's own Building -> Cancelled transition with
+        // this one, which only one of them wins
+        if !self.running.settle(job.build_id, &ctx.cancel) {
+            return self.mark_cancelled(job.build_id, &log).await;
+        }
+
+        // Update status to Success, unless the build was cancelled or removed
+        // meanwhile (e.g. its app was deleted)
+        let succeeded = self
+            .db
+            .transition_build_status(job.build_id, BuildStatus::Building, BuildStatus::Success)
+            .await
+            .context("Failed to update build status to success")?;
+        if !succeeded {
+            info!(build_id = %job.build_id, "Not releasing build that is no longer running");
+            return Ok(());
+        }
+
+        // Create a release and hand it over to the deployer
+        if let Some(config) = cfg.deploy {
+            let env = self
+                .db
+                .list_app_env(&job.app)
+                .await
+                .context("Failed to fetch app env vars")?;
+
+            let version = self
+                .db
+                .create_release(
+                    &job.app,
+                    job.build_id,
+                    &image.reference,
+                    &config,
+                    &env,
+                    &format!("Deploy build {}", job.build_id),
+                )
+                .await
+                .context("Failed to create release")?;
+
+            let job = DeployJob {
+                app: job.app,
+                version,
+            };
+            self.deploy_queue
+                .send(job)
+                .await
+                .context("Failed to enqueue deploy job")?;
+        }
+
+        Ok(())
+    }
+
+    async fn mark_cancelled(&self, build_id: Uuid, log: &BuildLog) -> Result<()> {
+        log.line("==> Build cancelled").await;
+        // Only a build that's still building is marked cancelled, like the
+        // cancel endpoint does, so a finished or removed one is left alone
+        let cancelled = self
+            .db
+            .transition_build_status(build_id, BuildStatus::Building, BuildStatus::Cancelled)
+            .await
+            .context("Failed to update build status to cancelled")?;
+        if cancelled {
+            info!(build_id = %build_id, "Build cancelled");
+        } else {
+            info!(build_id = %build_id, "Cancelled build is no longer running");
+        }
+        Ok(())
+    }
+
+    /// Extracts the build's source and builds an image from it.
+    async fn build_image(
+        &self,
+        job: &BuildJob,
+        ctx: &BuildContext,
+    ) -> Result<(NimbleConfig, Image)> {
+        let log = &ctx.log;
         let source_archive_path = self.config.paths().source_archive(job.build_id);
         let build_dir = self.config.paths().build_dir(job.build_id);
 
@@ -103,70 +422,208 @@ impl BuildWorker {
             .with_context(|| format!("creating build directory {}", build_dir.display()))?;
 
         // Extract archive into build dir
+        log.line("==> Extracting source").await;
         self.extract_archive(&source_archive_path, &build_dir)
             .await
             .with_context(|| format!("extracting archive {}", source_archive_path.display()))?;
 
-        // Check for nimble.yaml file
+        // Load nimble.yaml, if the project has one
         let nimble_yaml_path = build_dir.join("nimble.yaml");
         let has_nimble_yaml = tokio::fs::try_exists(&nimble_yaml_path)
             .await
             .with_context(|| format!("checking for nimble.yaml in {}", build_dir.display()))?;
+        let cfg = if has_nimble_yaml {
+            NimbleConfig::from_file(nimble_yaml_path)?
+        } else {
+            NimbleConfig::default()
+        };
 
-        if !has_nimble_yaml {
-            // TODO: try auto-detecting the builder type
-
-            // Update status to Failed
-            let _ = self
-                .db
-                .update_build_status(job.build_id, BuildStatus::Failed)
-                .await;
-            anyhow::bail!(
-                "Cannot detect build type: nimble.yaml not found in build directory {}",
-                build_dir.display()
-            );
-        }
-
-        let cfg = NimbleConfig::from_file(nimble_yaml_path)?;
-        let builder = select_builder(cfg.builder_type);
+        // Use the builder from nimble.yaml, or work out which one fits
+        let (builder_config, reason) = match &cfg.builder {
+            Some(builder_config) => (builder_config.clone(), "set in nimble.yaml".to_string()),
+            None => {
+                let detection = detect_builder(&build_dir)?;
+                (BuilderConfig::new(detection.builder), detection.reason)
+            }
+        };
+        let builder_type = builder_config.builder_type();
+        self.db
+            .set_build_builder(job.build_id, &builder_type, &reason)
+            .await
+            .context("Failed to record build builder")?;
+        let builder = select_builder(&builder_config, &self.config.plugin_path());
+        let ctx = &BuildContext {
+            secrets: self.build_secrets(&job.app, &builder_config).await?,
+            ..ctx.clone()
+        };
 
         let image_name = format!("nimble-build-{}", job.build_id);
         let image_tag = "latest";
+        if ctx.cancel.is_cancelled() {
+            anyhow::bail!("build cancelled");
+        }
 
+        let cache = if ctx.no_cache { " without cache" } else { "" };
+        log.line(&format!(
+            "==> Building image with the {builder_type} builder ({reason}){cache}"
+        ))
+        .await;
         let image = builder
-            .build(&build_dir, &image_name, image_tag)
+            .build(&build_dir, &image_name, image_tag, ctx)
             .await
-            .with_context(|| {
-                format!(
-                    "failed to build image for build_id {} using builder {:?}",
-                    job.build_id, cfg.builder_type
-                )
-            })
-            .inspect_err(|_| {
-                // Update status to Failed on error
-                let db = self.db.clone();
-                let build_id = job.build_id;
-                tokio::spawn(async move {
-                    let _ = db.update_build_status(build_id, BuildStatus::Failed).await;
-                });
-            })?;
+            .with_context(|| format!("failed to build image using builder {builder_type}"))?;
 
-        info!(
-            build_id = %job.build_id,
-            image_reference = %image.reference,
-            image_digest = ?image.digest,
-            "Build completed successfully"
-        );
+        Ok((cfg, image))
+    }
 
-        // Update status to Success
-        self.db
-            .update_build_status(job.build_id, BuildStatus::Success)
+    /// Decrypts the app's secret env vars that the build uses as build
+    /// secrets. Other env vars are left out of the build.
+    async fn build_secrets(
+        &self,
+        app: &str,
+        builder_config: &BuilderConfig,
+    ) -> Result<BTreeMap<String, String>> {
+        match builder_config {
+            BuilderConfig::Dockerfile(config) if !config.build_secrets.is_empty() => {
+                let names = config.build_secrets.values().collect::<Vec<_>>();
+                self.app_secrets(app, &names).await
+            }
+            _ => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Decrypts the app's secret env vars with the given names. Names that
+    /// aren't set, or aren't secret, are left out.
+    async fn app_secrets(&self, app: &str, names: &[&String]) -> Result<BTreeMap<String, String>> {
+        let env = self
+            .db
+            .list_app_env(app)
             .await
-            .context("Failed to update build status to success")?;
+            .context("Failed to fetch app env vars")?;
 
-        // TODO: update image info in DB
+        let mut secrets = BTreeMap::new();
+        for var in env {
+            if var.secret && names.contains(&&var.name) {
+                let value = self
+                    .secrets
+                    .decrypt(&var.value)
+                    .with_context(|| format!("Failed to decrypt secret {}", var.name))?;
+                secrets.insert(var.name, value);
+            }
+        }
 
-        Ok(())
+        Ok(secrets)
+    }
+
+    /// Works out where to push the app's images: nimble.yaml's `registry`
+    /// settings, falling back to the agent's. Returns `None` if neither
+    /// names a registry.
+    fn registry_target(
+        &self,
+        app: &str,
+        registry: Option<&RegistryConfig>,
+    ) -> Option<RegistryTarget> {
+        let registry = registry.cloned().unwrap_or_default();
+        let url = registry
+            .url
+            .or_else(|| self.config.registry_url().map(str::to_string))?;
+        let repository =
+            registry
+                .repository
+                .unwrap_or_else(|| match self.config.registry_repository() {
+                    Some(namespace) => format!("{namespace}/{app}"),
+                    None => app.to_string(),
+                });
+
+        // The agent's credentials are only ever sent to the agent's registry
+        let credentials = match registry.credentials {
+            Some(name) => RegistryCredentials::Secret(name),
+            None => match self.config.registry_credentials() {
+                Some(path) if self.config.registry_url() == Some(url.as_str()) => {
+                    RegistryCredentials::File(path.to_path_buf())
+                }
+                _ => RegistryCredentials::None,
+            },
+        };
+
+        Some(RegistryTarget {
+            url,
+            repository,
+            credentials,
+        })
+    }
+
+    /// Loads the credentials for pushing to a registry.
+    async fn registry_auth(
+        &self,
+        app: &str,
+        target: &RegistryTarget,
+    ) -> Result<Option<RegistryAuth>> {
+        let (credentials, source) = match &target.credentials {
+            RegistryCredentials::None => return Ok(None),
+            RegistryCredentials::Secret(name) => {
+                let value = self
+                    .app_secrets(app, &[name])
+                    .await?
+                    .remove(name)
+                    .with_context(|| {
+                        format!(
+                            "Registry credentials need the secret env var {name}, which isn't set \
+                             on the app (set it with `nimble env set --secret`)"
+                        )
+                    })?;
+                (value, format!("secret env var {name}"))
+            }
+            RegistryCredentials::File(path) => {
+                let value = tokio::fs::read_to_string(path).await.with_context(|| {
+                    format!(
+                        "Failed to read registry credentials from {}",
+                        path.display()
+                    )
+                })?;
+                (value, path.display().to_string())
+            }
+        };
+
+        let auth = RegistryAuth::parse(&target.url, &credentials).with_context(|| {
+            format!("Registry credentials in {source} must be written as <username>:<password>")
+        })?;
+        Ok(Some(auth))
+    }
+
+    /// Tags the built image for the registry and pushes it there, if there's
+    /// a registry to push to. Returns the pushed image, with the registry's
+    /// digest, or else the local image unchanged.
+    async fn push_image(
+        &self,
+        job: &BuildJob,
+        cfg: &NimbleConfig,
+        image: Image,
+        ctx: &BuildContext,
+    ) -> Result<Image> {
+        let Some(target) = self.registry_target(&job.app, cfg.registry.as_ref()) else {
+            return Ok(image);
+        };
+        let reference = format!("{}/{}:{}", target.url, target.repository, job.build_id);
+        let auth = self.registry_auth(&job.app, &target).await?;
+
+        ctx.log
+            .line(&format!("==> Pushing image to {reference}"))
+            .await;
+        self.runtime
+            .tag(&image.reference, &reference)
+            .await
+            .context("Failed to tag image for the registry")?;
+        let digest = self
+            .runtime
+            .push(&reference, auth.as_ref(), ctx)
+            .await
+            .with_context(|| format!("failed to push image to {}", target.url))?;
+        ctx.log
+            .line(&format!("==> Pushed image {reference}@{digest}"))
+            .await;
+
+        Ok(Image::with_digest(reference, digest))
     }
 
     async fn extract_archive(&self, archive_path: &Path, extract_to: &Path) -> Result<()> {
@@ -212,6 +669,114 @@ impl BuildWorker {
     }
 }
 
+/// Recovers builds left over from the agent's previous run, which was
+/// stopped before it could finish them.
+///
+/// Queued builds are enqueued again, oldest first. Builds that were running
+/// are marked interrupted, or rebuilt if `retry_interrupted_builds` is set.
+/// Builds whose source archive is gone are marked failed.
+///
+/// The build worker must already be running, since this waits for room in
+/// the queue.
+pub async fn reconcile_builds(
+    config: &AgentConfig,
+    db: &Database,
+    build_queue: &Sender<BuildJob>,
+) -> Result<()> {
+    // Oldest first. Lists come back newest first, and created_at only has
+    // second precision, so reverse them and rely on the sort being stable.
+    let mut builds = db
+        .list_builds(None, Some(BuildStatus::Queued), None)
+        .await?;
+    builds.extend(
+        db.list_builds(None, Some(BuildStatus::Building), None)
+            .await?,
+    );
+    builds.reverse();
+    builds.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+    for build in builds {
+        let log = open_build_log(config, build.id).await;
+
+        let retry = build.status == BuildStatus::Queued || config.retry_interrupted_builds();
+        if !retry {
+            log.line("==> Build interrupted by an agent restart").await;
+            db.update_build_status(build.id, BuildStatus::Interrupted)
+                .await?;
+            info!(build_id = %build.id, "Marked interrupted build");
+            continue;
+        }
+
+        // Builds recorded before apps existed don't say which app they're for
+        let Some(app) = build.app else {
+            log.line("==> Build can't be resumed, it doesn't belong to an app")
+                .await;
+            db.update_build_status(build.id, BuildStatus::Failed)
+                .await?;
+            error!(build_id = %build.id, "Build has no app, can't resume it");
+            continue;
+        };
+
+        let archive = config.paths().source_archive(build.id);
+        let missing = match tokio::fs::try_exists(&archive).await {
+            Ok(true) => None,
+            Ok(false) => Some("its source archive is missing".to_string()),
+            Err(e) => Some(format!("its source archive can't be read: {e}")),
+        };
+        if let Some(reason) = missing {
+            log.line(&format!("==> Build can't be resumed, {reason}"))
+                .await;
+            db.update_build_status(build.id, BuildStatus::Failed)
+                .await?;
+            error!(
+                build_id = %build.id,
+                archive = %archive.display(),
+                reason,
+                "Source archive unavailable for build"
+            );
+            continue;
+        }
+
+        if build.status == BuildStatus::Building {
+            log.line("==> Retrying build after an agent restart").await;
+            db.update_build_status(build.id, BuildStatus::Queued)
+                .await?;
+        }
+
+        info!(build_id = %build.id, "Re-enqueuing build");
+        build_queue
+            .send(BuildJob {
+                build_id: build.id,
+                app,
+                no_cache: build.no_cache,
+            })
+            .await
+            .context("Failed to enqueue build job")?;
+    }
+
+    Ok(())
+}
+
+/// Opens a build's log file, creating its directory if the build hasn't
+/// started yet. Builds still run if it can't be created, they just go
+/// unlogged.
+async fn open_build_log(config: &AgentConfig, build_id: Uuid) -> BuildLog {
+    let path = config.paths().build_log(build_id);
+    let opened = match path.parent() {
+        Some(parent) => create_dir_all(parent).await,
+        None => Ok(()),
+    };
+
+    match opened {
+        Ok(()) => BuildLog::create(&path).await,
+        Err(e) => Err(e),
+    }
+    .unwrap_or_else(|e| {
+        error!(build_id = %build_id, error = %e, "Failed to create build log");
+        BuildLog::discard()
+    })
+}
+
 fn sanitize_tar_path(entry_path: &Path, base: &Path) -> Result<PathBuf> {
     let mut out = base.to_path_buf();
 
@@ -227,3 +792,443 @@ fn sanitize_tar_path(entry_path: &Path, base: &Path) -> Result<PathBuf> {
 
     Ok(out)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        db::EnvVar,
+        testing::TestEnv,
+        workers::deploy::{DeploymentStatus, ReleaseStatus},
+    };
+
+    const DOCKERFILE: (&str, &str) = ("Dockerfile", "FROM scratch\n");
+    const DEPLOYED: (&str, &str) = (
+        "nimble.yaml",
+        "builder: dockerfile\ndeploy:\n  target: local:docker\n  ports: [8080]\n",
+    );
+
+    async fn build(env: &TestEnv, build_id: Uuid) -> crate::db::BuildRecord {
+        env.db.get_build(build_id).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn successful_build_is_released_and_deployed() {
+        let env = TestEnv::new().await;
+        let (deploy_tx, mut deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        let job = env.queue_build("web", &[DOCKERFILE, DEPLOYED]).await;
+        let build_id = job.build_id;
+
+        worker.process_build(job).await.unwrap();
+
+        let record = build(&env, build_id).await;
+        assert_eq!(record.status, BuildStatus::Success);
+        assert_eq!(record.failure_reason, None);
+        assert!(record.image_size.is_some());
+        assert!(record.duration.is_some());
+        let image = record.image.unwrap();
+        assert_eq!(image.reference, format!("nimble-build-{build_id}:latest"));
+
+        env.deploy_queued(&mut deploy_rx).await;
+
+        let release = env.db.get_release("web", 1).await.unwrap().unwrap();
+        assert_eq!(release.build_id, build_id);
+        assert_eq!(release.status, ReleaseStatus::Active);
+        let deployments = env
+            .db
+            .list_deployments(Some(DeploymentStatus::Running), Some("web"))
+            .await
+            .unwrap();
+        assert_eq!(deployments.len(), 1);
+        let container = env
+            .runtime
+            .container(deployments[0].container_id.as_deref().unwrap())
+            .unwrap();
+        assert!(container.running);
+        assert_eq!(container.image, image.reference);
+    }
+
+    #[tokio::test]
+    async fn build_without_deploy_section_is_not_released() {
+        let env = TestEnv::new().await;
+        let (deploy_tx, mut deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        let job = env.queue_build("web", &[DOCKERFILE]).await;
+        let build_id = job.build_id;
+
+        worker.process_build(job).await.unwrap();
+
+        let record = build(&env, build_id).await;
+        assert_eq!(record.status, BuildStatus::Success);
+        assert_eq!(record.builder_reason.as_deref(), Some("found Dockerfile"));
+        assert!(deploy_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_build_records_build_failure() {
+        let env = TestEnv::new().await;
+        let (deploy_tx, mut deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        // Set to the dockerfile builder, but without a Dockerfile
+        let job = env.queue_build("web", &[DEPLOYED]).await;
+        let build_id = job.build_id;
+
+        worker.process_build(job).await.unwrap_err();
+
+        let record = build(&env, build_id).await;
+        assert_eq!(record.status, BuildStatus::Failed);
+        assert_eq!(record.failure_reason, Some(BuildFailure::Build));
+        assert_eq!(record.image, None);
+        assert!(deploy_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn running_build_can_be_cancelled() {
+        let env = TestEnv::new().await;
+        env.runtime.set_build_time(Duration::from_secs(60));
+        let (deploy_tx, mut deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        let job = env.queue_build("web", &[DOCKERFILE, DEPLOYED]).await;
+        let build_id = job.build_id;
+
+        let running = tokio::spawn({
+            let worker = worker.clone();
+            async move { worker.process_build(job).await }
+        });
+        while build(&env, build_id).await.status != BuildStatus::Building {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert!(worker.running_builds().cancel(build_id));
+        running.await.unwrap().unwrap();
+
+        assert_eq!(build(&env, build_id).await.status, BuildStatus::Cancelled);
+        assert!(!worker.running_builds().cancel(build_id));
+        assert!(deploy_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn settled_build_is_left_to_the_cancel_endpoint() {
+        let running = RunningBuilds::default();
+        let cancelled = Uuid::new_v4();
+        let token = running.start(cancelled);
+        assert!(running.cancel(cancelled));
+        assert!(!running.settle(cancelled, &token));
+
+        let settled = Uuid::new_v4();
+        let token = running.start(settled);
+        assert!(running.settle(settled, &token));
+        assert!(!running.cancel(settled));
+        assert!(!token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn build_removed_while_running_is_not_released() {
+        let env = TestEnv::new().await;
+        env.runtime.set_build_time(Duration::from_millis(200));
+        let (deploy_tx, mut deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        let job = env.queue_build("web", &[DOCKERFILE, DEPLOYED]).await;
+        let build_id = job.build_id;
+
+        let running = tokio::spawn({
+            let worker = worker.clone();
+            async move { worker.process_build(job).await }
+        });
+        while build(&env, build_id).await.status != BuildStatus::Building {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        env.db.delete_app("web").await.unwrap();
+        running.await.unwrap().unwrap();
+
+        assert!(env.db.get_build(build_id).await.unwrap().is_none());
+        assert!(deploy_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn build_cancelled_while_queued_is_skipped() {
+        let env = TestEnv::new().await;
+        let (deploy_tx, _deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        let job = env.queue_build("web", &[DOCKERFILE, DEPLOYED]).await;
+        let build_id = job.build_id;
+        env.db
+            .transition_build_status(build_id, BuildStatus::Queued, BuildStatus::Cancelled)
+            .await
+            .unwrap();
+
+        worker.process_build(job).await.unwrap();
+
+        let record = build(&env, build_id).await;
+        assert_eq!(record.status, BuildStatus::Cancelled);
+        assert_eq!(record.image, None);
+    }
+
+    #[tokio::test]
+    async fn reconcile_requeues_queued_builds_and_interrupts_running_ones() {
+        let env = TestEnv::new().await;
+        let queued = env.queue_build("web", &[DOCKERFILE]).await;
+        let running = env.queue_build("api", &[DOCKERFILE]).await;
+        env.db
+            .update_build_status(running.build_id, BuildStatus::Building)
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(4);
+        reconcile_builds(&env.config, &env.db, &tx).await.unwrap();
+
+        let job = rx.try_recv().unwrap();
+        assert_eq!(job.build_id, queued.build_id);
+        assert_eq!(job.app, "web");
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            build(&env, queued.build_id).await.status,
+            BuildStatus::Queued
+        );
+        assert_eq!(
+            build(&env, running.build_id).await.status,
+            BuildStatus::Interrupted
+        );
+    }
+
+    #[tokio::test]
+    async fn reconcile_retries_interrupted_builds_when_enabled() {
+        let env = TestEnv::with_config(|config| config.with_retry_interrupted_builds()).await;
+        let running = env.queue_build("web", &[DOCKERFILE]).await;
+        env.db
+            .update_build_status(running.build_id, BuildStatus::Building)
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(4);
+        reconcile_builds(&env.config, &env.db, &tx).await.unwrap();
+
+        assert_eq!(rx.try_recv().unwrap().build_id, running.build_id);
+        assert_eq!(
+            build(&env, running.build_id).await.status,
+            BuildStatus::Queued
+        );
+    }
+
+    #[tokio::test]
+    async fn reconcile_fails_builds_without_source_archive() {
+        let env = TestEnv::new().await;
+        let queued = env.queue_build("web", &[DOCKERFILE]).await;
+        std::fs::remove_file(env.config.paths().source_archive(queued.build_id)).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(4);
+        reconcile_builds(&env.config, &env.db, &tx).await.unwrap();
+
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            build(&env, queued.build_id).await.status,
+            BuildStatus::Failed
+        );
+        let log = std::fs::read_to_string(env.config.paths().build_log(queued.build_id)).unwrap();
+        assert!(log.contains("its source archive is missing"), "{log}");
+    }
+
+    #[tokio::test]
+    async fn reconcile_fails_builds_without_app() {
+        let env = TestEnv::new().await;
+        let queued = env.queue_build("web", &[DOCKERFILE]).await;
+        // As recorded before builds belonged to apps
+        let db = sqlx::SqlitePool::connect(&format!(
+            "sqlite:{}",
+            env.dir.path().join("nimble.db").display()
+        ))
+        .await
+        .unwrap();
+        sqlx::query("UPDATE builds SET app = NULL WHERE id = ?1")
+            .bind(queued.build_id.to_string())
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(4);
+        reconcile_builds(&env.config, &env.db, &tx).await.unwrap();
+
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            build(&env, queued.build_id).await.status,
+            BuildStatus::Failed
+        );
+        let log = std::fs::read_to_string(env.config.paths().build_log(queued.build_id)).unwrap();
+        assert!(log.contains("it doesn't belong to an app"), "{log}");
+    }
+
+    #[tokio::test]
+    async fn pushed_image_is_recorded_with_registry_digest() {
+        let env = TestEnv::with_config(|config| {
+            config.with_registry("localhost:5000", Some("team"), None)
+        })
+        .await;
+        let (deploy_tx, mut deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        let job = env.queue_build("web", &[DOCKERFILE, DEPLOYED]).await;
+        let build_id = job.build_id;
+
+        worker.process_build(job).await.unwrap();
+
+        let reference = format!("localhost:5000/team/web:{build_id}");
+        let image = build(&env, build_id).await.image.unwrap();
+        assert_eq!(image.reference, reference);
+        assert_eq!(image.digest, env.runtime.pushed(&reference));
+        assert!(image.digest.is_some());
+
+        // The release runs the pushed image
+        env.deploy_queued(&mut deploy_rx).await;
+        let release = env.db.get_release("web", 1).await.unwrap().unwrap();
+        assert_eq!(release.image, reference);
+    }
+
+    #[tokio::test]
+    async fn nimble_yaml_registry_overrides_agent_registry() {
+        let env = TestEnv::with_config(|config| {
+            config.with_registry("localhost:5000", Some("team"), None)
+        })
+        .await;
+        let (deploy_tx, _deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        let config = (
+            "nimble.yaml",
+            "builder: dockerfile\nregistry:\n  url: registry.example.com\n  repository: apps/web\n",
+        );
+        let job = env.queue_build("web", &[DOCKERFILE, config]).await;
+        let build_id = job.build_id;
+
+        worker.process_build(job).await.unwrap();
+
+        let reference = format!("registry.example.com/apps/web:{build_id}");
+        assert_eq!(
+            build(&env, build_id).await.image.unwrap().reference,
+            reference
+        );
+        assert!(env.runtime.pushed(&reference).is_some());
+    }
+
+    #[tokio::test]
+    async fn registry_without_namespace_uses_app_name() {
+        let env =
+            TestEnv::with_config(|config| config.with_registry("localhost:5000", None, None)).await;
+        let (deploy_tx, _deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+
+        let target = worker.registry_target("web", None).unwrap();
+        assert_eq!(target.url, "localhost:5000");
+        assert_eq!(target.repository, "web");
+
+        // Without a registry, nothing is pushed
+        let env = TestEnv::new().await;
+        let (deploy_tx, _deploy_rx) = mpsc::channel(4);
+        assert!(
+            env.build_worker(deploy_tx)
+                .registry_target("web", None)
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn registry_credentials_file_is_only_sent_to_agent_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let credentials = dir.path().join("registry-credentials");
+        std::fs::write(&credentials, "deploy:hunter2\n").unwrap();
+        let env = TestEnv::with_config(|config| {
+            config.with_registry("localhost:5000", None, Some(&credentials))
+        })
+        .await;
+        let (deploy_tx, _deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+
+        let target = worker.registry_target("web", None).unwrap();
+        let auth = worker.registry_auth("web", &target).await.unwrap().unwrap();
+        assert_eq!(auth.server, "localhost:5000");
+        assert_eq!(auth.username, "deploy");
+        assert_eq!(auth.password, "hunter2");
+
+        let elsewhere = RegistryConfig {
+            url: Some("registry.example.com".to_string()),
+            ..Default::default()
+        };
+        let target = worker.registry_target("web", Some(&elsewhere)).unwrap();
+        assert_eq!(worker.registry_auth("web", &target).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn registry_credentials_are_read_from_app_secret() {
+        let env = TestEnv::new().await;
+        let (deploy_tx, _deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        env.db.create_app("web").await.unwrap();
+        let secret = EnvVar {
+            name: "REGISTRY_AUTH".to_string(),
+            value: env.secrets.encrypt("deploy:hunter2").unwrap(),
+            secret: true,
+        };
+        env.db.update_app_env("web", &[secret], &[]).await.unwrap();
+
+        let registry = RegistryConfig {
+            url: Some("registry.example.com".to_string()),
+            credentials: Some("REGISTRY_AUTH".to_string()),
+            ..Default::default()
+        };
+        let target = worker.registry_target("web", Some(&registry)).unwrap();
+        let auth = worker.registry_auth("web", &target).await.unwrap().unwrap();
+        assert_eq!(auth.server, "registry.example.com");
+        assert_eq!(auth.username, "deploy");
+    }
+
+    #[tokio::test]
+    async fn push_failure_records_push_failure() {
+        let env = TestEnv::new().await;
+        let (deploy_tx, mut deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        // The credentials secret isn't set, so the push can't go ahead
+        let config = (
+            "nimble.yaml",
+            "builder: dockerfile\nregistry:\n  url: localhost:5000\n  credentials: REGISTRY_AUTH\n",
+        );
+        let job = env.queue_build("web", &[DOCKERFILE, config]).await;
+        let build_id = job.build_id;
+
+        worker.process_build(job).await.unwrap_err();
+
+        let record = build(&env, build_id).await;
+        assert_eq!(record.status, BuildStatus::Failed);
+        assert_eq!(record.failure_reason, Some(BuildFailure::Push));
+        assert_eq!(record.image, None);
+        assert!(deploy_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn malformed_registry_credentials_file_fails_push() {
+        let dir = tempfile::tempdir().unwrap();
+        let credentials = dir.path().join("registry-credentials");
+        std::fs::write(&credentials, "hunter2\n").unwrap();
+        let env = TestEnv::with_config(|config| {
+            config.with_registry("localhost:5000", None, Some(&credentials))
+        })
+        .await;
+        let (deploy_tx, _deploy_rx) = mpsc::channel(4);
+        let worker = env.build_worker(deploy_tx);
+        let job = env.queue_build("web", &[DOCKERFILE]).await;
+        let build_id = job.build_id;
+
+        let err = worker.process_build(job).await.unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("must be written as <username>:<password>"),
+            "{err:#}"
+        );
+        assert_eq!(
+            build(&env, build_id).await.failure_reason,
+            Some(BuildFailure::Push)
+        );
+    }
+}
@@ -0,0 +1,594 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use anyhow::{Context, Result};
+use nimble_core::{
+    config::DeployConfig,
+    deployers::{
+        Container, Deployer,
+        health::{probe_port, wait_until_healthy},
+        select_deployer,
+    },
+    runtime::ContainerRuntime,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Receiver;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    db::{Database, DeploymentRecord, ReleaseRecord},
+    proxy::routes::Routes,
+    secrets::Secrets,
+};
+
+pub struct DeployJob {
+    /// Name of the app to deploy
+    pub app: String,
+    /// Version of the app's release to deploy
+    pub version: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseStatus {
+    /// Waiting to be deployed
+    Pending,
+    /// Currently live
+    Active,
+    /// Was live, but has been replaced by a newer release
+    Superseded,
+    /// Could not be deployed
+    Failed,
+}
+
+impl ReleaseStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReleaseStatus::Pending => "pending",
+            ReleaseStatus::Active => "active",
+            ReleaseStatus::Superseded => "superseded",
+            ReleaseStatus::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for ReleaseStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ReleaseStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(ReleaseStatus::Pending),
+            "active" => Ok(ReleaseStatus::Active),
+            "superseded" => Ok(ReleaseStatus::Superseded),
+            "failed" => Ok(ReleaseStatus::Failed),
+            _ => Err(format!("Unknown release status: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeploymentStatus {
+    Deploying,
+    Running,
+    Stopped,
+    Failed,
+}
+
+impl DeploymentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeploymentStatus::Deploying => "deploying",
+            DeploymentStatus::Running => "running",
+            DeploymentStatus::Stopped => "stopped",
+            DeploymentStatus::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for DeploymentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DeploymentStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "deploying" => Ok(DeploymentStatus::Deploying),
+            "running" => Ok(DeploymentStatus::Running),
+            "stopped" => Ok(DeploymentStatus::Stopped),
+            "failed" => Ok(DeploymentStatus::Failed),
+            _ => Err(format!("Unknown deployment status: {s}")),
+        }
+    }
+}
+
+pub struct DeployWorker {
+    db: Database,
+    routes: Routes,
+    secrets: Secrets,
+    runtime: Arc<dyn ContainerRuntime>,
+}
+
+impl DeployWorker {
+    pub fn new(
+        db: Database,
+        routes: Routes,
+        secrets: Secrets,
+        runtime: Arc<dyn ContainerRuntime>,
+    ) -> Self {
+        Self {
+            db,
+            routes,
+            secrets,
+            runtime,
+        }
+    }
+
+    /// Runs the deploy worker, processing deploy jobs from the channel.
+    pub async fn run(&self, mut deploy_queue: Receiver<DeployJob>) -> Result<()> {
+        info!("Deploy worker started");
+
+        while let Some(job) = deploy_queue.recv().await {
+            let (app, version) = (job.app.clone(), job.version);
+            info!(app = %app, version, "Processing deploy job");
+
+            if let Err(e) = self.process_deploy(job).await {
+                if matches!(self.db.get_app(&app).await, Ok(None)) {
+                    // Its containers were removed along with it
+                    info!(app = %app, version, error = %e, "Abandoned deploy of deleted app");
+                    continue;
+                }
+                error!(app = %app, version, error = %e, "Deploy failed");
+                let _ = self
+                    .db
+                    .update_release_status(&app, version, ReleaseStatus::Failed)
+                    .await;
+                // Continue processing other jobs even if one fails
+            }
+        }
+
+        info!("Deploy worker stopped (channel closed)");
+        Ok(())
+    }
+
+    /// Deploys a release with a blue/green replacement: the new container
+    /// starts next to the app's current one, and only replaces it once it
+    /// passes its health check.
+    async fn process_deploy(&self, job: DeployJob) -> Result<()> {
+        let Some(release) = self
+            .db
+            .get_release(&job.app, job.version)
+            .await
+            .context("Failed to fetch release")?
+        else {
+            // Jobs queued before their app was deleted are dropped
+            if self.db.get_app(&job.app).await?.is_none() {
+                info!(app = %job.app, version = job.version, "Skipping deploy of deleted app");
+                return Ok(());
+            }
+            anyhow::bail!("release v{} of app {} not found", job.version, job.app);
+        };
+
+        let deployer = select_deployer(release.config.target, Arc::clone(&self.runtime));
+
+        // The app's env vars take precedence over the env in nimble.yaml
+        let mut config = release.config.clone();
+        for var in &release.env {
+            let value = if var.secret {
+                self.secrets
+                    .decrypt(&var.value)
+                    .with_context(|| format!("Failed to decrypt secret {}", var.name))?
+            } else {
+                var.value.clone()
+            };
+            config.env.insert(var.name.clone(), value);
+        }
+
+        let deployment_id = Uuid::new_v4();
+        self.db
+            .create_deployment(
+                deployment_id,
+                &release,
+                release.config.target,
+                &release.image,
+            )
+            .await
+            .context("Failed to record deployment")?;
+
+        let previous = self
+            .db
+            .list_deployments(Some(DeploymentStatus::Running), Some(&release.app))
+            .await
+            .context("Failed to fetch running deployments")?;
+
+        // A fixed host port can only be bound by one container at a time, so
+        // the old containers are stopped while the new one starts, and
+        // started again if it doesn't come up healthy.
+        let mut paused = Vec::new();
+        if release.config.ports.iter().any(|p| p.host.is_some()) {
+            warn!(
+                app = %release.app,
+                "Deploy config uses fixed host ports, stopping previous container before starting the new one"
+            );
+            for old in &previous {
+                let Some(container_id) = &old.container_id else {
+                    continue;
+                };
+                info!(deployment_id = %old.id, container_id = %container_id, "Stopping previous container");
+                match deployer.stop(container_id).await {
+                    Ok(()) => paused.push(old),
+                    Err(e) => {
+                        warn!(deployment_id = %old.id, error = %e, "Failed to stop previous container")
+                    }
+                }
+            }
+        }
+
+        let container = match self
+            .start_release(deployer.as_ref(), &release, &config, deployment_id)
+            .await
+        {
+            Ok(container) => container,
+            Err(e) => {
+                let _ = self
+                    .db
+                    .update_deployment_status(deployment_id, DeploymentStatus::Failed)
+                    .await;
+                self.restart_deployments(deployer.as_ref(), paused).await;
+                // Route to the previous containers again, in case their
+                // ports changed
+                if let Err(e) = self.routes.reload().await {
+                    warn!(app = %release.app, error = %e, "Failed to reload proxy routes");
+                }
+                return Err(e);
+            }
+        };
+
+        // Switch over to the new container, unless the app was deleted while
+        // it was starting
+        let switched = self
+            .db
+            .transition_deployment_status(
+                deployment_id,
+                DeploymentStatus::Deploying,
+                DeploymentStatus::Running,
+            )
+            .await
+            .context("Failed to update deployment status to running")?;
+        if !switched {
+            if let Err(e) = deployer.remove(&container.id).await {
+                warn!(deployment_id = %deployment_id, error = %e, "Failed to remove container of deleted app");
+            }
+            anyhow::bail!("app {} was deleted during the deploy", release.app);
+        }
+
+        self.db
+            .activate_release(&release.app, release.version)
+            .await
+            .context("Failed to activate release")?;
+
+        if let Err(e) = self.routes.reload().await {
+            warn!(app = %release.app, error = %e, "Failed to reload proxy routes");
+        }
+
+        // The new release is live, so failing to clean up after the old ones
+        // doesn't fail the deploy
+        self.stop_deployments(deployer.as_ref(), previous).await;
+
+        info!(
+            app = %release.app,
+            version = release.version,
+            deployment_id = %deployment_id,
+            container_id = %container.id,
+            "Deploy completed successfully"
+        );
+
+        Ok(())
+    }
+
+    /// Starts the container for a release and waits for it to pass its
+    /// health check. An unhealthy container is removed again.
+    async fn start_release(
+        &self,
+        deployer: &dyn Deployer,
+        release: &ReleaseRecord,
+        config: &DeployConfig,
+        deployment_id: Uuid,
+    ) -> Result<Container> {
+        let container_name = container_name(&release.app, deployment_id);
+        let container = deployer
+            .deploy(&release.image, &container_name, config)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to start container for release v{} of app {}",
+                    release.version, release.app
+                )
+            })?;
+
+        let recorded = self
+            .db
+            .set_deployment_container(deployment_id, &container)
+            .await
+            .context("Failed to record deployment container")?;
+        if !recorded {
+            // Nothing would remove the container once the app is gone
+            if let Err(e) = deployer.remove(&container.id).await {
+                warn!(deployment_id = %deployment_id, error = %e, "Failed to remove container of deleted app");
+            }
+            anyhow::bail!("app {} was deleted during the deploy", release.app);
+        }
+
+        if let Some(check) = &release.config.healthcheck {
+            info!(deployment_id = %deployment_id, container_id = %container.id, "Waiting for new container to become healthy");
+
+            let healthy = match probe_port(check, &container) {
+                Ok(port) => wait_until_healthy(check, port).await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = healthy {
+                if let Err(e) = deployer.remove(&container.id).await {
+                    warn!(deployment_id = %deployment_id, error = %e, "Failed to remove unhealthy container");
+                }
+                return Err(e).with_context(|| {
+                    format!(
+                        "release v{} of app {} failed its health check",
+                        release.version, release.app
+                    )
+                });
+            }
+        }
+
+        Ok(container)
+    }
+
+    /// Starts the containers of previous deployments that were stopped to
+    /// make way for a release that then failed, so they serve the app again.
+    async fn restart_deployments(
+        &self,
+        deployer: &dyn Deployer,
+        deployments: Vec<&DeploymentRecord>,
+    ) {
+        for old in deployments {
+            let Some(container_id) = &old.container_id else {
+                continue;
+            };
+            info!(deployment_id = %old.id, container_id = %container_id, "Restarting previous container");
+
+            let restarted = match deployer.start(container_id).await {
+                Ok(ports) => {
+                    let container = Container {
+                        id: container_id.clone(),
+                        name: container_name(old.app.as_deref().unwrap_or_default(), old.id),
+                        ports,
+                    };
+                    // The ports can change if some weren't fixed
+                    self.db
+                        .set_deployment_container(old.id, &container)
+                        .await
+                        .map(|_| ())
+                }
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = restarted {
+                error!(deployment_id = %old.id, error = %e, "Failed to restart previous container");
+                let _ = self
+                    .db
+                    .update_deployment_status(old.id, DeploymentStatus::Failed)
+                    .await;
+            }
+        }
+    }
+
+    /// Removes the containers of previous deployments and marks them stopped.
+    /// Failures are only logged, since the app has moved on to a new
+    /// deployment by then.
+    async fn stop_deployments(&self, deployer: &dyn Deployer, deployments: Vec<DeploymentRecord>) {
+        for old in deployments {
+            if let Some(container_id) = &old.container_id {
+                info!(deployment_id = %old.id, container_id = %container_id, "Removing previous container");
+                if let Err(e) = deployer.remove(container_id).await {
+                    warn!(deployment_id = %old.id, error = %e, "Failed to remove previous container");
+                }
+            }
+            if let Err(e) = self
+                .db
+                .update_deployment_status(old.id, DeploymentStatus::Stopped)
+                .await
+            {
+                warn!(deployment_id = %old.id, error = %e, "Failed to mark previous deployment stopped");
+            }
+        }
+    }
+}
+
+/// Returns the name of a deployment's container.
+fn container_name(app: &str, deployment_id: Uuid) -> String {
+    format!("nimble-{app}-{deployment_id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestEnv;
+
+    const FIXED_PORT: &str = "target: local:docker\nports:\n  - 18080:8080\n";
+
+    /// A health check that can never pass, since it probes a port the
+    /// container doesn't publish
+    const UNHEALTHY: &str = "healthcheck:\n  type: tcp\n  port: 9999\n  retries: 1\n";
+
+    async fn running(env: &TestEnv, app: &str) -> Vec<DeploymentRecord> {
+        env.db
+            .list_deployments(Some(DeploymentStatus::Running), Some(app))
+            .await
+            .unwrap()
+    }
+
+    async fn release_status(env: &TestEnv, app: &str, version: i64) -> ReleaseStatus {
+        env.db
+            .get_release(app, version)
+            .await
+            .unwrap()
+            .unwrap()
+            .status
+    }
+
+    #[tokio::test]
+    async fn replaces_previous_deployment() {
+        let env = TestEnv::new().await;
+        let v1 = env
+            .release("web", "target: local:docker\nports: [8080]")
+            .await;
+        env.deploy("web", &[v1]).await;
+        let old = running(&env, "web").await.remove(0);
+
+        let v2 = env
+            .release("web", "target: local:docker\nports: [8080]")
+            .await;
+        env.deploy("web", &[v2]).await;
+
+        assert_eq!(
+            release_status(&env, "web", v1).await,
+            ReleaseStatus::Superseded
+        );
+        assert_eq!(release_status(&env, "web", v2).await, ReleaseStatus::Active);
+        let current = running(&env, "web").await;
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].release_version, Some(v2));
+        assert!(
+            env.runtime
+                .container(old.container_id.as_deref().unwrap())
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_health_check_keeps_previous_deployment_running() {
+        let env = TestEnv::new().await;
+        let v1 = env
+            .release("web", "target: local:docker\nports: [8080]")
+            .await;
+        env.deploy("web", &[v1]).await;
+
+        let v2 = env
+            .release(
+                "web",
+                &format!("target: local:docker\nports: [8080]\n{UNHEALTHY}"),
+            )
+            .await;
+        env.deploy("web", &[v2]).await;
+
+        assert_eq!(release_status(&env, "web", v1).await, ReleaseStatus::Active);
+        assert_eq!(release_status(&env, "web", v2).await, ReleaseStatus::Failed);
+        let current = running(&env, "web").await;
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].release_version, Some(v1));
+    }
+
+    #[tokio::test]
+    async fn failed_health_check_with_fixed_host_port_restarts_previous_container() {
+        let env = TestEnv::new().await;
+        let v1 = env.release("web", FIXED_PORT).await;
+        env.deploy("web", &[v1]).await;
+        let old = running(&env, "web").await.remove(0);
+
+        let v2 = env
+            .release("web", &format!("{FIXED_PORT}{UNHEALTHY}"))
+            .await;
+        env.deploy("web", &[v2]).await;
+
+        assert_eq!(release_status(&env, "web", v1).await, ReleaseStatus::Active);
+        assert_eq!(release_status(&env, "web", v2).await, ReleaseStatus::Failed);
+
+        let current = running(&env, "web").await;
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].id, old.id);
+        let container = env
+            .runtime
+            .container(old.container_id.as_deref().unwrap())
+            .unwrap();
+        assert!(container.running);
+
+        // The proxy still routes to the previous container
+        let routes = env.routes.current();
+        let backend = routes.resolve("web.localhost", "/").unwrap();
+        assert_eq!(backend.port, 18080);
+    }
+
+    #[tokio::test]
+    async fn fixed_host_port_is_handed_over_to_new_container() {
+        let env = TestEnv::new().await;
+        let v1 = env.release("web", FIXED_PORT).await;
+        let v2 = env.release("web", FIXED_PORT).await;
+        env.deploy("web", &[v1, v2]).await;
+
+        assert_eq!(release_status(&env, "web", v2).await, ReleaseStatus::Active);
+        let current = running(&env, "web").await;
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].release_version, Some(v2));
+    }
+
+    #[tokio::test]
+    async fn cleanup_failure_after_switch_over_keeps_release_active() {
+        let env = TestEnv::new().await;
+        let v1 = env.release("web", "target: local:docker").await;
+        env.deploy("web", &[v1]).await;
+
+        // Remove the old container behind the agent's back, so removing it
+        // during the switch over fails
+        let old = running(&env, "web").await.remove(0);
+        let old_container = old.container_id.as_deref().unwrap();
+        env.runtime.stop(old_container).await.unwrap();
+        env.runtime.remove(old_container).await.unwrap();
+
+        let v2 = env.release("web", "target: local:docker").await;
+        env.deploy("web", &[v2]).await;
+
+        assert_eq!(
+            release_status(&env, "web", v1).await,
+            ReleaseStatus::Superseded
+        );
+        assert_eq!(release_status(&env, "web", v2).await, ReleaseStatus::Active);
+        let old_status = env
+            .db
+            .list_deployments(Some(DeploymentStatus::Stopped), Some("web"))
+            .await
+            .unwrap();
+        assert_eq!(old_status.len(), 1);
+        assert_eq!(old_status[0].id, old.id);
+    }
+
+    #[tokio::test]
+    async fn skips_deploy_of_deleted_app() {
+        let env = TestEnv::new().await;
+        let version = env.release("web", "target: local:docker").await;
+        env.db.delete_app("web").await.unwrap();
+
+        let job = DeployJob {
+            app: "web".to_string(),
+            version,
+        };
+        env.deploy_worker().process_deploy(job).await.unwrap();
+
+        assert!(
+            env.db
+                .list_deployments(None, Some("web"))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
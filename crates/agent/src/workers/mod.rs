@@ -1 +1,3 @@
 pub mod build;
+pub mod deploy;
+pub mod pool;
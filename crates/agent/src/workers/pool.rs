@@ -0,0 +1,191 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use tokio::sync::{Notify, mpsc::Receiver};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::workers::build::{BuildJob, BuildWorker, RunningBuilds};
+
+/// What a build worker is currently doing.
+#[derive(Debug, Clone)]
+pub struct WorkerStatus {
+    pub id: usize,
+    /// The build the worker is running, if any
+    pub build: Option<RunningBuild>,
+    /// How many times the worker has been restarted after panicking
+    pub restarts: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct RunningBuild {
+    pub build_id: Uuid,
+    pub app: String,
+}
+
+/// Builds waiting for a worker.
+#[derive(Default)]
+struct Pending {
+    jobs: VecDeque<BuildJob>,
+    /// Apps that a worker is currently building
+    busy_apps: HashSet<String>,
+    /// Set once the build queue has closed
+    closed: bool,
+}
+
+/// BuildPool runs builds on a fixed number of workers.
+///
+/// Builds of different apps run in parallel, but builds of the same app run
+/// one at a time in the order they were queued, so that an older build can
+/// never deploy over a newer one. Each worker is supervised and restarted if
+/// it panics.
+#[derive(Clone)]
+pub struct BuildPool {
+    pending: Arc<Mutex<Pending>>,
+    /// Notified when a job is queued or an app's build finishes
+    changed: Arc<Notify>,
+    workers: Arc<Mutex<Vec<WorkerStatus>>>,
+    running: RunningBuilds,
+}
+
+impl BuildPool {
+    /// Starts `size` workers taking jobs from the build queue.
+    pub fn start(worker: BuildWorker, size: usize, mut queue: Receiver<BuildJob>) -> Self {
+        let pool = Self {
+            pending: Arc::default(),
+            changed: Arc::default(),
+            workers: Arc::new(Mutex::new(
+                (0..size)
+                    .map(|id| WorkerStatus {
+                        id,
+                        build: None,
+                        restarts: 0,
+                    })
+                    .collect(),
+            )),
+            running: worker.running_builds(),
+        };
+
+        // Move jobs from the queue to the pending list as they arrive
+        let dispatcher = pool.clone();
+        tokio::spawn(async move {
+            while let Some(job) = queue.recv().await {
+                lock(&dispatcher.pending).jobs.push_back(job);
+                dispatcher.changed.notify_waiters();
+            }
+            lock(&dispatcher.pending).closed = true;
+            dispatcher.changed.notify_waiters();
+        });
+
+        for id in 0..size {
+            tokio::spawn(pool.clone().supervise(id, worker.clone()));
+        }
+
+        info!(workers = size, "Build pool started");
+        pool
+    }
+
+    /// Returns the status of each worker.
+    pub fn workers(&self) -> Vec<WorkerStatus> {
+        lock(&self.workers).clone()
+    }
+
+    /// Cancels a running build. Returns false if the build isn't running.
+    pub fn cancel(&self, build_id: Uuid) -> bool {
+        self.running.cancel(build_id)
+    }
+
+    /// Drops an app's builds that are still waiting for a worker. Returns
+    /// how many were dropped.
+    pub fn purge_app(&self, app: &str) -> usize {
+        let mut pending = lock(&self.pending);
+        let before = pending.jobs.len();
+        pending.jobs.retain(|job| job.app != app);
+        before - pending.jobs.len()
+    }
+
+    /// Runs a worker, restarting it whenever it panics.
+    async fn supervise(self, id: usize, worker: BuildWorker) {
+        loop {
+            let pool = self.clone();
+            let run = worker.clone();
+            let result = tokio::spawn(async move { run.run(id, &pool).await }).await;
+
+            match result {
+                Ok(()) => return,
+                Err(e) if e.is_panic() => {
+                    error!(worker = id, "Build worker panicked, restarting it");
+
+                    let build = {
+                        let mut workers = lock(&self.workers);
+                        workers[id].restarts += 1;
+                        workers[id].build.take()
+                    };
+                    if let Some(build) = build {
+                        worker.abandon(build.build_id).await;
+                        self.release_app(&build.app);
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Waits for the next job that worker `id` may run, and marks its app as
+    /// busy. Returns `None` once the queue has closed and drained.
+    pub(crate) async fn next_job(&self, id: usize) -> Option<BuildJob> {
+        loop {
+            // Register for notifications before checking, so that a change
+            // made in between isn't missed
+            let notified = self.changed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut guard = lock(&self.pending);
+                let pending = &mut *guard;
+
+                // The first pending job of each app is its oldest
+                let next = pending
+                    .jobs
+                    .iter()
+                    .position(|job| !pending.busy_apps.contains(&job.app));
+                if let Some(job) = next.and_then(|pos| pending.jobs.remove(pos)) {
+                    pending.busy_apps.insert(job.app.clone());
+                    lock(&self.workers)[id].build = Some(RunningBuild {
+                        build_id: job.build_id,
+                        app: job.app.clone(),
+                    });
+                    return Some(job);
+                }
+
+                if pending.closed && pending.jobs.is_empty() {
+                    return None;
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Marks worker `id`'s current job as done, letting the next build of its
+    /// app start.
+    pub(crate) fn finish_job(&self, id: usize) {
+        // Don't hold the workers lock while taking the pending lock
+        let build = lock(&self.workers)[id].build.take();
+        if let Some(build) = build {
+            self.release_app(&build.app);
+        }
+    }
+
+    fn release_app(&self, app: &str) {
+        lock(&self.pending).busy_apps.remove(app);
+        self.changed.notify_waiters();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
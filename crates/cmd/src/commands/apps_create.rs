@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::types::{AppResponse, ErrorResponse};
+
+#[derive(Args, Debug)]
+pub struct AppsCreateArgs {
+    /// Name of the app to create
+    pub name: String,
+}
+
+pub async fn execute(agent_url: &str, args: &AppsCreateArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{agent_url}/apps");
+
+    let response = client
+        .post(&url)
+        .json(&json!({ "name": args.name }))
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let app: AppResponse = response.json().await.context("Failed to parse response")?;
+        println!("App created: {}", app.name);
+    } else if status == StatusCode::CONFLICT {
+        anyhow::bail!("App already exists: {}", args.name);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to create app: {}", error.error);
+    }
+
+    Ok(())
+}
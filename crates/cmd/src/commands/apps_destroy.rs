@@ -0,0 +1,57 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+
+use crate::types::ErrorResponse;
+
+#[derive(Args, Debug)]
+pub struct AppsDestroyArgs {
+    /// Name of the app to destroy
+    pub name: String,
+    /// Skip the confirmation prompt
+    #[arg(long)]
+    pub yes: bool,
+}
+
+pub async fn execute(agent_url: &str, args: &AppsDestroyArgs) -> Result<()> {
+    if !args.yes && !confirm(&args.name)? {
+        anyhow::bail!("Aborted: confirmation did not match app name");
+    }
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/apps/{}", agent_url, args.name);
+
+    let response = client
+        .delete(&url)
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        println!("App destroyed: {}", args.name);
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("App not found: {}", args.name);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to destroy app: {}", error.error);
+    }
+
+    Ok(())
+}
+
+/// Asks the user to type the app name to confirm destroying it.
+fn confirm(name: &str) -> Result<bool> {
+    println!("This will stop {name} and delete all of its builds and deployments.");
+    print!("Type the app name to confirm: ");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().lock().read_line(&mut input)?;
+    Ok(input.trim() == name)
+}
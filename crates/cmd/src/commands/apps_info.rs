@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+
+use crate::types::{AppInfoResponse, ErrorResponse};
+
+#[derive(Args, Debug)]
+pub struct AppsInfoArgs {
+    /// Name of the app
+    pub name: String,
+}
+
+pub async fn execute(agent_url: &str, args: &AppsInfoArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/apps/{}", agent_url, args.name);
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let info: AppInfoResponse = response.json().await.context("Failed to parse response")?;
+
+        println!("App Details:");
+        println!("  Name:     {}", info.app.name);
+        println!("  Created:  {}", info.app.created_at);
+        println!("  Updated:  {}", info.app.updated_at);
+
+        match info.deployment {
+            Some(deployment) => {
+                println!("Current Deployment:");
+                println!("  ID:         {}", deployment.id);
+                if let Some(release) = deployment.release {
+                    println!("  Release:    v{release}");
+                }
+                println!("  Build:      {}", deployment.build_id);
+                println!("  Image:      {}", deployment.image);
+                println!(
+                    "  Container:  {}",
+                    deployment.container_id.as_deref().unwrap_or("-")
+                );
+                for port in &deployment.ports {
+                    println!(
+                        "  Port:       {}/{} -> {}",
+                        port.container,
+                        port.protocol.as_str(),
+                        port.host
+                    );
+                }
+                println!("  Status:     {}", deployment.status);
+                println!("  Deployed:   {}", deployment.updated_at);
+            }
+            None => println!("Not deployed."),
+        }
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("App not found: {}", args.name);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to get app: {}", error.error);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::types::{AppResponse, ErrorResponse};
+
+#[derive(Args, Debug)]
+pub struct AppsListArgs {}
+
+pub async fn execute(agent_url: &str, _args: &AppsListArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{agent_url}/apps");
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let apps: Vec<AppResponse> = response.json().await.context("Failed to parse response")?;
+
+        if apps.is_empty() {
+            println!("No apps found.");
+        } else {
+            println!("{:<30} {:<20} {:<20}", "NAME", "CREATED", "UPDATED");
+            println!("{}", "-".repeat(72));
+
+            for app in apps {
+                println!(
+                    "{:<30} {:<20} {:<20}",
+                    app.name, app.created_at, app.updated_at
+                );
+            }
+        }
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to list apps: {}", error.error);
+    }
+
+    Ok(())
+}
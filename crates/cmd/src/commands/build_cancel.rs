@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+
+use crate::types::{BuildResponse, ErrorResponse};
+
+#[derive(Args, Debug)]
+pub struct BuildCancelArgs {
+    /// Build ID to cancel
+    pub id: String,
+}
+
+pub async fn execute(agent_url: &str, args: &BuildCancelArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/builds/{}/cancel", agent_url, args.id);
+
+    let response = client
+        .post(&url)
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status == StatusCode::ACCEPTED {
+        println!("Cancelling build {}", args.id);
+    } else if status.is_success() {
+        let build: BuildResponse = response.json().await.context("Failed to parse response")?;
+        println!("Build {} cancelled", build.id);
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("Build not found: {}", args.id);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to cancel build: {}", error.error);
+    }
+
+    Ok(())
+}
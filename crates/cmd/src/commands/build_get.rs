@@ -27,7 +27,37 @@ pub async fn execute(agent_url: &str, args: &BuildGetArgs) -> Result<()> {
 
         println!("Build Details:");
         println!("  ID:       {}", build.id);
+        println!("  App:      {}", build.app.as_deref().unwrap_or("-"));
         println!("  Status:   {}", build.status);
+        match build.failure_reason.as_deref() {
+            Some("push") => {
+                println!("  Failure:  push (the image couldn't be pushed to the registry)")
+            }
+            Some(reason) => println!("  Failure:  {reason}"),
+            None => {}
+        }
+        if let Some(builder) = &build.builder {
+            match &build.builder_reason {
+                Some(reason) => println!("  Builder:  {builder} ({reason})"),
+                None => println!("  Builder:  {builder}"),
+            }
+        }
+        if let Some(image) = &build.image {
+            println!("  Image:    {}", image.reference);
+            if let Some(digest) = &image.digest {
+                println!("  Digest:   {digest}");
+            }
+            if let Some(size) = image.size {
+                println!("  Size:     {}", format_size(size));
+            }
+        }
+        if let Some(ms) = build.duration_ms {
+            println!("  Duration: {}", format_duration(ms));
+        }
+        if let Some(source) = &build.rebuild_of {
+            let cache = if build.no_cache { " (no cache)" } else { "" };
+            println!("  Rebuild:  of {source}{cache}");
+        }
         println!("  Created:  {}", build.created_at);
         println!("  Updated:  {}", build.updated_at);
     } else if status == StatusCode::NOT_FOUND {
@@ -41,3 +71,29 @@ pub async fn execute(agent_url: &str, args: &BuildGetArgs) -> Result<()> {
 
     Ok(())
 }
+
+/// Formats a size in bytes with a binary unit, e.g. `12.3 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Formats a duration in milliseconds, e.g. `1m 05s` or `4.2s`.
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    if secs < 60 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
@@ -1,11 +1,15 @@
 use anyhow::{Context, Result};
 use clap::Args;
+use reqwest::StatusCode;
 
 use crate::types::{BuildResponse, ErrorResponse};
 
 #[derive(Args, Debug)]
 pub struct BuildListArgs {
-    /// Filter results by status (queued, building, success, failed)
+    /// Only show builds of this app
+    #[arg(long)]
+    pub app: Option<String>,
+    /// Filter results by status (queued, building, success, failed, cancelled, interrupted)
     #[arg(long)]
     pub status: Option<String>,
     /// Limit number of results returned
@@ -15,7 +19,10 @@ pub struct BuildListArgs {
 
 pub async fn execute(agent_url: &str, args: &BuildListArgs) -> Result<()> {
     let client = reqwest::Client::new();
-    let url = format!("{agent_url}/builds");
+    let url = match &args.app {
+        Some(app) => format!("{agent_url}/apps/{app}/builds"),
+        None => format!("{agent_url}/builds"),
+    };
 
     let mut query_params: Vec<(String, String)> = Vec::new();
 
@@ -48,18 +55,26 @@ pub async fn execute(agent_url: &str, args: &BuildListArgs) -> Result<()> {
             println!("No builds found.");
         } else {
             println!(
-                "{:<40} {:<12} {:<20} {:<20}",
-                "ID", "STATUS", "CREATED", "UPDATED"
+                "{:<40} {:<20} {:<12} {:<20} {:<20}",
+                "ID", "APP", "STATUS", "CREATED", "UPDATED"
             );
-            println!("{}", "-".repeat(92));
+            println!("{}", "-".repeat(113));
 
             for build in builds {
                 println!(
-                    "{:<40} {:<12} {:<20} {:<20}",
-                    build.id, build.status, build.created_at, build.updated_at
+                    "{:<40} {:<20} {:<12} {:<20} {:<20}",
+                    build.id,
+                    build.app.as_deref().unwrap_or("-"),
+                    build.status,
+                    build.created_at,
+                    build.updated_at
                 );
             }
         }
+    } else if status == StatusCode::NOT_FOUND
+        && let Some(app) = &args.app
+    {
+        anyhow::bail!("App not found: {app}");
     } else {
         let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
             error: format!("HTTP {status}"),
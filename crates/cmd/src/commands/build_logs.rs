@@ -0,0 +1,56 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+
+use crate::types::ErrorResponse;
+
+#[derive(Args, Debug)]
+pub struct BuildLogsArgs {
+    /// Build ID to show logs for
+    pub id: String,
+    /// Keep streaming output until the build finishes
+    #[arg(short, long)]
+    pub follow: bool,
+}
+
+pub async fn execute(agent_url: &str, args: &BuildLogsArgs) -> Result<()> {
+    stream_logs(agent_url, &args.id, args.follow).await
+}
+
+/// Copies a build's log to stdout as it is received.
+pub(crate) async fn stream_logs(agent_url: &str, build_id: &str, follow: bool) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{agent_url}/builds/{build_id}/logs");
+
+    let mut response = client
+        .get(&url)
+        .query(&[("follow", follow)])
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let mut stdout = std::io::stdout();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .context("Failed to read build logs")?
+        {
+            stdout.write_all(&chunk)?;
+            stdout.flush()?;
+        }
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("Build not found: {build_id}");
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to get build logs: {}", error.error);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+
+use crate::{
+    commands::deploy::wait_for_completion,
+    types::{CreateBuildResponse, ErrorResponse},
+};
+
+#[derive(Args, Debug)]
+pub struct BuildRebuildArgs {
+    /// Build ID to rebuild
+    pub id: String,
+    /// Don't use cached image layers, and pull base images again
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Stream the build's output until it finishes
+    #[arg(long)]
+    pub wait: bool,
+}
+
+pub async fn execute(agent_url: &str, args: &BuildRebuildArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/builds/{}/rebuild", agent_url, args.id);
+
+    let response = client
+        .post(&url)
+        .query(&[("no_cache", args.no_cache)])
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let build: CreateBuildResponse =
+            response.json().await.context("Failed to parse response")?;
+
+        println!("Rebuilding build {}", args.id);
+        println!("Build ID: {}", build.build_id);
+        println!("App: {}", build.app);
+        println!("Status: {}", build.status);
+
+        if args.wait {
+            wait_for_completion(agent_url, &build.build_id).await?;
+        }
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("Build not found: {}", args.id);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to rebuild build: {}", error.error);
+    }
+
+    Ok(())
+}
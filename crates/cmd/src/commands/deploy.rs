@@ -8,12 +8,16 @@ use std::{
 use anyhow::{Context, Result};
 use clap::Args;
 use flate2::{Compression, write::GzEncoder};
+use nimble_core::config::{NimbleConfig, validate_app_name};
 use reqwest::StatusCode;
 use tar::Builder;
 use tokio::time::sleep;
 use walkdir::WalkDir;
 
-use crate::types::{BuildResponse, CreateBuildResponse, ErrorResponse};
+use crate::{
+    commands::build_logs::stream_logs,
+    types::{BuildResponse, CreateBuildResponse, ErrorResponse},
+};
 
 const POLL_INTERVAL: Duration = Duration::from_secs(2);
 
@@ -21,12 +25,17 @@ const POLL_INTERVAL: Duration = Duration::from_secs(2);
 pub struct DeployArgs {
     /// Directory containing the source to deploy
     pub directory: PathBuf,
-    /// Block until the build finishes
+    /// App to deploy as (overrides `app` in nimble.yaml)
+    #[arg(long)]
+    pub app: Option<String>,
+    /// Stream the build's output until it finishes
     #[arg(long)]
     pub wait: bool,
 }
 
 pub async fn execute(agent_url: &str, args: &DeployArgs) -> Result<()> {
+    let app = resolve_app(args)?;
+
     let archive =
         create_tarball(&args.directory).with_context(|| "Failed to create deployment archive")?;
 
@@ -35,6 +44,7 @@ pub async fn execute(agent_url: &str, args: &DeployArgs) -> Result<()> {
 
     let response = client
         .post(&url)
+        .query(&[("app", &app)])
         .header("Content-Type", "application/gzip")
         .body(archive)
         .send()
@@ -49,6 +59,7 @@ pub async fn execute(agent_url: &str, args: &DeployArgs) -> Result<()> {
 
         println!("Build created successfully!");
         println!("Build ID: {}", build.build_id);
+        println!("App: {}", build.app);
         println!("Status: {}", build.status);
 
         if args.wait {
@@ -64,11 +75,13 @@ pub async fn execute(agent_url: &str, args: &DeployArgs) -> Result<()> {
     Ok(())
 }
 
-async fn wait_for_completion(agent_url: &str, build_id: &str) -> Result<()> {
+pub(crate) async fn wait_for_completion(agent_url: &str, build_id: &str) -> Result<()> {
     println!("Waiting for build {build_id} to finish...");
-    let client = reqwest::Client::new();
-    let mut last_reported_status: Option<String> = None;
+    stream_logs(agent_url, build_id, true).await?;
 
+    // The log stream ends once the build has finished, but keep polling in
+    // case the connection was dropped early
+    let client = reqwest::Client::new();
     loop {
         let url = format!("{agent_url}/builds/{build_id}");
         let response = client
@@ -83,18 +96,26 @@ async fn wait_for_completion(agent_url: &str, build_id: &str) -> Result<()> {
                     .json()
                     .await
                     .context("Failed to parse build status")?;
-                if last_reported_status.as_deref() != Some(build.status.as_str()) {
-                    println!("Status: {}", build.status);
-                    last_reported_status = Some(build.status.clone());
-                }
                 match build.status.as_str() {
                     "success" | "succeeded" => {
                         println!("Build finished successfully.");
                         return Ok(());
                     }
                     "failed" | "errored" => {
+                        if build.failure_reason.as_deref() == Some("push") {
+                            anyhow::bail!(
+                                "Build failed: {} (the image couldn't be pushed to the registry)",
+                                build.id
+                            );
+                        }
                         anyhow::bail!("Build failed: {}", build.id);
                     }
+                    "cancelled" => {
+                        anyhow::bail!("Build cancelled: {}", build.id);
+                    }
+                    "interrupted" => {
+                        anyhow::bail!("Build interrupted by an agent restart: {}", build.id);
+                    }
                     _ => {
                         sleep(POLL_INTERVAL).await;
                     }
@@ -113,6 +134,25 @@ async fn wait_for_completion(agent_url: &str, build_id: &str) -> Result<()> {
     }
 }
 
+/// Determines the app to deploy as, from `--app` or the `app` key in nimble.yaml.
+fn resolve_app(args: &DeployArgs) -> Result<String> {
+    if let Some(app) = &args.app {
+        validate_app_name(app)?;
+        return Ok(app.clone());
+    }
+
+    let config_path = args.directory.join("nimble.yaml");
+    if config_path.exists() {
+        let config = NimbleConfig::from_file(&config_path)
+            .with_context(|| format!("Invalid config file {}", config_path.display()))?;
+        if let Some(app) = config.app {
+            return Ok(app);
+        }
+    }
+
+    anyhow::bail!("No app name given: set `app` in nimble.yaml or pass --app")
+}
+
 fn create_tarball(dir: &Path) -> Result<Vec<u8>> {
     let directory = dir
         .canonicalize()
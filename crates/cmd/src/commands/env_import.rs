@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde_json::{Value, json};
+
+use crate::commands::env_set::update_env;
+
+#[derive(Args, Debug)]
+pub struct EnvImportArgs {
+    /// Name of the app
+    pub app: String,
+    /// Path to a .env file of KEY=VALUE lines
+    pub file: PathBuf,
+    /// Store the values as secrets, encrypted at rest and hidden from `env list`
+    #[arg(long)]
+    pub secret: bool,
+}
+
+pub async fn execute(agent_url: &str, args: &EnvImportArgs) -> Result<()> {
+    let contents = std::fs::read_to_string(&args.file)
+        .with_context(|| format!("Failed to read {}", args.file.display()))?;
+
+    let vars = parse_dotenv(&contents)
+        .with_context(|| format!("Failed to parse {}", args.file.display()))?;
+    if vars.is_empty() {
+        anyhow::bail!("No variables found in {}", args.file.display());
+    }
+
+    let set: Vec<Value> = vars
+        .into_iter()
+        .map(|(name, value)| json!({ "name": name, "value": value, "secret": args.secret }))
+        .collect();
+
+    update_env(agent_url, &args.app, json!({ "set": set })).await
+}
+
+/// Parses the contents of a .env file.
+///
+/// Supports `KEY=VALUE` lines with an optional `export` prefix, `#` comments
+/// (on their own line or after a value), and single- or double-quoted
+/// values. Double-quoted values may use `\n`,
+/// `\"` and `\\` escapes.
+fn parse_dotenv(contents: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let (name, value) = line
+            .split_once('=')
+            .with_context(|| format!("line {}: expected KEY=VALUE", i + 1))?;
+        let value = value.trim();
+
+        let value = if let Some(quote @ ('"' | '\'')) = value.chars().next() {
+            let quoted = &value[1..];
+            let end = closing_quote(quoted, quote)
+                .with_context(|| format!("line {}: unterminated quote", i + 1))?;
+
+            // A quoted value may be followed by a comment
+            let rest = quoted[end + 1..].trim_start();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                anyhow::bail!("line {}: unexpected text after quoted value", i + 1);
+            }
+
+            let quoted = &quoted[..end];
+            if quote == '"' {
+                unescape(quoted)
+            } else {
+                quoted.to_string()
+            }
+        } else {
+            // Unquoted values end at an inline comment
+            match value.find(" #") {
+                Some(end) => value[..end].trim_end().to_string(),
+                None => value.to_string(),
+            }
+        };
+
+        vars.push((name.trim().to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Returns the index of the quote that closes a value, skipping quotes
+/// escaped with `\` in double-quoted values.
+fn closing_quote(s: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            c if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Vec<(String, String)> {
+        parse_dotenv(contents).unwrap()
+    }
+
+    fn var(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn plain_values_comments_and_export() {
+        let contents = "# Database\n\nDB_HOST=localhost\nexport DB_PORT = 5432\n  EMPTY=\n";
+        assert_eq!(
+            parse(contents),
+            [
+                var("DB_HOST", "localhost"),
+                var("DB_PORT", "5432"),
+                var("EMPTY", "")
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_comments_end_unquoted_values() {
+        assert_eq!(
+            parse("URL=http://example.com/#anchor # the homepage\n"),
+            [var("URL", "http://example.com/#anchor")]
+        );
+        assert_eq!(parse("A=\"x # y\" # z\n"), [var("A", "x # y")]);
+        assert_eq!(parse("B='x # y'   # z\n"), [var("B", "x # y")]);
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert_eq!(
+            parse("A=\"hello world\"\nB='single $quoted'\nC=\"it's\"\nD='say \"hi\"'\n"),
+            [
+                var("A", "hello world"),
+                var("B", "single $quoted"),
+                var("C", "it's"),
+                var("D", "say \"hi\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn double_quoted_values_are_unescaped() {
+        assert_eq!(
+            parse(r#"KEY="line1\nline2 \"quoted\" back\\slash""#),
+            [var("KEY", "line1\nline2 \"quoted\" back\\slash")]
+        );
+        // Single-quoted values are taken literally
+        assert_eq!(parse(r"KEY='a\nb'"), [var("KEY", r"a\nb")]);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_rejected() {
+        for contents in [
+            "A=1\nKEY=\"open\n",
+            "A=1\nKEY='open\n",
+            "A=1\nKEY=\"open\\\"\n",
+        ] {
+            let err = parse_dotenv(contents).unwrap_err().to_string();
+            assert_eq!(err, "line 2: unterminated quote", "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        let err = parse_dotenv("A=1\nNOT A VAR\n").unwrap_err().to_string();
+        assert_eq!(err, "line 2: expected KEY=VALUE");
+
+        let err = parse_dotenv("KEY=\"a\" b\n").unwrap_err().to_string();
+        assert_eq!(err, "line 1: unexpected text after quoted value");
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+
+use crate::types::{EnvVarResponse, ErrorResponse};
+
+#[derive(Args, Debug)]
+pub struct EnvListArgs {
+    /// Name of the app
+    pub app: String,
+}
+
+pub async fn execute(agent_url: &str, args: &EnvListArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/apps/{}/env", agent_url, args.app);
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let vars: Vec<EnvVarResponse> =
+            response.json().await.context("Failed to parse response")?;
+
+        if vars.is_empty() {
+            println!("No env vars set.");
+        }
+        for var in vars {
+            match var.value {
+                Some(value) if !var.secret => println!("{}={}", var.name, value),
+                _ => println!("{}=<secret>", var.name),
+            }
+        }
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("App not found: {}", args.app);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to list env: {}", error.error);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+use crate::types::{ErrorResponse, UpdateEnvResponse};
+
+#[derive(Args, Debug)]
+pub struct EnvSetArgs {
+    /// Name of the app
+    pub app: String,
+    /// Variables to set, as KEY=VALUE
+    #[arg(required = true, value_parser = parse_assignment)]
+    pub vars: Vec<(String, String)>,
+    /// Store the values as secrets, encrypted at rest and hidden from `env list`
+    #[arg(long)]
+    pub secret: bool,
+}
+
+fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got {s}"))
+}
+
+pub async fn execute(agent_url: &str, args: &EnvSetArgs) -> Result<()> {
+    let set: Vec<Value> = args
+        .vars
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value, "secret": args.secret }))
+        .collect();
+
+    update_env(agent_url, &args.app, json!({ "set": set })).await
+}
+
+/// Sends an env update to the agent and reports the release rolling it out.
+pub(crate) async fn update_env(agent_url: &str, app: &str, body: Value) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{agent_url}/apps/{app}/env");
+
+    let response = client
+        .patch(&url)
+        .json(&body)
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let update: UpdateEnvResponse =
+            response.json().await.context("Failed to parse response")?;
+        match update.release {
+            Some(release) => println!(
+                "{} and deploying as v{}",
+                release.description, release.version
+            ),
+            None => println!("Env updated. It will be applied the next time {app} is deployed."),
+        }
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("App not found: {app}");
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to update env: {}", error.error);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use anyhow::Result;
+use clap::Args;
+use serde_json::json;
+
+use crate::commands::env_set::update_env;
+
+#[derive(Args, Debug)]
+pub struct EnvUnsetArgs {
+    /// Name of the app
+    pub app: String,
+    /// Names of the variables to unset
+    #[arg(required = true)]
+    pub names: Vec<String>,
+}
+
+pub async fn execute(agent_url: &str, args: &EnvUnsetArgs) -> Result<()> {
+    update_env(agent_url, &args.app, json!({ "unset": args.names })).await
+}
@@ -0,0 +1,61 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+
+use crate::types::ErrorResponse;
+
+#[derive(Args, Debug)]
+pub struct LogsArgs {
+    /// App to show logs for
+    pub app: String,
+    /// Keep streaming new output
+    #[arg(short, long)]
+    pub follow: bool,
+    /// Only show lines newer than this, e.g. `30s`, `10m` or `2h`
+    #[arg(long)]
+    pub since: Option<String>,
+    /// Only show this many of the most recent lines
+    #[arg(long)]
+    pub tail: Option<u64>,
+}
+
+pub async fn execute(agent_url: &str, args: &LogsArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/apps/{}/logs", agent_url, args.app);
+
+    let mut query = vec![("follow", args.follow.to_string())];
+    if let Some(since) = &args.since {
+        query.push(("since", since.clone()));
+    }
+    if let Some(tail) = args.tail {
+        query.push(("tail", tail.to_string()));
+    }
+
+    let mut response = client
+        .get(&url)
+        .query(&query)
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let mut stdout = std::io::stdout();
+        while let Some(chunk) = response.chunk().await.context("Failed to read logs")? {
+            stdout.write_all(&chunk)?;
+            stdout.flush()?;
+        }
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("App not found: {}", args.app);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to get logs: {}", error.error);
+    }
+
+    Ok(())
+}
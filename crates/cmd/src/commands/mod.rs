@@ -1,3 +1,17 @@
+pub mod apps_create;
+pub mod apps_destroy;
+pub mod apps_info;
+pub mod apps_list;
+pub mod build_cancel;
 pub mod build_get;
 pub mod build_list;
+pub mod build_logs;
+pub mod build_rebuild;
 pub mod deploy;
+pub mod env_import;
+pub mod env_list;
+pub mod env_set;
+pub mod env_unset;
+pub mod logs;
+pub mod releases;
+pub mod rollback;
@@ -0,0 +1,59 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+
+use crate::types::{ErrorResponse, ReleaseResponse};
+
+#[derive(Args, Debug)]
+pub struct ReleasesArgs {
+    /// Name of the app
+    pub app: String,
+}
+
+pub async fn execute(agent_url: &str, args: &ReleasesArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/apps/{}/releases", agent_url, args.app);
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let releases: Vec<ReleaseResponse> =
+            response.json().await.context("Failed to parse response")?;
+
+        if releases.is_empty() {
+            println!("No releases found.");
+        } else {
+            println!(
+                "{:<8} {:<12} {:<40} {:<20} DESCRIPTION",
+                "VERSION", "STATUS", "BUILD", "CREATED"
+            );
+            println!("{}", "-".repeat(110));
+
+            for release in releases {
+                println!(
+                    "{:<8} {:<12} {:<40} {:<20} {}",
+                    format!("v{}", release.version),
+                    release.status,
+                    release.build_id,
+                    release.created_at,
+                    release.description
+                );
+            }
+        }
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("App not found: {}", args.app);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to list releases: {}", error.error);
+    }
+
+    Ok(())
+}
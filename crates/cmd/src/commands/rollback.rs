@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::types::{ErrorResponse, ReleaseResponse};
+
+#[derive(Args, Debug)]
+pub struct RollbackArgs {
+    /// Name of the app to roll back
+    pub app: String,
+    /// Release to roll back to (e.g. v3); defaults to the previous release
+    #[arg(long, value_parser = parse_version)]
+    pub to: Option<i64>,
+}
+
+/// Parses a release version written as `v3` or `3`.
+fn parse_version(s: &str) -> Result<i64, String> {
+    s.strip_prefix('v')
+        .unwrap_or(s)
+        .parse()
+        .map_err(|_| format!("invalid release version: {s}"))
+}
+
+pub async fn execute(agent_url: &str, args: &RollbackArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/apps/{}/rollback", agent_url, args.app);
+
+    let response = client
+        .post(&url)
+        .json(&json!({ "to": args.to }))
+        .send()
+        .await
+        .context("Failed to send request to agent")?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        let release: ReleaseResponse = response.json().await.context("Failed to parse response")?;
+        println!("{} as v{}", release.description, release.version);
+        println!("Status: {}", release.status);
+    } else if status == StatusCode::NOT_FOUND {
+        anyhow::bail!("App not found: {}", args.app);
+    } else {
+        let error: ErrorResponse = response.json().await.unwrap_or(ErrorResponse {
+            error: format!("HTTP {status}"),
+        });
+        anyhow::bail!("Failed to roll back: {}", error.error);
+    }
+
+    Ok(())
+}
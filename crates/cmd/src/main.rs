@@ -4,7 +4,11 @@ mod types;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
-use crate::commands::{build_get, build_list, deploy};
+use crate::commands::{
+    apps_create, apps_destroy, apps_info, apps_list, build_cancel, build_get, build_list,
+    build_logs, build_rebuild, deploy, env_import, env_list, env_set, env_unset, logs, releases,
+    rollback,
+};
 
 const DEFAULT_AGENT_URL: &str = "http://localhost:7080";
 
@@ -24,11 +28,39 @@ struct Cli {
 enum Commands {
     /// Create a new build from a directory of source files
     Deploy(deploy::DeployArgs),
+    /// Show the output of an app's running containers
+    Logs(logs::LogsArgs),
+    /// List an app's releases
+    Releases(releases::ReleasesArgs),
+    /// Redeploy a previous release of an app
+    Rollback(rollback::RollbackArgs),
+    /// Manage apps
+    Apps {
+        #[command(subcommand)]
+        command: AppsCommands,
+    },
     /// Manage builds
     Build {
         #[command(subcommand)]
         command: BuildCommands,
     },
+    /// Manage an app's environment variables
+    Env {
+        #[command(subcommand)]
+        command: EnvCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum AppsCommands {
+    /// Create a new app
+    Create(apps_create::AppsCreateArgs),
+    /// List apps
+    List(apps_list::AppsListArgs),
+    /// Get details about a specific app
+    Info(apps_info::AppsInfoArgs),
+    /// Stop an app and delete its builds and deployments
+    Destroy(apps_destroy::AppsDestroyArgs),
 }
 
 #[derive(Subcommand)]
@@ -37,6 +69,24 @@ enum BuildCommands {
     List(build_list::BuildListArgs),
     /// Get details about a specific build
     Get(build_get::BuildGetArgs),
+    /// Show the output of a build
+    Logs(build_logs::BuildLogsArgs),
+    /// Cancel a queued or running build
+    Cancel(build_cancel::BuildCancelArgs),
+    /// Build a previous build's source again
+    Rebuild(build_rebuild::BuildRebuildArgs),
+}
+
+#[derive(Subcommand)]
+enum EnvCommands {
+    /// Set environment variables
+    Set(env_set::EnvSetArgs),
+    /// Unset environment variables
+    Unset(env_unset::EnvUnsetArgs),
+    /// List environment variables
+    List(env_list::EnvListArgs),
+    /// Set environment variables from a .env file
+    Import(env_import::EnvImportArgs),
 }
 
 #[tokio::main]
@@ -47,6 +97,29 @@ async fn main() -> Result<()> {
         Commands::Deploy(args) => {
             deploy::execute(&cli.agent_url, args).await?;
         }
+        Commands::Logs(args) => {
+            logs::execute(&cli.agent_url, args).await?;
+        }
+        Commands::Releases(args) => {
+            releases::execute(&cli.agent_url, args).await?;
+        }
+        Commands::Rollback(args) => {
+            rollback::execute(&cli.agent_url, args).await?;
+        }
+        Commands::Apps { command } => match command {
+            AppsCommands::Create(args) => {
+                apps_create::execute(&cli.agent_url, args).await?;
+            }
+            AppsCommands::List(args) => {
+                apps_list::execute(&cli.agent_url, args).await?;
+            }
+            AppsCommands::Info(args) => {
+                apps_info::execute(&cli.agent_url, args).await?;
+            }
+            AppsCommands::Destroy(args) => {
+                apps_destroy::execute(&cli.agent_url, args).await?;
+            }
+        },
         Commands::Build { command } => match command {
             BuildCommands::List(args) => {
                 build_list::execute(&cli.agent_url, args).await?;
@@ -54,6 +127,29 @@ async fn main() -> Result<()> {
             BuildCommands::Get(args) => {
                 build_get::execute(&cli.agent_url, args).await?;
             }
+            BuildCommands::Logs(args) => {
+                build_logs::execute(&cli.agent_url, args).await?;
+            }
+            BuildCommands::Cancel(args) => {
+                build_cancel::execute(&cli.agent_url, args).await?;
+            }
+            BuildCommands::Rebuild(args) => {
+                build_rebuild::execute(&cli.agent_url, args).await?;
+            }
+        },
+        Commands::Env { command } => match command {
+            EnvCommands::Set(args) => {
+                env_set::execute(&cli.agent_url, args).await?;
+            }
+            EnvCommands::Unset(args) => {
+                env_unset::execute(&cli.agent_url, args).await?;
+            }
+            EnvCommands::List(args) => {
+                env_list::execute(&cli.agent_url, args).await?;
+            }
+            EnvCommands::Import(args) => {
+                env_import::execute(&cli.agent_url, args).await?;
+            }
         },
     }
 
@@ -1,17 +1,87 @@
+use nimble_core::deployers::PublishedPort;
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize)]
 pub struct BuildResponse {
     pub id: String,
+    pub app: Option<String>,
     pub status: String,
+    pub rebuild_of: Option<String>,
+    #[serde(default)]
+    pub no_cache: bool,
+    #[serde(default)]
+    pub builder: Option<String>,
+    #[serde(default)]
+    pub builder_reason: Option<String>,
+    #[serde(default)]
+    pub image: Option<BuildImage>,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub failure_reason: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct BuildImage {
+    pub reference: String,
+    pub digest: Option<String>,
+    pub size: Option<u64>,
+}
+
 #[derive(Deserialize)]
 pub struct CreateBuildResponse {
     pub build_id: String,
+    pub app: String,
+    pub status: String,
+}
+
+#[derive(Deserialize)]
+pub struct AppResponse {
+    pub name: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct AppInfoResponse {
+    #[serde(flatten)]
+    pub app: AppResponse,
+    pub deployment: Option<DeploymentResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct DeploymentResponse {
+    pub id: String,
+    pub release: Option<i64>,
+    pub build_id: String,
+    pub image: String,
+    pub container_id: Option<String>,
+    pub ports: Vec<PublishedPort>,
+    pub status: String,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReleaseResponse {
+    pub version: i64,
+    pub build_id: String,
+    pub description: String,
     pub status: String,
+    pub created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct EnvVarResponse {
+    pub name: String,
+    pub value: Option<String>,
+    pub secret: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateEnvResponse {
+    pub release: Option<ReleaseResponse>,
 }
 
 #[derive(Deserialize)]
@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use anyhow::bail;
+
+use crate::config::BuilderType;
+
+/// A builder chosen by inspecting a project's files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    /// The builder to build the project with
+    pub builder: BuilderType,
+    /// Why the builder was chosen (e.g. "found go.mod")
+    pub reason: String,
+}
+
+/// Files that identify the builder for a project, in order of precedence. A
+/// Dockerfile always wins, since it says exactly how to build the project.
+const MARKERS: &[(&str, BuilderType)] = &[
+    ("Dockerfile", BuilderType::Dockerfile),
+    ("go.mod", BuilderType::Go),
+    ("package.json", BuilderType::Node),
+    ("Cargo.toml", BuilderType::Rust),
+    ("requirements.txt", BuilderType::Python),
+    ("pyproject.toml", BuilderType::Python),
+    // Only reached if none of the above are present, i.e. the project is
+    // plain HTML with no build step
+    ("index.html", BuilderType::Static),
+];
+
+/// Detects which builder to use for the project at `path`, from the files in
+/// its root directory.
+///
+/// # Errors
+///
+/// Returns an error if the kind of project can't be recognised.
+pub fn detect_builder(path: &Path) -> anyhow::Result<Detection> {
+    let Some((marker, builder)) = MARKERS
+        .iter()
+        .find(|(marker, _)| path.join(marker).is_file())
+    else {
+        bail!("Cannot detect build type: set `builder` in nimble.yaml or add a Dockerfile");
+    };
+
+    Ok(Detection {
+        builder: builder.clone(),
+        reason: format!("found {marker}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Detects the builder for a project made of the given (empty) files.
+    fn detect(files: &[&str]) -> anyhow::Result<Detection> {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        detect_builder(dir.path())
+    }
+
+    #[test]
+    fn each_marker_picks_its_builder() {
+        for (marker, builder) in MARKERS {
+            let detection = detect(&[marker]).unwrap();
+            assert_eq!(&detection.builder, builder, "{marker}");
+            assert_eq!(detection.reason, format!("found {marker}"));
+        }
+    }
+
+    #[test]
+    fn dockerfile_wins_over_other_markers() {
+        let files = [
+            "go.mod",
+            "package.json",
+            "Cargo.toml",
+            "requirements.txt",
+            "pyproject.toml",
+            "index.html",
+            "Dockerfile",
+        ];
+        assert_eq!(detect(&files).unwrap().builder, BuilderType::Dockerfile);
+    }
+
+    #[test]
+    fn markers_follow_their_precedence() {
+        for (files, expected) in [
+            (&["package.json", "go.mod"][..], BuilderType::Go),
+            (&["Cargo.toml", "package.json"], BuilderType::Node),
+            (&["requirements.txt", "Cargo.toml"], BuilderType::Rust),
+            (&["pyproject.toml", "requirements.txt"], BuilderType::Python),
+        ] {
+            assert_eq!(detect(files).unwrap().builder, expected, "{files:?}");
+        }
+    }
+
+    #[test]
+    fn index_html_is_only_used_when_nothing_else_matches() {
+        assert_eq!(
+            detect(&["index.html"]).unwrap().builder,
+            BuilderType::Static
+        );
+
+        // A site with a build step is built by its language's builder
+        let detection = detect(&["index.html", "package.json"]).unwrap();
+        assert_eq!(detection.builder, BuilderType::Node);
+        assert_eq!(detection.reason, "found package.json");
+    }
+
+    #[test]
+    fn markers_must_be_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("Dockerfile")).unwrap();
+        std::fs::write(dir.path().join("go.mod"), "").unwrap();
+        assert_eq!(detect_builder(dir.path()).unwrap().builder, BuilderType::Go);
+    }
+
+    #[test]
+    fn unrecognised_projects_are_an_error() {
+        let err = detect(&["README.md", "main.c"]).unwrap_err();
+        assert!(
+            err.to_string().contains("Cannot detect build type"),
+            "{err}"
+        );
+    }
+}
@@ -1,21 +1,26 @@
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path};
 
 use async_trait::async_trait;
-use tokio::process::Command;
 
-use crate::builders::{Builder, Image};
+use crate::{
+    builders::{BuildContext, Builder, Image},
+    config::DockerfileConfig,
+    runtime::{BuildOptions, BuildSecret},
+};
 
-pub struct DockerBuilder;
+pub struct DockerBuilder {
+    config: DockerfileConfig,
+}
 
 impl DockerBuilder {
-    pub fn new() -> Self {
-        DockerBuilder
+    pub fn new(config: DockerfileConfig) -> Self {
+        DockerBuilder { config }
     }
 }
 
 impl Default for DockerBuilder {
     fn default() -> Self {
-        Self::new()
+        Self::new(DockerfileConfig::default())
     }
 }
 
@@ -26,93 +31,111 @@ impl Builder for DockerBuilder {
         build_path: &Path,
         image_name: &str,
         image_tag: &str,
+        ctx: &BuildContext,
     ) -> anyhow::Result<Image> {
-        // Check that Dockerfile exists
-        let dockerfile_path = build_path.join("Dockerfile");
-        if !dockerfile_path.exists() {
-            anyhow::bail!(
-                "Dockerfile not found in build directory: {}",
-                build_path.display()
-            );
+        let context = match &self.config.context {
+            Some(context) => build_path.join(context),
+            None => build_path.to_path_buf(),
+        };
+        if !context.is_dir() {
+            anyhow::bail!("Build context not found: {}", context.display());
         }
 
-        // Build the full image reference
-        let image_ref = format!("{image_name}:{image_tag}");
-
-        // Run docker build
-        let output = Command::new("docker")
-            .arg("build")
-            .arg("--tag")
-            .arg(&image_ref)
-            .arg("--file")
-            .arg(&dockerfile_path)
-            .arg(build_path)
-            .output()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to execute docker build: {e}"))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("Docker build failed: {}\nStderr: {}", output.status, stderr);
+        // Like `docker build`, the Dockerfile defaults to the one in the
+        // context
+        let dockerfile_path = match &self.config.dockerfile {
+            Some(dockerfile) => build_path.join(dockerfile),
+            None => context.join("Dockerfile"),
+        };
+        if !dockerfile_path.exists() {
+            anyhow::bail!("Dockerfile not found: {}", dockerfile_path.display());
         }
 
-        // Try to get the image digest
-        let digest = get_image_digest(&image_ref).await.ok();
+        // Secret values come from the app's secret env vars, so they're never
+        // part of the uploaded source
+        let mut secrets = Vec::new();
+        for (id, name) in &self.config.build_secrets {
+            let value = ctx.secrets.get(name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Build secret {id} needs the secret env var {name}, which isn't set on the \
+                     app (set it with `nimble env set --secret`)"
+                )
+            })?;
+            secrets.push(BuildSecret {
+                id: id.clone(),
+                value: value.clone(),
+            });
+        }
 
-        Ok(Image {
-            reference: image_ref,
-            digest,
-        })
+        let options = BuildOptions {
+            context,
+            dockerfile: dockerfile_path,
+            tag: format!("{image_name}:{image_tag}"),
+            no_cache: ctx.no_cache,
+            target: self.config.target.clone(),
+            build_args: self.config.build_args.clone(),
+            secrets,
+        };
+
+        ctx.runtime.build(&options, ctx).await
     }
 }
 
-/// Gets the digest of a Docker image by inspecting it.
-async fn get_image_digest(image_ref: &str) -> anyhow::Result<String> {
-    let output = Command::new("docker")
-        .arg("inspect")
-        .arg("--format={{index .RepoDigests 0}}")
-        .arg(image_ref)
-        .output()
+/// Name of the Dockerfile written into the build directory by builders that
+/// generate their own
+pub(crate) const GENERATED_DOCKERFILE: &str = ".nimble.Dockerfile";
+
+/// Name of the ignore file written next to the generated Dockerfile
+pub(crate) const GENERATED_DOCKERIGNORE: &str = ".nimble.Dockerfile.dockerignore";
+
+/// Builds an image from a Dockerfile generated by a builder. The Dockerfile
+/// is written into `build_path`, which is used as the build context.
+///
+/// Paths in `ignore` are left out of the build context, along with those in
+/// the project's own .dockerignore. Files in `include` are other files the
+/// builder wrote for the Dockerfile to copy, and are always sent.
+pub(crate) async fn build_generated(
+    build_path: &Path,
+    dockerfile: &str,
+    ignore: &[&str],
+    include: &[&str],
+    image_name: &str,
+    image_tag: &str,
+    ctx: &BuildContext,
+) -> anyhow::Result<Image> {
+    let dockerfile_path = build_path.join(GENERATED_DOCKERFILE);
+    tokio::fs::write(&dockerfile_path, dockerfile)
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to inspect image: {e}"))?;
+        .map_err(|e| anyhow::anyhow!("Failed to write generated Dockerfile: {e}"))?;
 
-    if !output.status.success() {
-        anyhow::bail!(
-            "Failed to inspect image: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-    }
-
-    let output_str = String::from_utf8_lossy(&output.stdout);
-    let output_str = output_str.trim();
-
-    // Extract digest from format like "image@sha256:abc123..."
-    // If the output is empty or doesn't contain @, try getting the ID instead
-    if output_str.is_empty() || !output_str.contains('@') {
-        // Fallback: get the image ID
-        let id_output = Command::new("docker")
-            .arg("inspect")
-            .arg("--format={{.Id}}")
-            .arg(image_ref)
-            .output()
+    if !ignore.is_empty() || !include.is_empty() {
+        // BuildKit reads `<dockerfile>.dockerignore` instead of the project's
+        // .dockerignore, so include the project's patterns too. The last
+        // matching pattern wins, so the builder's files come last.
+        let mut patterns = tokio::fs::read_to_string(build_path.join(".dockerignore"))
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to get image ID: {e}"))?;
-
-        if id_output.status.success() {
-            let id = String::from_utf8_lossy(&id_output.stdout)
-                .trim()
-                .to_string();
-            if !id.is_empty() {
-                return Ok(id);
-            }
+            .unwrap_or_default();
+        for path in ignore {
+            patterns.push('\n');
+            patterns.push_str(path);
+        }
+        for path in include {
+            patterns.push_str(&format!("\n!{path}"));
         }
-        anyhow::bail!("Could not determine image digest or ID");
+        tokio::fs::write(build_path.join(GENERATED_DOCKERIGNORE), patterns)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to write generated .dockerignore: {e}"))?;
     }
 
-    // Extract the digest part (everything after @)
-    if let Some(digest_part) = output_str.split('@').nth(1) {
-        Ok(digest_part.to_string())
-    } else {
-        anyhow::bail!("Could not parse digest from inspect output: {output_str}")
-    }
+    let options = BuildOptions {
+        context: build_path.to_path_buf(),
+        dockerfile: dockerfile_path,
+        tag: format!("{image_name}:{image_tag}"),
+        no_cache: ctx.no_cache,
+        target: None,
+        build_args: BTreeMap::new(),
+        secrets: Vec::new(),
+    };
+
+    ctx.runtime.build(&options, ctx).await
 }
@@ -1,18 +1,67 @@
-use std::path::Path;
+use std::{fmt::Write, path::Path};
 
-use crate::builders::{Builder, Image};
+use crate::{
+    builders::{BuildContext, Builder, Image, docker::build_generated},
+    config::GoConfig,
+};
 
-pub struct GoBuilder;
+/// Go version used if go.mod has no `go` directive (the latest release)
+const DEFAULT_GO_VERSION: &str = "1";
+
+/// Image the built binary runs in
+const RUNTIME_IMAGE: &str = "alpine:3.20";
+
+/// Builds a Go module into a minimal image, without needing a Dockerfile.
+pub struct GoBuilder {
+    config: GoConfig,
+}
 
 impl GoBuilder {
-    pub fn new() -> Self {
-        GoBuilder
+    pub fn new(config: GoConfig) -> Self {
+        GoBuilder { config }
+    }
+
+    /// Generates a multi-stage Dockerfile that builds the binary with the
+    /// given Go version and copies it into a small runtime image.
+    fn dockerfile(&self, go_version: &str, has_go_sum: bool) -> anyhow::Result<String> {
+        let mut build = vec!["go", "build", "-trimpath", "-o", "/out/app"];
+        let tags = self.config.tags.join(",");
+        if !tags.is_empty() {
+            build.extend(["-tags", &tags]);
+        }
+        if let Some(ldflags) = &self.config.ldflags {
+            build.extend(["-ldflags", ldflags]);
+        }
+        let main = main_package(self.config.main.as_deref());
+        build.push(&main);
+
+        let mut dockerfile = String::new();
+        writeln!(dockerfile, "FROM golang:{go_version}-alpine AS build")?;
+        if self.config.cgo {
+            writeln!(dockerfile, "RUN apk add --no-cache build-base")?;
+        }
+        writeln!(dockerfile, "WORKDIR /src")?;
+        writeln!(dockerfile, "ENV CGO_ENABLED={}", u8::from(self.config.cgo))?;
+        // Download modules in their own layer, so they're only fetched again
+        // when the dependencies change
+        let go_sum = if has_go_sum { " go.sum" } else { "" };
+        writeln!(dockerfile, "COPY go.mod{go_sum} ./")?;
+        writeln!(dockerfile, "RUN go mod download")?;
+        writeln!(dockerfile, "COPY . .")?;
+        writeln!(dockerfile, "RUN {}", serde_json::to_string(&build)?)?;
+        writeln!(dockerfile)?;
+        writeln!(dockerfile, "FROM {RUNTIME_IMAGE}")?;
+        writeln!(dockerfile, "RUN apk add --no-cache ca-certificates tzdata")?;
+        writeln!(dockerfile, "COPY --from=build /out/app /usr/local/bin/app")?;
+        writeln!(dockerfile, r#"ENTRYPOINT ["/usr/local/bin/app"]"#)?;
+
+        Ok(dockerfile)
     }
 }
 
 impl Default for GoBuilder {
     fn default() -> Self {
-        Self::new()
+        Self::new(GoConfig::default())
     }
 }
 
@@ -20,24 +69,124 @@ impl Default for GoBuilder {
 impl Builder for GoBuilder {
     async fn build(
         &self,
-        _build_path: &Path,
-        _image_name: &str,
-        _image_tag: &str,
+        build_path: &Path,
+        image_name: &str,
+        image_tag: &str,
+        ctx: &BuildContext,
     ) -> anyhow::Result<Image> {
-        anyhow::bail!("unimplemented")
+        let go_mod_path = build_path.join("go.mod");
+        if !go_mod_path.exists() {
+            anyhow::bail!(
+                "go.mod not found in build directory: {}",
+                build_path.display()
+            );
+        }
+
+        let go_mod = tokio::fs::read_to_string(&go_mod_path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read go.mod: {e}"))?;
+        let go_version = go_version(&go_mod)?.unwrap_or(DEFAULT_GO_VERSION);
+        ctx.log.line(&format!("==> Using Go {go_version}")).await;
+
+        let has_go_sum = build_path.join("go.sum").exists();
+        let dockerfile = self.dockerfile(go_version, has_go_sum)?;
+
+        build_generated(
+            build_path,
+            &dockerfile,
+            &[],
+            &[],
+            image_name,
+            image_tag,
+            ctx,
+        )
+        .await
+    }
+}
+
+/// Returns the version in go.mod's `go` directive, e.g. `1.22` or `1.22.3`.
+fn go_version(go_mod: &str) -> anyhow::Result<Option<&str>> {
+    for line in go_mod.lines() {
+        let line = line.split("//").next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        if words.next() != Some("go") {
+            continue;
+        }
+
+        // The version becomes part of an image tag
+        let version = words.next().unwrap_or_default();
+        let valid = !version.is_empty()
+            && words.next().is_none()
+            && version
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.');
+        if !valid {
+            anyhow::bail!("Invalid go directive in go.mod: {}", line.trim());
+        }
+        return Ok(Some(version));
     }
+
+    Ok(None)
 }
 
-#[allow(dead_code)]
-const DOCKERFILE: &str = r#"
-# Stage 1: build the binary
-FROM golang:1.22-alpine AS builder
-WORKDIR /src
-COPY . .
-RUN go build -o myapp .
-
-# Stage 2: minimal image
-FROM alpine:3.18
-COPY --from=builder /src/myapp /usr/local/bin/myapp
-ENTRYPOINT ["myapp"]
-"#;
+/// Returns the package argument for `go build`, which must start with `./`
+/// to be taken as a directory in the module.
+fn main_package(main: Option<&str>) -> String {
+    let main = main.unwrap_or(".").trim_start_matches("./");
+    if main.is_empty() || main == "." {
+        ".".to_string()
+    } else {
+        format!("./{main}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_comes_from_the_go_directive() {
+        let go_mod = "module example.com/app\n\ngo 1.22\n\nrequire example.com/dep v1.0.0\n";
+        assert_eq!(go_version(go_mod).unwrap(), Some("1.22"));
+
+        let go_mod = "module example.com/app\n\ngo 1.22.3 // patch release\n";
+        assert_eq!(go_version(go_mod).unwrap(), Some("1.22.3"));
+    }
+
+    #[test]
+    fn toolchain_line_is_not_the_go_directive() {
+        let go_mod = "module example.com/app\n\ngo 1.21\n\ntoolchain go1.22.3\n";
+        assert_eq!(go_version(go_mod).unwrap(), Some("1.21"));
+
+        let go_mod = "module example.com/app\n\ntoolchain go1.22.3\n";
+        assert_eq!(go_version(go_mod).unwrap(), None);
+    }
+
+    #[test]
+    fn go_mod_without_a_go_directive_has_no_version() {
+        let go_mod = "module example.com/app\n\n// go 1.22\nrequire example.com/go v1.0.0\n";
+        assert_eq!(go_version(go_mod).unwrap(), None);
+        assert_eq!(go_version("").unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_go_directives_are_rejected() {
+        for go_mod in ["go\n", "go 1.22 extra\n", "go 1.22;rm\n"] {
+            assert!(go_version(go_mod).is_err(), "{go_mod:?}");
+        }
+    }
+
+    #[test]
+    fn main_package_is_a_module_relative_path() {
+        for (main, expected) in [
+            (None, "."),
+            (Some("."), "."),
+            (Some("./"), "."),
+            (Some(""), "."),
+            (Some("cmd/server"), "./cmd/server"),
+            (Some("./cmd/server"), "./cmd/server"),
+        ] {
+            assert_eq!(main_package(main), expected, "{main:?}");
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::{path::Path, process::ExitStatus, sync::Arc};
+
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::Command,
+    sync::Mutex,
+};
+
+use crate::builders::BuildContext;
+
+/// Destination for a build's output, written line by line.
+///
+/// Lines are flushed as they are written, so the log can be followed while
+/// the build is running.
+#[derive(Clone, Default)]
+pub struct BuildLog {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl BuildLog {
+    /// Opens a log file for appending, creating it if it doesn't exist.
+    pub async fn create(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    /// Returns a log that throws away everything written to it.
+    pub fn discard() -> Self {
+        Self::default()
+    }
+
+    /// Appends a line to the log.
+    ///
+    /// Logging is best effort: write errors are ignored rather than failing
+    /// the build.
+    pub async fn line(&self, line: &str) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        let mut file = file.lock().await;
+        let mut buf = Vec::with_capacity(line.len() + 1);
+        buf.extend_from_slice(line.as_bytes());
+        buf.push(b'\n');
+        let _ = file.write_all(&buf).await;
+        let _ = file.flush().await;
+    }
+}
+
+/// Runs a command to completion, writing its stdout and stderr to the log
+/// as they are produced.
+///
+/// If the build is cancelled first, the command is killed and an
+/// `Interrupted` error is returned.
+pub(crate) async fn run_logged(
+    mut cmd: Command,
+    ctx: &BuildContext,
+) -> std::io::Result<ExitStatus> {
+    let mut child = cmd
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let log = &ctx.log;
+
+    let status = tokio::select! {
+        status = async {
+            tokio::join!(copy_lines(stdout, log), copy_lines(stderr, log));
+            child.wait().await
+        } => Some(status),
+        _ = ctx.cancel.cancelled() => None,
+    };
+
+    match status {
+        Some(status) => status,
+        None => {
+            child.kill().await?;
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "build cancelled",
+            ))
+        }
+    }
+}
+
+/// Writes each line read from `reader` to the log, until the end of input.
+pub(crate) async fn copy_lines(reader: impl AsyncRead + Unpin, log: &BuildLog) {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&buf);
+                log.line(line.trim_end_matches(['\r', '\n'])).await;
+            }
+        }
+    }
+}
@@ -1,21 +1,40 @@
+pub mod detect;
 pub mod docker;
 pub mod go;
+pub mod log;
+pub mod node;
+pub mod plugin;
+pub mod python;
+pub mod rust;
+pub mod static_site;
 
-use std::path::Path;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    builders::{docker::DockerBuilder, go::GoBuilder},
-    config::BuilderType,
+    builders::{
+        docker::DockerBuilder, go::GoBuilder, log::BuildLog, node::NodeBuilder,
+        plugin::PluginBuilder, python::PythonBuilder, rust::RustBuilder,
+        static_site::StaticBuilder,
+    },
+    config::BuilderConfig,
+    runtime::{ContainerRuntime, cli::CliRuntime},
 };
 
 /// Represents a built Docker image
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Image {
     /// The full image reference (e.g., "myapp:latest" or "registry.example.com/myapp:v1.0.0")
     pub reference: String,
     /// Optional image digest (e.g., "sha256:abc123...")
+    #[serde(default)]
     pub digest: Option<String>,
 }
 
@@ -37,6 +56,34 @@ impl Image {
     }
 }
 
+/// Per-build settings and facilities passed to a builder.
+#[derive(Clone)]
+pub struct BuildContext {
+    /// Where the builder writes the build's output
+    pub log: BuildLog,
+    /// Cancelled when the build should be abandoned. Builders stop any
+    /// processes they started and return an error.
+    pub cancel: CancellationToken,
+    /// Build without using cached layers, pulling base images afresh
+    pub no_cache: bool,
+    /// Container runtime that builds images
+    pub runtime: Arc<dyn ContainerRuntime>,
+    /// Values of the app's secret env vars the build may use, by name
+    pub secrets: BTreeMap<String, String>,
+}
+
+impl Default for BuildContext {
+    fn default() -> Self {
+        Self {
+            log: BuildLog::default(),
+            cancel: CancellationToken::default(),
+            no_cache: false,
+            runtime: Arc::new(CliRuntime::docker()),
+            secrets: BTreeMap::new(),
+        }
+    }
+}
+
 /// Trait for building Docker images from source code.
 #[async_trait::async_trait]
 pub trait Builder: Send + Sync {
@@ -47,6 +94,7 @@ pub trait Builder: Send + Sync {
     /// * `build_path` - Path to the directory containing the source code to build
     /// * `image_name` - Name for the built image (e.g., "myapp" or "registry.com/myapp")
     /// * `image_tag` - Tag for the built image (e.g., "latest" or "v1.0.0")
+    /// * `ctx` - Build context, including the log to write build output to
     ///
     /// # Returns
     ///
@@ -56,12 +104,20 @@ pub trait Builder: Send + Sync {
         build_path: &Path,
         image_name: &str,
         image_tag: &str,
+        ctx: &BuildContext,
     ) -> anyhow::Result<Image>;
 }
 
-pub fn select_builder(r#type: BuilderType) -> Box<dyn Builder> {
-    match r#type {
-        BuilderType::Dockerfile => Box::new(DockerBuilder::new()),
-        BuilderType::Go => Box::new(GoBuilder::new()),
+/// Returns the builder for the given config. Builder plugins are looked up in
+/// the directories in `plugin_path`.
+pub fn select_builder(config: &BuilderConfig, plugin_path: &[PathBuf]) -> Box<dyn Builder> {
+    match config {
+        BuilderConfig::Dockerfile(config) => Box::new(DockerBuilder::new(config.clone())),
+        BuilderConfig::Go(config) => Box::new(GoBuilder::new(config.clone())),
+        BuilderConfig::Node => Box::new(NodeBuilder::new()),
+        BuilderConfig::Python(config) => Box::new(PythonBuilder::new(config.clone())),
+        BuilderConfig::Rust(config) => Box::new(RustBuilder::new(config.clone())),
+        BuilderConfig::Static(config) => Box::new(StaticBuilder::new(config.clone())),
+        BuilderConfig::Plugin(name) => Box::new(PluginBuilder::new(name, plugin_path.to_vec())),
     }
 }
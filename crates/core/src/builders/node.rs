@@ -0,0 +1,423 @@
+use std::{fmt::Write, path::Path};
+
+use serde_json::Value;
+
+use crate::builders::{BuildContext, Builder, Image, docker::build_generated};
+
+/// Node.js version used if package.json doesn't constrain `engines.node`
+const DEFAULT_NODE_VERSION: &str = "lts";
+
+/// Files the dependencies are installed from, copied before the rest of the
+/// source so the install is cached until they change
+const INSTALL_FILES: &[&str] = &[
+    "package.json",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    ".npmrc",
+    ".yarnrc",
+    ".yarnrc.yml",
+];
+
+/// Builds a Node.js package into an image that runs its `start` script.
+pub struct NodeBuilder;
+
+impl NodeBuilder {
+    pub fn new() -> Self {
+        NodeBuilder
+    }
+}
+
+impl Default for NodeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Package manager a project is installed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PackageManager {
+    Npm,
+    /// Yarn 1.x
+    Yarn,
+    /// Yarn 2 and later
+    YarnBerry,
+    Pnpm,
+}
+
+impl PackageManager {
+    /// Picks the package manager from the project's lockfile, falling back to
+    /// the `packageManager` field in package.json. Returns it along with
+    /// whether there is a lockfile to install from.
+    pub(crate) fn detect(build_path: &Path, package: &Value) -> (Self, bool) {
+        let exists = |name: &str| build_path.join(name).exists();
+
+        if exists("pnpm-lock.yaml") {
+            return (PackageManager::Pnpm, true);
+        }
+        if exists("yarn.lock") {
+            let yarn = if exists(".yarnrc.yml") {
+                PackageManager::YarnBerry
+            } else {
+                PackageManager::Yarn
+            };
+            return (yarn, true);
+        }
+        if exists("package-lock.json") || exists("npm-shrinkwrap.json") {
+            return (PackageManager::Npm, true);
+        }
+
+        let package_manager = package["packageManager"].as_str().unwrap_or_default();
+        let manager = if package_manager.starts_with("pnpm@") {
+            PackageManager::Pnpm
+        } else if package_manager.starts_with("yarn@1.") {
+            PackageManager::Yarn
+        } else if package_manager.starts_with("yarn@") {
+            PackageManager::YarnBerry
+        } else {
+            PackageManager::Npm
+        };
+        (manager, false)
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::Yarn | PackageManager::YarnBerry => "yarn",
+            PackageManager::Pnpm => "pnpm",
+        }
+    }
+
+    /// Installs all dependencies, including dev dependencies needed to build.
+    fn install(self, locked: bool) -> &'static str {
+        match (self, locked) {
+            (PackageManager::Npm, true) => "npm ci",
+            (PackageManager::Npm, false) => "npm install",
+            (PackageManager::Yarn, true) => "yarn install --frozen-lockfile",
+            (PackageManager::Yarn, false) => "yarn install",
+            (PackageManager::YarnBerry, true) => "yarn install --immutable",
+            (PackageManager::YarnBerry, false) => "yarn install",
+            (PackageManager::Pnpm, true) => "pnpm install --frozen-lockfile",
+            (PackageManager::Pnpm, false) => "pnpm install",
+        }
+    }
+
+    /// Removes dev dependencies once the project has been built.
+    fn prune(self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm prune --omit=dev",
+            PackageManager::Yarn => "yarn install --production --ignore-scripts --prefer-offline",
+            PackageManager::YarnBerry => "yarn workspaces focus --all --production",
+            PackageManager::Pnpm => "pnpm prune --prod",
+        }
+    }
+
+    /// Whether the package manager has to be installed with corepack, rather
+    /// than coming with the Node.js image.
+    fn needs_corepack(self) -> bool {
+        matches!(self, PackageManager::YarnBerry | PackageManager::Pnpm)
+    }
+
+    /// Writes the instructions that install all of the package's
+    /// dependencies into the working directory. Only the files needed to
+    /// install are copied, so the install is cached until they change.
+    pub(crate) fn write_install(
+        self,
+        dockerfile: &mut String,
+        build_path: &Path,
+        locked: bool,
+    ) -> std::fmt::Result {
+        let install_files = INSTALL_FILES
+            .iter()
+            .copied()
+            .filter(|name| build_path.join(name).exists())
+            .collect::<Vec<_>>();
+
+        if self.needs_corepack() {
+            writeln!(dockerfile, "ENV COREPACK_ENABLE_DOWNLOAD_PROMPT=0")?;
+            writeln!(
+                dockerfile,
+                "RUN npm install --global --force corepack@latest && corepack enable"
+            )?;
+        }
+        if self == PackageManager::YarnBerry {
+            // Install into node_modules rather than Plug'n'Play, so the app
+            // can be started with npm
+            writeln!(dockerfile, "ENV YARN_NODE_LINKER=node-modules")?;
+        }
+        writeln!(dockerfile, "COPY {} ./", install_files.join(" "))?;
+        if build_path.join(".yarn").is_dir() {
+            writeln!(dockerfile, "COPY .yarn ./.yarn")?;
+        }
+        writeln!(dockerfile, "RUN {}", self.install(locked))
+    }
+}
+
+/// Reads and parses the project's package.json.
+pub(crate) async fn read_package(build_path: &Path) -> anyhow::Result<Value> {
+    let package_path = build_path.join("package.json");
+    if !package_path.exists() {
+        anyhow::bail!(
+            "package.json not found in build directory: {}",
+            build_path.display()
+        );
+    }
+
+    let package = tokio::fs::read_to_string(&package_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read package.json: {e}"))?;
+    serde_json::from_str(&package).map_err(|e| anyhow::anyhow!("Failed to parse package.json: {e}"))
+}
+
+#[async_trait::async_trait]
+impl Builder for NodeBuilder {
+    async fn build(
+        &self,
+        build_path: &Path,
+        image_name: &str,
+        image_tag: &str,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<Image> {
+        let package = read_package(build_path).await?;
+
+        // `npm start` runs server.js if there's no start script
+        let scripts = &package["scripts"];
+        if scripts["start"].as_str().is_none() && !build_path.join("server.js").exists() {
+            anyhow::bail!("package.json has no start script to run the app with");
+        }
+        let has_build = scripts["build"].as_str().is_some();
+
+        let node_version = node_version(package["engines"]["node"].as_str());
+        let (manager, locked) = PackageManager::detect(build_path, &package);
+        ctx.log
+            .line(&format!(
+                "==> Using Node.js {node_version} and {}",
+                manager.name()
+            ))
+            .await;
+
+        let mut dockerfile = String::new();
+        writeln!(dockerfile, "FROM node:{node_version}-alpine AS build")?;
+        writeln!(dockerfile, "WORKDIR /app")?;
+        manager.write_install(&mut dockerfile, build_path, locked)?;
+        writeln!(dockerfile, "COPY . .")?;
+        if has_build {
+            writeln!(dockerfile, "RUN {} run build", manager.name())?;
+        }
+        writeln!(dockerfile, "RUN {}", manager.prune())?;
+        writeln!(dockerfile)?;
+        writeln!(dockerfile, "FROM node:{node_version}-alpine")?;
+        writeln!(dockerfile, "ENV NODE_ENV=production")?;
+        writeln!(dockerfile, "WORKDIR /app")?;
+        writeln!(dockerfile, "COPY --from=build --chown=node:node /app ./")?;
+        writeln!(dockerfile, "USER node")?;
+        writeln!(dockerfile, r#"CMD ["npm", "start"]"#)?;
+
+        // A local node_modules may hold native addons built for another
+        // platform, and `COPY . .` would copy it over the installed one
+        let ignore = ["node_modules", ".git"];
+        build_generated(
+            build_path,
+            &dockerfile,
+            &ignore,
+            &[],
+            image_name,
+            image_tag,
+            ctx,
+        )
+        .await
+    }
+}
+
+/// Picks a `node` image version satisfying package.json's `engines.node`
+/// range: the highest major version it allows, e.g. `20` for `^20.1.0`,
+/// `20.x` or `>=18 <21`, and `18.19.0` for an exact version. Ranges with no
+/// upper bound, like `>=18`, get the current LTS.
+pub(crate) fn node_version(range: Option<&str>) -> String {
+    let range = range.unwrap_or_default().trim();
+
+    let exact =
+        range.split('.').count() == 3 && range.chars().all(|c| c.is_ascii_digit() || c == '.');
+    if exact {
+        return range.to_string();
+    }
+
+    // Of several alternatives, take the one allowing the highest version
+    let mut major: Option<u64> = None;
+    for alternative in range.split("||") {
+        let Some(highest) = highest_major(alternative) else {
+            return DEFAULT_NODE_VERSION.to_string();
+        };
+        major = Some(major.map_or(highest, |major| major.max(highest)));
+    }
+
+    major.map_or_else(
+        || DEFAULT_NODE_VERSION.to_string(),
+        |major| major.to_string(),
+    )
+}
+
+/// Returns the highest major version meeting every comparator of a range
+/// such as `>=18 <21` or `18 - 20`, or `None` if there's no upper bound or no
+/// version meets them all.
+fn highest_major(range: &str) -> Option<u64> {
+    // A hyphen range is an inclusive lower and upper bound
+    if let Some((from, to)) = range.split_once(" - ") {
+        return highest_major(&format!(">={} <={}", from.trim(), to.trim()));
+    }
+
+    // Operators may be followed by a space
+    let mut comparators = Vec::new();
+    let mut pending = String::new();
+    for token in range.split_whitespace() {
+        pending.push_str(token);
+        if pending.contains(|c: char| c.is_ascii_alphanumeric() || c == '*') {
+            comparators.push(std::mem::take(&mut pending));
+        }
+    }
+
+    let mut upper: Option<u64> = None;
+    let mut lower = 0;
+    for comparator in &comparators {
+        let split = comparator
+            .find(|c: char| c.is_ascii_alphanumeric() || c == '*')
+            .unwrap_or(comparator.len());
+        let (op, version) = comparator.split_at(split);
+        // Missing and wildcard parts (`20`, `20.x`, `20.*`) are `None`
+        let mut parts = version.trim_start_matches('v').split('.').map(|part| {
+            part.chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse::<u64>()
+                .ok()
+        });
+        let Some(Some(major)) = parts.next() else {
+            // `*` or `x` allows anything
+            continue;
+        };
+        let rest = parts.collect::<Vec<_>>();
+        let rest_is_zero = rest.iter().all(|part| part.unwrap_or(0) == 0);
+        let whole_major = rest.iter().all(Option::is_none);
+
+        let (comparator_lower, comparator_upper) = match op {
+            "<" if rest_is_zero => (None, Some(major.checked_sub(1)?)),
+            "<" | "<=" => (None, Some(major)),
+            // `>20` rules out every 20.x release
+            ">" if whole_major => (Some(major + 1), None),
+            ">" | ">=" => (Some(major), None),
+            "" | "=" | "^" | "~" | "~>" => (Some(major), Some(major)),
+            _ => return None,
+        };
+
+        if let Some(comparator_lower) = comparator_lower {
+            lower = lower.max(comparator_lower);
+        }
+        if let Some(comparator_upper) = comparator_upper {
+            upper = Some(upper.map_or(comparator_upper, |upper| upper.min(comparator_upper)));
+        }
+    }
+
+    upper.filter(|&upper| upper >= lower)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn version_is_the_highest_major_the_range_allows() {
+        for (range, expected) in [
+            ("^20.1.0", "20"),
+            ("~20.1", "20"),
+            ("20.x", "20"),
+            ("20", "20"),
+            ("v20.*", "20"),
+            ("18.19.0", "18.19.0"),
+            ("<20", "19"),
+            ("<20.0.0", "19"),
+            ("<20.1", "20"),
+            ("<=20", "20"),
+            (">=18 <21", "20"),
+            (">= 18 < 21", "20"),
+            ("18 - 20", "20"),
+            ("^18 || ^20", "20"),
+            ("^20 || ^18.12", "20"),
+        ] {
+            assert_eq!(node_version(Some(range)), expected, "{range}");
+        }
+    }
+
+    #[test]
+    fn ranges_without_an_upper_bound_use_the_lts() {
+        for range in [">=18", ">18.1.0", "*", "", "^18 || >=20", "latest"] {
+            assert_eq!(node_version(Some(range)), DEFAULT_NODE_VERSION, "{range}");
+        }
+        assert_eq!(node_version(None), DEFAULT_NODE_VERSION);
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_use_the_lts() {
+        for range in [">20 <21", ">=21 <=20", "<0"] {
+            assert_eq!(node_version(Some(range)), DEFAULT_NODE_VERSION, "{range}");
+        }
+    }
+
+    /// Detects the package manager for a project with the given files and
+    /// package.json.
+    fn detect(files: &[&str], package: Value) -> (PackageManager, bool) {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        PackageManager::detect(dir.path(), &package)
+    }
+
+    #[test]
+    fn package_manager_comes_from_the_lockfile() {
+        for (files, expected) in [
+            (&["package-lock.json"][..], PackageManager::Npm),
+            (&["npm-shrinkwrap.json"], PackageManager::Npm),
+            (&["yarn.lock"], PackageManager::Yarn),
+            (&["yarn.lock", ".yarnrc.yml"], PackageManager::YarnBerry),
+            (&["pnpm-lock.yaml"], PackageManager::Pnpm),
+        ] {
+            assert_eq!(detect(files, json!({})), (expected, true), "{files:?}");
+        }
+    }
+
+    #[test]
+    fn lockfiles_take_precedence_pnpm_then_yarn_then_npm() {
+        let all = ["package-lock.json", "yarn.lock", "pnpm-lock.yaml"];
+        assert_eq!(detect(&all, json!({})), (PackageManager::Pnpm, true));
+
+        let yarn_and_npm = ["package-lock.json", "yarn.lock"];
+        assert_eq!(
+            detect(&yarn_and_npm, json!({})),
+            (PackageManager::Yarn, true)
+        );
+
+        // The lockfile wins over the packageManager field
+        let package = json!({ "packageManager": "pnpm@9.1.0" });
+        assert_eq!(
+            detect(&["package-lock.json"], package),
+            (PackageManager::Npm, true)
+        );
+    }
+
+    #[test]
+    fn package_manager_falls_back_to_the_package_manager_field() {
+        for (field, expected) in [
+            ("pnpm@9.1.0", PackageManager::Pnpm),
+            ("yarn@1.22.19", PackageManager::Yarn),
+            ("yarn@4.1.0", PackageManager::YarnBerry),
+            ("npm@10.2.0", PackageManager::Npm),
+        ] {
+            let package = json!({ "packageManager": field });
+            assert_eq!(detect(&[], package), (expected, false), "{field}");
+        }
+        assert_eq!(detect(&[], json!({})), (PackageManager::Npm, false));
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::Command,
+};
+
+use crate::builders::{BuildContext, Builder, Image, log::copy_lines};
+
+/// Prefix of builder plugin executables, followed by the plugin's name
+pub const PLUGIN_EXECUTABLE_PREFIX: &str = "nimble-builder-";
+
+/// Builds an image by running an external builder plugin.
+///
+/// The plugin is an executable named `nimble-builder-<name>`, looked up in
+/// the plugin path. It's run in the workspace and gets a JSON
+/// [`PluginRequest`] on stdin. Anything it writes to stderr goes to the
+/// build log, and on success it writes the built [`Image`] to stdout as
+/// JSON, e.g. `{"reference": "myapp:latest", "digest": "sha256:..."}`.
+pub struct PluginBuilder {
+    name: String,
+    plugin_path: Vec<PathBuf>,
+}
+
+impl PluginBuilder {
+    /// Creates a builder that runs the plugin `name`, looking for it in the
+    /// directories in `plugin_path`, in order.
+    pub fn new(name: impl Into<String>, plugin_path: Vec<PathBuf>) -> Self {
+        PluginBuilder {
+            name: name.into(),
+            plugin_path,
+        }
+    }
+
+    /// Finds the plugin's executable in the plugin path.
+    fn executable(&self) -> anyhow::Result<PathBuf> {
+        let file_name = format!("{PLUGIN_EXECUTABLE_PREFIX}{}", self.name);
+        self.plugin_path
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                let dirs = self
+                    .plugin_path
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect::<Vec<_>>();
+                anyhow::anyhow!(
+                    "Builder plugin {file_name} not found in plugin path: {}",
+                    dirs.join(":")
+                )
+            })
+    }
+}
+
+/// What a builder plugin is asked to build, passed to it as JSON on stdin.
+#[derive(Debug, Serialize)]
+pub struct PluginRequest<'a> {
+    /// Directory containing the source code to build
+    pub workspace: &'a Path,
+    /// Name for the built image
+    pub image_name: &'a str,
+    /// Tag for the built image
+    pub image_tag: &'a str,
+    /// Build without using cached layers
+    pub no_cache: bool,
+}
+
+#[async_trait::async_trait]
+impl Builder for PluginBuilder {
+    async fn build(
+        &self,
+        build_path: &Path,
+        image_name: &str,
+        image_tag: &str,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<Image> {
+        let executable = self.executable()?;
+        ctx.log
+            .line(&format!("==> Running {}", executable.display()))
+            .await;
+
+        let request = serde_json::to_vec(&PluginRequest {
+            workspace: build_path,
+            image_name,
+            image_tag,
+            no_cache: ctx.no_cache,
+        })?;
+
+        let mut child = Command::new(&executable)
+            .current_dir(build_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to run builder plugin {}: {e}", self.name))?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        let result = tokio::select! {
+            result = async {
+                let write = async {
+                    // A plugin that doesn't need the request may exit without
+                    // reading it, so a broken pipe isn't an error
+                    let _ = stdin.write_all(&request).await;
+                    drop(stdin);
+                };
+                let read = async {
+                    let mut output = Vec::new();
+                    stdout.read_to_end(&mut output).await.map(|_| output)
+                };
+                let ((), output, ()) = tokio::join!(write, read, copy_lines(stderr, &ctx.log));
+                let status = child.wait().await?;
+                output.map(|output| (status, output))
+            } => Some(result),
+            _ = ctx.cancel.cancelled() => None,
+        };
+
+        let Some(result) = result else {
+            child.kill().await?;
+            anyhow::bail!("build cancelled");
+        };
+        let (status, output) = result
+            .map_err(|e| anyhow::anyhow!("Failed to run builder plugin {}: {e}", self.name))?;
+
+        if !status.success() {
+            anyhow::bail!(
+                "Builder plugin {} failed: {status} (see the build logs for details)",
+                self.name
+            );
+        }
+
+        let image: Image = serde_json::from_slice(&output).map_err(|e| {
+            anyhow::anyhow!(
+                "Builder plugin {} returned an invalid image: {e}",
+                self.name
+            )
+        })?;
+        if image.reference.trim().is_empty() {
+            anyhow::bail!(
+                "Builder plugin {} returned an image without a reference",
+                self.name
+            );
+        }
+
+        Ok(image)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::builders::log::BuildLog;
+
+    /// Writes an executable `nimble-builder-test` shell script into `dir`.
+    fn write_plugin(dir: &Path, script: &str) {
+        let path = dir.join(format!("{PLUGIN_EXECUTABLE_PREFIX}test"));
+        std::fs::write(&path, format!("#!/bin/sh\n{script}")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Runs the `test` plugin from `plugin_path` on an empty workspace,
+    /// returning its result, the workspace and the build log.
+    async fn run_plugin(
+        plugin_path: Vec<PathBuf>,
+    ) -> (anyhow::Result<Image>, tempfile::TempDir, String) {
+        let workspace = tempfile::tempdir().unwrap();
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_path = log_dir.path().join("build.log");
+        let ctx = BuildContext {
+            log: BuildLog::create(&log_path).await.unwrap(),
+            no_cache: true,
+            ..Default::default()
+        };
+
+        let result = PluginBuilder::new("test", plugin_path)
+            .build(workspace.path(), "app", "v1", &ctx)
+            .await;
+        let log = std::fs::read_to_string(log_path).unwrap();
+        (result, workspace, log)
+    }
+
+    #[tokio::test]
+    async fn plugin_gets_the_request_and_returns_the_image() {
+        let plugins = tempfile::tempdir().unwrap();
+        write_plugin(
+            plugins.path(),
+            "cat > request.json\n\
+             echo 'building app' >&2\n\
+             echo '{\"reference\": \"app:v1\", \"digest\": \"sha256:abc\"}'\n",
+        );
+
+        let (result, workspace, log) = run_plugin(vec![plugins.path().to_path_buf()]).await;
+
+        assert_eq!(
+            result.unwrap(),
+            Image {
+                reference: "app:v1".to_string(),
+                digest: Some("sha256:abc".to_string()),
+            }
+        );
+        // It's run in the workspace, with the request on stdin
+        let request: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(workspace.path().join("request.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            request,
+            serde_json::json!({
+                "workspace": workspace.path(),
+                "image_name": "app",
+                "image_tag": "v1",
+                "no_cache": true,
+            })
+        );
+        // Its stderr goes to the build log
+        assert!(log.contains("==> Running "), "{log}");
+        assert!(log.contains("building app\n"), "{log}");
+    }
+
+    #[tokio::test]
+    async fn plugin_is_looked_up_in_plugin_path_order() {
+        let empty = tempfile::tempdir().unwrap();
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        write_plugin(first.path(), "echo '{\"reference\": \"first\"}'\n");
+        write_plugin(second.path(), "echo '{\"reference\": \"second\"}'\n");
+
+        let plugin_path = [&empty, &first, &second]
+            .iter()
+            .map(|dir| dir.path().to_path_buf())
+            .collect();
+        let (result, _, _) = run_plugin(plugin_path).await;
+        assert_eq!(result.unwrap(), Image::new("first"));
+    }
+
+    #[tokio::test]
+    async fn missing_plugin_is_an_error() {
+        let empty = tempfile::tempdir().unwrap();
+        let (result, _, _) = run_plugin(vec![empty.path().to_path_buf()]).await;
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("nimble-builder-test not found"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn plugin_failure_is_an_error() {
+        let plugins = tempfile::tempdir().unwrap();
+        write_plugin(plugins.path(), "echo 'compile error' >&2\nexit 3\n");
+
+        let (result, _, log) = run_plugin(vec![plugins.path().to_path_buf()]).await;
+        let err = result.unwrap_err().to_string();
+        assert!(err.starts_with("Builder plugin test failed"), "{err}");
+        assert!(log.contains("compile error\n"), "{log}");
+    }
+
+    #[tokio::test]
+    async fn invalid_image_output_is_an_error() {
+        for output in [
+            "not json",
+            "{\"digest\": \"sha256:abc\"}",
+            "{\"reference\": \" \"}",
+        ] {
+            let plugins = tempfile::tempdir().unwrap();
+            write_plugin(plugins.path(), &format!("echo '{output}'\n"));
+
+            let (result, _, _) = run_plugin(vec![plugins.path().to_path_buf()]).await;
+            let err = result.unwrap_err().to_string();
+            assert!(err.starts_with("Builder plugin test returned"), "{err}");
+        }
+    }
+}
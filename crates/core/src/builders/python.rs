@@ -0,0 +1,525 @@
+use std::{fmt::Write, path::Path};
+
+use toml::Table;
+
+use crate::{
+    builders::{BuildContext, Builder, Image, docker::build_generated},
+    config::PythonConfig,
+};
+
+/// Python version used if the project doesn't pin one (the latest release)
+const DEFAULT_PYTHON_VERSION: &str = "3";
+
+/// Image uv is copied from, for projects managed with uv
+const UV_IMAGE: &str = "ghcr.io/astral-sh/uv:latest";
+
+/// Builds a Python application into an image that runs as a non-root user.
+pub struct PythonBuilder {
+    config: PythonConfig,
+}
+
+impl PythonBuilder {
+    pub fn new(config: PythonConfig) -> Self {
+        PythonBuilder { config }
+    }
+}
+
+impl Default for PythonBuilder {
+    fn default() -> Self {
+        Self::new(PythonConfig::default())
+    }
+}
+
+/// Tool the project's dependencies are installed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Installer {
+    /// pip, from requirements.txt
+    Pip,
+    Poetry,
+    Uv,
+}
+
+impl Installer {
+    /// Picks the installer from the project's lockfile or dependency files.
+    fn detect(build_path: &Path, pyproject: Option<&Table>) -> Option<Self> {
+        let exists = |name: &str| build_path.join(name).exists();
+        let uses_poetry = pyproject
+            .and_then(|pyproject| pyproject.get("tool"))
+            .and_then(|tool| tool.get("poetry"))
+            .is_some();
+
+        if exists("uv.lock") {
+            Some(Installer::Uv)
+        } else if exists("poetry.lock") || uses_poetry {
+            Some(Installer::Poetry)
+        } else if exists("requirements.txt") {
+            Some(Installer::Pip)
+        } else if pyproject.is_some() {
+            Some(Installer::Uv)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Installer::Pip => "pip",
+            Installer::Poetry => "Poetry",
+            Installer::Uv => "uv",
+        }
+    }
+
+    /// Writes the instructions that install the dependencies into the
+    /// virtualenv at /venv. Dependencies are installed before the rest of
+    /// the source is copied, so they're cached until they change.
+    fn write_install(self, dockerfile: &mut String, build_path: &Path) -> std::fmt::Result {
+        let lockfile = |name: &str| {
+            if build_path.join(name).exists() {
+                format!(" {name}")
+            } else {
+                String::new()
+            }
+        };
+
+        match self {
+            Installer::Pip => {
+                writeln!(dockerfile, "COPY requirements.txt ./")?;
+                writeln!(dockerfile, "RUN pip install -r requirements.txt")?;
+                writeln!(dockerfile, "COPY . .")?;
+            }
+            Installer::Poetry => {
+                // Poetry lives in its own virtualenv, and installs into the
+                // app's because VIRTUAL_ENV points at it
+                writeln!(
+                    dockerfile,
+                    "RUN python -m venv /opt/poetry && /opt/poetry/bin/pip install poetry"
+                )?;
+                let lock = lockfile("poetry.lock");
+                writeln!(dockerfile, "COPY pyproject.toml{lock} ./")?;
+                writeln!(
+                    dockerfile,
+                    "RUN /opt/poetry/bin/poetry install --only main --no-root --no-interaction"
+                )?;
+                writeln!(dockerfile, "COPY . .")?;
+                writeln!(
+                    dockerfile,
+                    "RUN /opt/poetry/bin/poetry install --only main --no-interaction"
+                )?;
+            }
+            Installer::Uv => {
+                let lock = lockfile("uv.lock");
+                let locked = if lock.is_empty() { "" } else { " --locked" };
+                writeln!(dockerfile, "COPY --from={UV_IMAGE} /uv /usr/local/bin/uv")?;
+                writeln!(
+                    dockerfile,
+                    "ENV UV_PROJECT_ENVIRONMENT=/venv UV_PYTHON_DOWNLOADS=never UV_LINK_MODE=copy"
+                )?;
+                writeln!(dockerfile, "COPY pyproject.toml{lock} ./")?;
+                writeln!(
+                    dockerfile,
+                    "RUN uv sync --no-dev --no-install-project{locked}"
+                )?;
+                writeln!(dockerfile, "COPY . .")?;
+                writeln!(dockerfile, "RUN uv sync --no-dev{locked}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Builder for PythonBuilder {
+    async fn build(
+        &self,
+        build_path: &Path,
+        image_name: &str,
+        image_tag: &str,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<Image> {
+        let pyproject = match tokio::fs::read_to_string(build_path.join("pyproject.toml")).await {
+            Ok(pyproject) => Some(
+                pyproject
+                    .parse::<Table>()
+                    .map_err(|e| anyhow::anyhow!("Failed to parse pyproject.toml: {e}"))?,
+            ),
+            Err(_) => None,
+        };
+
+        let Some(installer) = Installer::detect(build_path, pyproject.as_ref()) else {
+            anyhow::bail!(
+                "requirements.txt or pyproject.toml not found in build directory: {}",
+                build_path.display()
+            );
+        };
+
+        let command = match &self.config.command {
+            Some(command) => serde_json::to_string(command)?,
+            None => {
+                let Some(web) = procfile_web(build_path).await else {
+                    anyhow::bail!(
+                        "No start command: set builder.command or add a web process to a Procfile"
+                    );
+                };
+                // Procfile commands are run by a shell, and often use $PORT
+                serde_json::to_string(&["/bin/sh", "-c", &format!("exec {web}")])?
+            }
+        };
+
+        let python_version = python_version(build_path, pyproject.as_ref()).await;
+        ctx.log
+            .line(&format!(
+                "==> Using Python {python_version} and {}",
+                installer.name()
+            ))
+            .await;
+
+        let mut dockerfile = String::new();
+        writeln!(dockerfile, "FROM python:{python_version}-slim AS build")?;
+        writeln!(
+            dockerfile,
+            "ENV PIP_NO_CACHE_DIR=1 PIP_DISABLE_PIP_VERSION_CHECK=1 PYTHONDONTWRITEBYTECODE=1"
+        )?;
+        writeln!(dockerfile, "RUN python -m venv /venv")?;
+        writeln!(dockerfile, "ENV VIRTUAL_ENV=/venv PATH=/venv/bin:$PATH")?;
+        writeln!(dockerfile, "WORKDIR /app")?;
+        installer.write_install(&mut dockerfile, build_path)?;
+        writeln!(dockerfile)?;
+        writeln!(dockerfile, "FROM python:{python_version}-slim")?;
+        writeln!(
+            dockerfile,
+            "ENV VIRTUAL_ENV=/venv PATH=/venv/bin:$PATH PYTHONUNBUFFERED=1"
+        )?;
+        writeln!(dockerfile, "RUN useradd --create-home --uid 10001 app")?;
+        writeln!(dockerfile, "WORKDIR /app")?;
+        writeln!(dockerfile, "COPY --from=build /venv /venv")?;
+        writeln!(dockerfile, "COPY --from=build --chown=app:app /app ./")?;
+        writeln!(dockerfile, "USER app")?;
+        writeln!(dockerfile, "CMD {command}")?;
+
+        // A local virtualenv points at the host's interpreter, and bytecode
+        // caches are tied to its Python version
+        let ignore = [".git", ".venv", "__pycache__"];
+        build_generated(
+            build_path,
+            &dockerfile,
+            &ignore,
+            &[],
+            image_name,
+            image_tag,
+            ctx,
+        )
+        .await
+    }
+}
+
+/// Returns the command of the `web` process in the project's Procfile.
+async fn procfile_web(build_path: &Path) -> Option<String> {
+    let procfile = tokio::fs::read_to_string(build_path.join("Procfile"))
+        .await
+        .ok()?;
+
+    procfile.lines().find_map(|line| {
+        let (name, command) = line.split_once(':')?;
+        let command = command.trim();
+        (name.trim() == "web" && !command.is_empty()).then(|| command.to_string())
+    })
+}
+
+/// Picks a `python` image version from the project's .python-version file,
+/// or else the Python requirement in pyproject.toml.
+async fn python_version(build_path: &Path, pyproject: Option<&Table>) -> String {
+    let pinned = tokio::fs::read_to_string(build_path.join(".python-version"))
+        .await
+        .ok()
+        .and_then(|pinned| {
+            pinned
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+        })
+        // The version becomes part of an image tag, so skip anything that
+        // isn't a plain version (e.g. `pypy3.10`)
+        .filter(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'));
+    if let Some(version) = pinned {
+        return version;
+    }
+
+    // PEP 621 projects use `requires-python`, Poetry its own dependency
+    let requirement = pyproject.and_then(|pyproject| {
+        pyproject
+            .get("project")
+            .and_then(|project| project.get("requires-python"))
+            .or_else(|| {
+                pyproject
+                    .get("tool")?
+                    .get("poetry")?
+                    .get("dependencies")?
+                    .get("python")
+            })
+            .and_then(|requirement| requirement.as_str())
+    });
+
+    requirement
+        .and_then(version_from_requirement)
+        .unwrap_or_else(|| DEFAULT_PYTHON_VERSION.to_string())
+}
+
+/// Picks the highest `python` image version meeting a requirement such as
+/// `>=3.9,<3.12` (`3.11`), `~=3.12.1` (`3.12`) or Poetry's `^3.11` (`3`, the
+/// latest 3.x). Returns `None` if nothing bounds the version from above, as
+/// the latest release meets the requirement then, or if no version does.
+fn version_from_requirement(requirement: &str) -> Option<String> {
+    // Poetry separates alternatives with `||`; pick the highest of them
+    let mut best: Option<PythonBound> = None;
+    for alternative in requirement.split('|').filter(|s| !s.trim().is_empty()) {
+        let bound = highest_meeting(alternative)?;
+        best = Some(best.map_or(bound, |best| best.max(bound)));
+    }
+
+    match best? {
+        PythonBound::Major(major) => Some(major.to_string()),
+        PythonBound::Minor(major, minor) => Some(format!("{major}.{minor}")),
+    }
+}
+
+/// The highest version a requirement allows: any release of a major version,
+/// or a particular minor one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PythonBound {
+    Minor(u64, u64),
+    Major(u64),
+}
+
+impl PythonBound {
+    fn key(self) -> (u64, u64) {
+        match self {
+            PythonBound::Minor(major, minor) => (major, minor),
+            PythonBound::Major(major) => (major, u64::MAX),
+        }
+    }
+
+    fn max(self, other: Self) -> Self {
+        if other.key() > self.key() {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn min(self, other: Self) -> Self {
+        if other.key() < self.key() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// Returns the highest version meeting every clause of a requirement, e.g.
+/// `>=3.9,<3.12` or Poetry's `>=3.9 <3.12`, or `None` if there's no upper
+/// bound or no version meets them all.
+fn highest_meeting(requirement: &str) -> Option<PythonBound> {
+    // Clauses are separated by commas or spaces, and operators may be
+    // followed by a space
+    let mut clauses = Vec::new();
+    let mut pending = String::new();
+    for token in requirement
+        .split([',', ' ', '\t'])
+        .filter(|t| !t.is_empty())
+    {
+        pending.push_str(token);
+        if pending.contains(|c: char| c.is_ascii_digit() || c == '*') {
+            clauses.push(std::mem::take(&mut pending));
+        }
+    }
+
+    let mut upper: Option<PythonBound> = None;
+    let mut lower = (0, 0);
+    let mut excluded = Vec::new();
+    for clause in &clauses {
+        let split = clause
+            .find(|c: char| c.is_ascii_digit() || c == '*')
+            .unwrap_or(clause.len());
+        let (op, version) = clause.split_at(split);
+        let wildcard = version.ends_with(".*");
+        let parts = version
+            .trim_end_matches(".*")
+            .split('.')
+            .map(|part| {
+                let digits = part
+                    .chars()
+                    .take_while(char::is_ascii_digit)
+                    .collect::<String>();
+                digits.parse::<u64>().ok()
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let (major, minor, patch) = match parts.as_slice() {
+            [major] => (*major, None, None),
+            [major, minor] => (*major, Some(*minor), None),
+            [major, minor, patch, ..] => (*major, Some(*minor), Some(*patch)),
+            [] => return None,
+        };
+        let at_most = |bound: PythonBound| match minor {
+            Some(minor) => PythonBound::Minor(major, minor).min(bound),
+            None => PythonBound::Major(major).min(bound),
+        };
+
+        let (clause_lower, clause_upper) = match op.trim() {
+            "<" => {
+                let below = match (minor, patch) {
+                    (Some(minor), Some(patch)) if patch > 0 => PythonBound::Minor(major, minor),
+                    (Some(minor), _) if minor > 0 => PythonBound::Minor(major, minor - 1),
+                    _ => PythonBound::Major(major.checked_sub(1)?),
+                };
+                (None, Some(below))
+            }
+            "<=" => (None, Some(at_most(PythonBound::Major(major)))),
+            ">" | ">=" => (Some((major, minor.unwrap_or(0))), None),
+            "!=" => {
+                if let (Some(minor), true) = (minor, wildcard || patch.is_none()) {
+                    // `!=3.12` only excludes 3.12.0, but it's still meant
+                    // to rule that release series out
+                    excluded.push(PythonBound::Minor(major, minor));
+                }
+                (None, None)
+            }
+            // Compatible release: `~=3.12` allows 3.x, `~=3.12.1` only 3.12
+            "~=" => {
+                let upper = match patch {
+                    Some(_) => at_most(PythonBound::Major(major)),
+                    None => PythonBound::Major(major),
+                };
+                (Some((major, minor.unwrap_or(0))), Some(upper))
+            }
+            // Poetry's caret allows changes that don't touch the first
+            // non-zero part, its tilde only patch releases
+            "^" => {
+                let upper = match minor {
+                    Some(minor) if major == 0 => PythonBound::Minor(0, minor),
+                    _ => PythonBound::Major(major),
+                };
+                (Some((major, minor.unwrap_or(0))), Some(upper))
+            }
+            "~" => (
+                Some((major, minor.unwrap_or(0))),
+                Some(at_most(PythonBound::Major(major))),
+            ),
+            // An exact version, which is what Poetry takes a bare one to mean
+            "" | "=" | "==" | "===" => (
+                Some((major, minor.unwrap_or(0))),
+                Some(at_most(PythonBound::Major(major))),
+            ),
+            _ => return None,
+        };
+
+        if let Some(clause_lower) = clause_lower {
+            lower = lower.max(clause_lower);
+        }
+        if let Some(clause_upper) = clause_upper {
+            upper = Some(upper.map_or(clause_upper, |upper| upper.min(clause_upper)));
+        }
+    }
+
+    // Step below excluded release series
+    let mut upper = upper?;
+    while let PythonBound::Minor(major, minor) = upper
+        && excluded.contains(&upper)
+    {
+        upper = PythonBound::Minor(major, minor.checked_sub(1)?);
+    }
+
+    (upper.key() >= lower).then_some(upper)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requirement_picks_the_highest_allowed_version() {
+        for (requirement, expected) in [
+            (">=3.9,<3.12", Some("3.11")),
+            ("<3.12", Some("3.11")),
+            ("<3.12.1", Some("3.12")),
+            ("<=3.12", Some("3.12")),
+            (">= 3.9, < 3.12", Some("3.11")),
+            (">=3.9 <3.12", Some("3.11")),
+            (">=3.9,<4", Some("3")),
+            ("<3.13,!=3.12.*", Some("3.11")),
+            ("==3.12.*", Some("3.12")),
+            ("==3.12.4", Some("3.12")),
+            ("3.11", Some("3.11")),
+            ("~=3.12.1", Some("3.12")),
+            ("~=3.12", Some("3")),
+            ("^3.11", Some("3")),
+            ("~3.11", Some("3.11")),
+            ("~3.11 || ~3.12", Some("3.12")),
+            (">=3.10", None),
+            (">3.10,!=3.11.*", None),
+            ("^3.11 || >=3.9", None),
+            // Nothing meets this
+            (">=3.12,<3.12", None),
+        ] {
+            assert_eq!(
+                version_from_requirement(requirement).as_deref(),
+                expected,
+                "{requirement}"
+            );
+        }
+    }
+
+    async fn version_of(files: &[(&str, &str)]) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+        }
+        let pyproject = files
+            .iter()
+            .find(|(name, _)| *name == "pyproject.toml")
+            .map(|(_, contents)| contents.parse::<Table>().unwrap());
+
+        python_version(dir.path(), pyproject.as_ref()).await
+    }
+
+    #[tokio::test]
+    async fn version_comes_from_python_version_file_first() {
+        let pyproject = "[project]\nrequires-python = \"<3.12\"\n";
+        let files = [
+            (".python-version", "# pinned\n3.10.4\n"),
+            ("pyproject.toml", pyproject),
+        ];
+        assert_eq!(version_of(&files).await, "3.10.4");
+
+        // Interpreters other than CPython can't be used as an image tag
+        let files = [
+            (".python-version", "pypy3.10\n"),
+            ("pyproject.toml", pyproject),
+        ];
+        assert_eq!(version_of(&files).await, "3.11");
+    }
+
+    #[tokio::test]
+    async fn version_comes_from_uv_requires_python() {
+        let pyproject = "[project]\nname = \"app\"\nrequires-python = \">=3.9,<3.13\"\n";
+        assert_eq!(version_of(&[("pyproject.toml", pyproject)]).await, "3.12");
+    }
+
+    #[tokio::test]
+    async fn version_comes_from_poetry_python_dependency() {
+        let pyproject = "[tool.poetry.dependencies]\npython = \"~3.11\"\n";
+        assert_eq!(version_of(&[("pyproject.toml", pyproject)]).await, "3.11");
+    }
+
+    #[tokio::test]
+    async fn version_defaults_to_the_latest_release() {
+        assert_eq!(version_of(&[]).await, DEFAULT_PYTHON_VERSION);
+
+        let pyproject = "[project]\nrequires-python = \">=3.10\"\n";
+        assert_eq!(
+            version_of(&[("pyproject.toml", pyproject)]).await,
+            DEFAULT_PYTHON_VERSION
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use std::{fmt::Write, path::Path};
+
+use toml::Table;
+
+use crate::{
+    builders::{BuildContext, Builder, Image, docker::build_generated},
+    config::RustConfig,
+};
+
+/// Rust version used if the project has no rust-toolchain file (the latest
+/// stable release)
+const DEFAULT_RUST_VERSION: &str = "1";
+
+/// Image the built binary runs in
+const RUNTIME_IMAGE: &str = "alpine:3.20";
+
+/// Builds a Rust crate's binary in release mode into a minimal image.
+pub struct RustBuilder {
+    config: RustConfig,
+}
+
+impl RustBuilder {
+    pub fn new(config: RustConfig) -> Self {
+        RustBuilder { config }
+    }
+
+    /// Determines the binary to build, from nimble.yaml or else from the
+    /// crate's `[[bin]]` targets or package name.
+    fn binary(&self, manifest: &Table) -> anyhow::Result<String> {
+        let binary = match &self.config.binary {
+            Some(binary) => binary.clone(),
+            None => {
+                let bins = manifest
+                    .get("bin")
+                    .and_then(|bins| bins.as_array())
+                    .map(|bins| {
+                        bins.iter()
+                            .filter_map(|bin| bin.get("name")?.as_str())
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                let package = manifest
+                    .get("package")
+                    .and_then(|package| package.get("name"))
+                    .and_then(|name| name.as_str());
+
+                match (bins.as_slice(), package) {
+                    ([bin], _) => bin.to_string(),
+                    ([], Some(package)) => package.to_string(),
+                    ([], None) => anyhow::bail!(
+                        "Cargo.toml has no package to build; set builder.binary in nimble.yaml"
+                    ),
+                    _ => anyhow::bail!(
+                        "Cargo.toml has several binaries ({}); set builder.binary in nimble.yaml",
+                        bins.join(", ")
+                    ),
+                }
+            }
+        };
+
+        // The name ends up in the Dockerfile
+        let valid = !binary.is_empty()
+            && binary
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            anyhow::bail!("Invalid binary name: {binary}");
+        }
+
+        Ok(binary)
+    }
+}
+
+impl Default for RustBuilder {
+    fn default() -> Self {
+        Self::new(RustConfig::default())
+    }
+}
+
+#[async_trait::async_trait]
+impl Builder for RustBuilder {
+    async fn build(
+        &self,
+        build_path: &Path,
+        image_name: &str,
+        image_tag: &str,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<Image> {
+        let manifest_path = build_path.join("Cargo.toml");
+        if !manifest_path.exists() {
+            anyhow::bail!(
+                "Cargo.toml not found in build directory: {}",
+                build_path.display()
+            );
+        }
+
+        let manifest = tokio::fs::read_to_string(&manifest_path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read Cargo.toml: {e}"))?;
+        let manifest = manifest
+            .parse::<Table>()
+            .map_err(|e| anyhow::anyhow!("Failed to parse Cargo.toml: {e}"))?;
+
+        let binary = self.binary(&manifest)?;
+        let rust_version = rust_version(build_path).await;
+        ctx.log
+            .line(&format!(
+                "==> Building binary {binary} with Rust {rust_version}"
+            ))
+            .await;
+
+        let locked = if build_path.join("Cargo.lock").exists() {
+            " --locked"
+        } else {
+            ""
+        };
+
+        // cargo-chef reduces the project to a recipe of its manifests and
+        // lockfile, and builds just the dependencies from it. The recipe only
+        // changes when the dependencies do, so until then the dependencies
+        // come from the layer cache.
+        let mut dockerfile = String::new();
+        writeln!(dockerfile, "FROM rust:{rust_version}-alpine AS chef")?;
+        writeln!(
+            dockerfile,
+            "RUN apk add --no-cache musl-dev && cargo install cargo-chef --locked"
+        )?;
+        writeln!(dockerfile, "WORKDIR /src")?;
+        writeln!(dockerfile)?;
+        writeln!(dockerfile, "FROM chef AS planner")?;
+        writeln!(dockerfile, "COPY . .")?;
+        writeln!(
+            dockerfile,
+            "RUN cargo chef prepare --recipe-path recipe.json"
+        )?;
+        writeln!(dockerfile)?;
+        writeln!(dockerfile, "FROM chef AS build")?;
+        writeln!(
+            dockerfile,
+            "COPY --from=planner /src/recipe.json recipe.json"
+        )?;
+        writeln!(
+            dockerfile,
+            "RUN cargo chef cook --release{locked} --recipe-path recipe.json"
+        )?;
+        writeln!(dockerfile, "COPY . .")?;
+        writeln!(
+            dockerfile,
+            "RUN cargo build --release{locked} --bin {binary}"
+        )?;
+        writeln!(dockerfile)?;
+        writeln!(dockerfile, "FROM {RUNTIME_IMAGE}")?;
+        writeln!(dockerfile, "RUN apk add --no-cache ca-certificates")?;
+        writeln!(
+            dockerfile,
+            "COPY --from=build /src/target/release/{binary} /usr/local/bin/{binary}"
+        )?;
+        writeln!(dockerfile, r#"ENTRYPOINT ["/usr/local/bin/{binary}"]"#)?;
+
+        // A local target directory can run to gigabytes, which every build
+        // would send and then copy into both stages that copy the source
+        let ignore = ["target", ".git"];
+        build_generated(
+            build_path,
+            &dockerfile,
+            &ignore,
+            &[],
+            image_name,
+            image_tag,
+            ctx,
+        )
+        .await
+    }
+}
+
+/// Picks a `rust` image version from the project's rust-toolchain.toml (or
+/// legacy rust-toolchain) file, if it pins a numbered release.
+async fn rust_version(build_path: &Path) -> String {
+    let channel = match tokio::fs::read_to_string(build_path.join("rust-toolchain.toml")).await {
+        Ok(toolchain) => toolchain.parse::<Table>().ok().and_then(|toolchain| {
+            toolchain
+                .get("toolchain")?
+                .get("channel")?
+                .as_str()
+                .map(str::to_string)
+        }),
+        Err(_) => tokio::fs::read_to_string(build_path.join("rust-toolchain"))
+            .await
+            .ok()
+            .map(|channel| channel.trim().to_string()),
+    };
+
+    // Channels like `stable` or `nightly-2024-01-01` have no matching image
+    channel
+        .filter(|channel| {
+            channel.starts_with(|c: char| c.is_ascii_digit())
+                && channel.chars().all(|c| c.is_ascii_digit() || c == '.')
+        })
+        .unwrap_or_else(|| DEFAULT_RUST_VERSION.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(manifest: &str, binary: Option<&str>) -> anyhow::Result<String> {
+        let builder = RustBuilder::new(RustConfig {
+            binary: binary.map(str::to_string),
+        });
+        builder.binary(&manifest.parse::<Table>().unwrap())
+    }
+
+    #[test]
+    fn binary_is_chosen_from_the_manifest_or_override() {
+        for (manifest, override_binary, expected) in [
+            ("[package]\nname = \"app\"\n", None, "app"),
+            (
+                "[package]\nname = \"app\"\n\n[[bin]]\nname = \"server\"\npath = \"src/main.rs\"\n",
+                None,
+                "server",
+            ),
+            (
+                "[package]\nname = \"app\"\n\n[[bin]]\nname = \"server\"\n\n[[bin]]\nname = \"worker\"\n",
+                Some("worker"),
+                "worker",
+            ),
+            ("[package]\nname = \"app\"\n", Some("other"), "other"),
+            // The override doesn't need a package, e.g. for workspaces
+            ("[workspace]\nmembers = [\"app\"]\n", Some("app"), "app"),
+        ] {
+            assert_eq!(
+                binary(manifest, override_binary).unwrap(),
+                expected,
+                "{manifest:?} with {override_binary:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn several_binaries_need_an_override() {
+        let manifest = "[package]\nname = \"app\"\n\n[[bin]]\nname = \"server\"\n\n[[bin]]\nname = \"worker\"\n";
+        let err = binary(manifest, None).unwrap_err().to_string();
+        assert!(err.contains("several binaries (server, worker)"), "{err}");
+        assert!(err.contains("builder.binary"), "{err}");
+    }
+
+    #[test]
+    fn manifest_without_a_package_needs_an_override() {
+        let err = binary("[workspace]\nmembers = [\"app\"]\n", None).unwrap_err();
+        assert!(err.to_string().contains("no package to build"), "{err}");
+    }
+
+    #[test]
+    fn invalid_binary_names_are_rejected() {
+        for (manifest, override_binary) in [
+            ("[package]\nname = \"app\"\n", Some("app && rm -rf /")),
+            ("[package]\nname = \"app\"\n", Some("")),
+            ("[[bin]]\nname = \"bad name\"\n", None),
+        ] {
+            let err = binary(manifest, override_binary).unwrap_err();
+            assert!(err.to_string().starts_with("Invalid binary name"), "{err}");
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    fmt::Write,
+    path::{Component, Path},
+};
+
+use crate::{
+    builders::{
+        BuildContext, Builder, Image,
+        docker::{GENERATED_DOCKERFILE, GENERATED_DOCKERIGNORE, build_generated},
+        node::{PackageManager, node_version, read_package},
+    },
+    config::StaticConfig,
+};
+
+/// Image that serves the site, with nginx running as a non-root user on
+/// port 8080
+const SERVER_IMAGE: &str = "nginxinc/nginx-unprivileged:alpine";
+
+/// Name of the nginx config written into the build directory
+const NGINX_CONFIG: &str = ".nimble.nginx.conf";
+
+/// Builds an image that serves a static site, optionally produced by a build
+/// command.
+pub struct StaticBuilder {
+    config: StaticConfig,
+}
+
+impl StaticBuilder {
+    pub fn new(config: StaticConfig) -> Self {
+        StaticBuilder { config }
+    }
+
+    /// Generates the nginx server config for the site.
+    fn nginx_config(&self) -> Result<String, std::fmt::Error> {
+        let mut conf = String::new();
+
+        // Longest prefix first, since the first matching pattern wins
+        let mut cache = self.config.cache.iter().collect::<Vec<_>>();
+        cache.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        writeln!(conf, "map $uri $cache_control {{")?;
+        writeln!(conf, "    default \"\";")?;
+        for (prefix, header) in cache {
+            let pattern = prefix.replace('.', "\\.");
+            writeln!(conf, "    \"~^{pattern}\" \"{header}\";")?;
+        }
+        writeln!(conf, "}}")?;
+        writeln!(conf)?;
+
+        writeln!(conf, "server {{")?;
+        writeln!(conf, "    listen 8080;")?;
+        writeln!(conf, "    root /usr/share/nginx/html;")?;
+        writeln!(conf, "    index index.html;")?;
+        // Redirects keep the host and port the client used, which differ
+        // from the container's behind the proxy
+        writeln!(conf, "    absolute_redirect off;")?;
+        writeln!(conf, "    add_header Cache-Control $cache_control;")?;
+        writeln!(conf)?;
+        writeln!(conf, "    location ~ /\\.(?!well-known/) {{")?;
+        writeln!(conf, "        return 404;")?;
+        writeln!(conf, "    }}")?;
+        writeln!(conf)?;
+        writeln!(conf, "    location / {{")?;
+        let fallback = if self.config.spa {
+            "/index.html"
+        } else {
+            "=404"
+        };
+        writeln!(conf, "        try_files $uri $uri/ {fallback};")?;
+        writeln!(conf, "    }}")?;
+        if let Some(page) = &self.config.not_found {
+            let page = page.trim_start_matches("./");
+            writeln!(conf)?;
+            writeln!(conf, "    error_page 404 /{page};")?;
+        }
+        writeln!(conf, "}}")?;
+
+        Ok(conf)
+    }
+
+    /// Writes a stage that runs the build command, in the configured image
+    /// or else a Node.js image with the project's packages installed.
+    async fn write_build_stage(
+        &self,
+        dockerfile: &mut String,
+        build_path: &Path,
+        build: &str,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<()> {
+        match &self.config.build_image {
+            Some(image) => {
+                ctx.log
+                    .line(&format!("==> Running `{build}` in {image}"))
+                    .await;
+                writeln!(dockerfile, "FROM {image} AS build")?;
+                writeln!(dockerfile, "WORKDIR /src")?;
+            }
+            None if build_path.join("package.json").exists() => {
+                let package = read_package(build_path).await?;
+                let node_version = node_version(package["engines"]["node"].as_str());
+                let (manager, locked) = PackageManager::detect(build_path, &package);
+                ctx.log
+                    .line(&format!(
+                        "==> Running `{build}` with Node.js {node_version} and {}",
+                        manager.name()
+                    ))
+                    .await;
+                writeln!(dockerfile, "FROM node:{node_version}-alpine AS build")?;
+                writeln!(dockerfile, "WORKDIR /src")?;
+                manager.write_install(dockerfile, build_path, locked)?;
+            }
+            None => {
+                let node_version = node_version(None);
+                ctx.log
+                    .line(&format!(
+                        "==> Running `{build}` with Node.js {node_version}"
+                    ))
+                    .await;
+                writeln!(dockerfile, "FROM node:{node_version}-alpine AS build")?;
+                writeln!(dockerfile, "WORKDIR /src")?;
+            }
+        }
+        writeln!(dockerfile, "COPY . .")?;
+        writeln!(dockerfile, "RUN {build}")?;
+        writeln!(dockerfile)?;
+
+        Ok(())
+    }
+}
+
+impl Default for StaticBuilder {
+    fn default() -> Self {
+        Self::new(StaticConfig::default())
+    }
+}
+
+#[async_trait::async_trait]
+impl Builder for StaticBuilder {
+    async fn build(
+        &self,
+        build_path: &Path,
+        image_name: &str,
+        image_tag: &str,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<Image> {
+        let default_output = if self.config.build.is_some() {
+            "dist"
+        } else {
+            "."
+        };
+        let output = self.config.output.as_deref().unwrap_or(default_output);
+
+        let mut dockerfile = String::new();
+        let mut site = match &self.config.build {
+            Some(build) => {
+                self.write_build_stage(&mut dockerfile, build_path, build, ctx)
+                    .await?;
+                format!("--from=build /src/{output}")
+            }
+            None => {
+                if !build_path.join(output).join("index.html").exists() {
+                    anyhow::bail!("index.html not found in the site directory: {output}");
+                }
+                output.to_string()
+            }
+        };
+
+        // Serving the project root would serve the files written for the
+        // build too, and COPY can't leave them out, so remove them from a
+        // copy of the site first
+        if Path::new(output)
+            .components()
+            .all(|c| c == Component::CurDir)
+        {
+            writeln!(dockerfile, "FROM {SERVER_IMAGE} AS site")?;
+            writeln!(dockerfile, "USER root")?;
+            writeln!(dockerfile, "COPY {site}/ /site/")?;
+            writeln!(
+                dockerfile,
+                "RUN cd /site && rm -f {GENERATED_DOCKERFILE} {GENERATED_DOCKERIGNORE} {NGINX_CONFIG}"
+            )?;
+            writeln!(dockerfile)?;
+            site = "--from=site /site".to_string();
+        }
+
+        tokio::fs::write(build_path.join(NGINX_CONFIG), self.nginx_config()?)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to write nginx config: {e}"))?;
+
+        writeln!(dockerfile, "FROM {SERVER_IMAGE}")?;
+        writeln!(
+            dockerfile,
+            "COPY {NGINX_CONFIG} /etc/nginx/conf.d/default.conf"
+        )?;
+        writeln!(dockerfile, "COPY {site}/ /usr/share/nginx/html/")?;
+
+        let ignore = [".git", "node_modules", "nimble.yaml"];
+        build_generated(
+            build_path,
+            &dockerfile,
+            &ignore,
+            &[NGINX_CONFIG],
+            image_name,
+            image_tag,
+            ctx,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::runtime::{context::write_context, fake::FakeRuntime};
+
+    /// Builds the site in `dir` with the fake runtime, returning the
+    /// generated Dockerfile and the paths sent in the build context.
+    async fn build(dir: &Path, config: StaticConfig) -> (String, Vec<String>) {
+        let ctx = BuildContext {
+            runtime: Arc::new(FakeRuntime::new()),
+            ..Default::default()
+        };
+        StaticBuilder::new(config)
+            .build(dir, "site", "latest", &ctx)
+            .await
+            .unwrap();
+
+        let dockerfile = dir.join(GENERATED_DOCKERFILE);
+        let mut archive = Vec::new();
+        write_context(dir, &dockerfile, &mut archive).unwrap();
+        let sent = tar::Archive::new(archive.as_slice())
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect();
+
+        (std::fs::read_to_string(dockerfile).unwrap(), sent)
+    }
+
+    #[tokio::test]
+    async fn site_at_project_root_leaves_out_builder_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<h1>Hi</h1>").unwrap();
+        // The project's own ignore file can't keep out the nginx config
+        std::fs::write(dir.path().join(".dockerignore"), ".nimble.*\n").unwrap();
+
+        let (dockerfile, sent) = build(dir.path(), StaticConfig::default()).await;
+
+        assert!(sent.contains(&NGINX_CONFIG.to_string()), "{sent:?}");
+        assert!(sent.contains(&"index.html".to_string()), "{sent:?}");
+        assert!(
+            !sent.contains(&GENERATED_DOCKERIGNORE.to_string()),
+            "{sent:?}"
+        );
+        assert!(
+            dockerfile.contains(&format!(
+                "COPY ./ /site/\nRUN cd /site && rm -f {GENERATED_DOCKERFILE} \
+                 {GENERATED_DOCKERIGNORE} {NGINX_CONFIG}\n"
+            )),
+            "{dockerfile}"
+        );
+        assert!(
+            dockerfile.ends_with("COPY --from=site /site/ /usr/share/nginx/html/\n"),
+            "{dockerfile}"
+        );
+    }
+
+    #[tokio::test]
+    async fn site_in_subdirectory_is_copied_directly() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("public")).unwrap();
+        std::fs::write(dir.path().join("public/index.html"), "<h1>Hi</h1>").unwrap();
+        let config = StaticConfig {
+            output: Some("public".to_string()),
+            ..Default::default()
+        };
+
+        let (dockerfile, sent) = build(dir.path(), config).await;
+
+        assert!(sent.contains(&NGINX_CONFIG.to_string()), "{sent:?}");
+        assert!(!dockerfile.contains("AS site"), "{dockerfile}");
+        assert!(
+            dockerfile.ends_with("COPY public/ /usr/share/nginx/html/\n"),
+            "{dockerfile}"
+        );
+    }
+
+    #[tokio::test]
+    async fn built_site_at_build_root_leaves_out_builder_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StaticConfig {
+            build: Some("./generate.sh".to_string()),
+            build_image: Some("alpine:3".to_string()),
+            output: Some(".".to_string()),
+            ..Default::default()
+        };
+
+        let (dockerfile, _) = build(dir.path(), config).await;
+
+        assert!(
+            dockerfile.contains("COPY --from=build /src/./ /site/\n"),
+            "{dockerfile}"
+        );
+        assert!(
+            dockerfile.ends_with("COPY --from=site /site/ /usr/share/nginx/html/\n"),
+            "{dockerfile}"
+        );
+    }
+}
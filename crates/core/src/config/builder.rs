@@ -0,0 +1,630 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Component, Path},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::Value;
+
+use crate::config::{
+    ConfigError, as_bool, as_mapping, as_str, check_keys, key_path, scalar_to_string, string_list,
+};
+
+/// Prefix of builder types that name an external builder plugin
+const PLUGIN_PREFIX: &str = "plugin:";
+
+/// Builder type for building the application.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuilderType {
+    /// Use a Dockerfile to build the application
+    Dockerfile,
+    /// Use the Go builder
+    Go,
+    /// Use the Node.js builder
+    Node,
+    /// Use the Python builder
+    Python,
+    /// Use the Rust builder
+    Rust,
+    /// Use the static site builder
+    Static,
+    /// Use an external builder plugin, run as `nimble-builder-<name>`
+    Plugin(String),
+}
+
+impl fmt::Display for BuilderType {
+    /// Formats the builder type as written in nimble.yaml.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuilderType::Dockerfile => f.write_str("dockerfile"),
+            BuilderType::Go => f.write_str("go"),
+            BuilderType::Node => f.write_str("node"),
+            BuilderType::Python => f.write_str("python"),
+            BuilderType::Rust => f.write_str("rust"),
+            BuilderType::Static => f.write_str("static"),
+            BuilderType::Plugin(name) => write!(f, "{PLUGIN_PREFIX}{name}"),
+        }
+    }
+}
+
+impl FromStr for BuilderType {
+    type Err = ConfigError;
+
+    /// Parses a BuilderType from a string.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - String representation of the builder type (case-insensitive),
+    ///   or `plugin:<name>` for a builder plugin
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the string doesn't match any known builder type,
+    /// or names a plugin with characters other than letters, digits, `-` and
+    /// `_`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        if let Some(name) = lower.strip_prefix(PLUGIN_PREFIX) {
+            // The name becomes part of an executable's file name
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(ConfigError::InvalidBuilder(s.to_string()));
+            }
+            return Ok(BuilderType::Plugin(name.to_string()));
+        }
+
+        match lower.as_str() {
+            "dockerfile" => Ok(BuilderType::Dockerfile),
+            "go" => Ok(BuilderType::Go),
+            "node" => Ok(BuilderType::Node),
+            "python" => Ok(BuilderType::Python),
+            "rust" => Ok(BuilderType::Rust),
+            "static" => Ok(BuilderType::Static),
+            _ => Err(ConfigError::InvalidBuilder(s.to_string())),
+        }
+    }
+}
+
+impl Serialize for BuilderType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BuilderType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        BuilderType::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The builder to build the project with, and its settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuilderConfig {
+    /// Build from the project's Dockerfile
+    Dockerfile(DockerfileConfig),
+    /// Build a Go module
+    Go(GoConfig),
+    /// Build a Node.js package
+    Node,
+    /// Build a Python application
+    Python(PythonConfig),
+    /// Build a Rust crate
+    Rust(RustConfig),
+    /// Serve a static site
+    Static(StaticConfig),
+    /// Build with the named builder plugin
+    Plugin(String),
+}
+
+/// Keys allowed in the long form of `builder`, for every builder type
+const BUILDER_KEYS: &[&str] = &["type"];
+
+impl BuilderConfig {
+    /// Creates a BuilderConfig for the given builder type with default settings.
+    pub fn new(builder_type: BuilderType) -> Self {
+        match builder_type {
+            BuilderType::Dockerfile => BuilderConfig::Dockerfile(DockerfileConfig::default()),
+            BuilderType::Go => BuilderConfig::Go(GoConfig::default()),
+            BuilderType::Node => BuilderConfig::Node,
+            BuilderType::Python => BuilderConfig::Python(PythonConfig::default()),
+            BuilderType::Rust => BuilderConfig::Rust(RustConfig::default()),
+            BuilderType::Static => BuilderConfig::Static(StaticConfig::default()),
+            BuilderType::Plugin(name) => BuilderConfig::Plugin(name),
+        }
+    }
+
+    /// Returns the type of builder this configures.
+    pub fn builder_type(&self) -> BuilderType {
+        match self {
+            BuilderConfig::Dockerfile(_) => BuilderType::Dockerfile,
+            BuilderConfig::Go(_) => BuilderType::Go,
+            BuilderConfig::Node => BuilderType::Node,
+            BuilderConfig::Python(_) => BuilderType::Python,
+            BuilderConfig::Rust(_) => BuilderType::Rust,
+            BuilderConfig::Static(_) => BuilderType::Static,
+            BuilderConfig::Plugin(name) => BuilderType::Plugin(name.clone()),
+        }
+    }
+
+    pub(crate) fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        if let Value::String(builder_type) = value {
+            return Ok(Self::new(BuilderType::from_str(builder_type)?));
+        }
+
+        let map = as_mapping(value, key)?;
+        let sub = |name: &str| key_path(Some(key), name);
+
+        let builder_type = map
+            .get("type")
+            .ok_or_else(|| ConfigError::MissingField(sub("type")))
+            .and_then(|v| as_str(v, &sub("type")))
+            .and_then(BuilderType::from_str)?;
+
+        match builder_type {
+            BuilderType::Node | BuilderType::Plugin(_) => {
+                check_keys(map, BUILDER_KEYS, Some(key))?;
+                Ok(Self::new(builder_type))
+            }
+            BuilderType::Dockerfile => Ok(BuilderConfig::Dockerfile(DockerfileConfig::from_value(
+                value, key,
+            )?)),
+            BuilderType::Go => Ok(BuilderConfig::Go(GoConfig::from_value(value, key)?)),
+            BuilderType::Python => Ok(BuilderConfig::Python(PythonConfig::from_value(value, key)?)),
+            BuilderType::Rust => Ok(BuilderConfig::Rust(RustConfig::from_value(value, key)?)),
+            BuilderType::Static => Ok(BuilderConfig::Static(StaticConfig::from_value(value, key)?)),
+        }
+    }
+}
+
+/// Settings for the Dockerfile builder.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DockerfileConfig {
+    /// Dockerfile to build, relative to the project root. Defaults to
+    /// `Dockerfile` in the build context.
+    pub dockerfile: Option<String>,
+    /// Directory sent as the build context, relative to the project root.
+    /// Defaults to the root itself.
+    pub context: Option<String>,
+    /// Stage of a multi-stage Dockerfile to build, passed to `--target`
+    pub target: Option<String>,
+    /// Build arguments, passed to `--build-arg`
+    pub build_args: BTreeMap<String, String>,
+    /// Secrets mounted into the build with `RUN --mount=type=secret`, as a
+    /// map from secret ID to the name of the app's secret env var holding
+    /// the value
+    pub build_secrets: BTreeMap<String, String>,
+}
+
+const DOCKERFILE_KEYS: &[&str] = &[
+    "type",
+    "dockerfile",
+    "context",
+    "target",
+    "build_args",
+    "build_secrets",
+];
+
+impl DockerfileConfig {
+    fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        let map = as_mapping(value, key)?;
+        check_keys(map, DOCKERFILE_KEYS, Some(key))?;
+        let sub = |name: &str| key_path(Some(key), name);
+        let invalid = |msg: String| ConfigError::InvalidBuilderConfig(msg);
+
+        let dockerfile = map
+            .get("dockerfile")
+            .map(|v| as_str(v, &sub("dockerfile")))
+            .transpose()?
+            .map(|dockerfile| relative_path(dockerfile, &sub("dockerfile")))
+            .transpose()?;
+
+        let context = map
+            .get("context")
+            .map(|v| as_str(v, &sub("context")))
+            .transpose()?
+            .map(|context| relative_path(context, &sub("context")))
+            .transpose()?;
+
+        let target = map
+            .get("target")
+            .map(|v| as_str(v, &sub("target")))
+            .transpose()?
+            .map(|target| {
+                if target.is_empty() || target.contains(char::is_whitespace) {
+                    Err(invalid(format!(
+                        "{} is not a build stage: {target}",
+                        sub("target")
+                    )))
+                } else {
+                    Ok(target.to_string())
+                }
+            })
+            .transpose()?;
+
+        let mut build_args = BTreeMap::new();
+        if let Some(value) = map.get("build_args") {
+            for (name, value) in as_mapping(value, &sub("build_args"))? {
+                let name = as_str(name, &sub("build_args"))?;
+                let value = scalar_to_string(value, &key_path(Some(&sub("build_args")), name))?;
+                if name.is_empty() || name.contains(['=', ' ']) {
+                    return Err(invalid(format!(
+                        "invalid build argument name in {}: {name}",
+                        sub("build_args")
+                    )));
+                }
+                build_args.insert(name.to_string(), value);
+            }
+        }
+
+        // Either a list of env var names, each used as its own secret ID, or
+        // a map from secret ID to env var name
+        let mut build_secrets = BTreeMap::new();
+        if let Some(value) = map.get("build_secrets") {
+            let secrets = match value {
+                Value::Mapping(secrets) => secrets
+                    .iter()
+                    .map(|(id, name)| {
+                        let id = as_str(id, &sub("build_secrets"))?;
+                        let name = as_str(name, &key_path(Some(&sub("build_secrets")), id))?;
+                        Ok((id.to_string(), name.to_string()))
+                    })
+                    .collect::<Result<Vec<_>, ConfigError>>()?,
+                _ => string_list(value, &sub("build_secrets"))?
+                    .into_iter()
+                    .map(|name| (name.clone(), name))
+                    .collect(),
+            };
+            for (id, name) in secrets {
+                // Both end up in `--secret id=<id>,env=<name>`
+                let valid = |s: &str| {
+                    !s.is_empty()
+                        && s.chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+                };
+                if !valid(&id) || !valid(&name) {
+                    return Err(invalid(format!(
+                        "invalid build secret in {}: {id}: {name}",
+                        sub("build_secrets")
+                    )));
+                }
+                build_secrets.insert(id, name);
+            }
+        }
+
+        Ok(DockerfileConfig {
+            dockerfile,
+            context,
+            target,
+            build_args,
+            build_secrets,
+        })
+    }
+}
+
+/// Settings for the Go builder.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoConfig {
+    /// Directory of the `main` package to build, relative to the project
+    /// root. Defaults to the root itself.
+    pub main: Option<String>,
+    /// Build tags, passed to `go build -tags`
+    pub tags: Vec<String>,
+    /// Linker flags, passed to `go build -ldflags`
+    pub ldflags: Option<String>,
+    /// Whether to build with cgo enabled. Defaults to off, which produces a
+    /// static binary.
+    pub cgo: bool,
+}
+
+const GO_KEYS: &[&str] = &["type", "main", "tags", "ldflags", "cgo"];
+
+impl GoConfig {
+    fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        let map = as_mapping(value, key)?;
+        check_keys(map, GO_KEYS, Some(key))?;
+        let sub = |name: &str| key_path(Some(key), name);
+
+        let main = map
+            .get("main")
+            .map(|v| as_str(v, &sub("main")))
+            .transpose()?
+            .map(|main| relative_path(main, &sub("main")))
+            .transpose()?;
+
+        let tags = map
+            .get("tags")
+            .map(|v| string_list(v, &sub("tags")))
+            .transpose()?
+            .unwrap_or_default();
+
+        let ldflags = map
+            .get("ldflags")
+            .map(|v| as_str(v, &sub("ldflags")))
+            .transpose()?
+            .map(str::to_string);
+
+        let cgo = map
+            .get("cgo")
+            .map(|v| as_bool(v, &sub("cgo")))
+            .transpose()?
+            .unwrap_or(false);
+
+        Ok(GoConfig {
+            main,
+            tags,
+            ldflags,
+            cgo,
+        })
+    }
+}
+
+/// Settings for the Python builder.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PythonConfig {
+    /// Command that starts the app. Defaults to the `web` process in the
+    /// project's Procfile.
+    pub command: Option<Vec<String>>,
+}
+
+const PYTHON_KEYS: &[&str] = &["type", "command"];
+
+impl PythonConfig {
+    fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        let map = as_mapping(value, key)?;
+        check_keys(map, PYTHON_KEYS, Some(key))?;
+
+        let command = map
+            .get("command")
+            .map(|v| string_list(v, &key_path(Some(key), "command")))
+            .transpose()?;
+
+        Ok(PythonConfig { command })
+    }
+}
+
+/// Settings for the Rust builder.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustConfig {
+    /// Binary target to build and run. Defaults to the crate's only binary.
+    pub binary: Option<String>,
+}
+
+const RUST_KEYS: &[&str] = &["type", "binary"];
+
+impl RustConfig {
+    fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        let map = as_mapping(value, key)?;
+        check_keys(map, RUST_KEYS, Some(key))?;
+
+        let binary = map
+            .get("binary")
+            .map(|v| as_str(v, &key_path(Some(key), "binary")))
+            .transpose()?
+            .map(str::to_string);
+
+        Ok(RustConfig { binary })
+    }
+}
+
+/// Settings for the static site builder.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticConfig {
+    /// Shell command that builds the site, e.g. `npm run build`
+    pub build: Option<String>,
+    /// Image the build command runs in. Defaults to a Node.js image.
+    pub build_image: Option<String>,
+    /// Directory of the site to serve, relative to the project root.
+    /// Defaults to `dist` if there's a build command, else the root itself.
+    pub output: Option<String>,
+    /// Serve index.html for paths that don't match a file, for single-page
+    /// apps that do their own routing
+    pub spa: bool,
+    /// Page served for paths that don't match a file, relative to the output
+    /// directory
+    pub not_found: Option<String>,
+    /// Cache-Control header values, by path prefix
+    pub cache: BTreeMap<String, String>,
+}
+
+const STATIC_KEYS: &[&str] = &[
+    "type",
+    "build",
+    "build_image",
+    "output",
+    "spa",
+    "not_found",
+    "cache",
+];
+
+impl StaticConfig {
+    fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        let map = as_mapping(value, key)?;
+        check_keys(map, STATIC_KEYS, Some(key))?;
+        let sub = |name: &str| key_path(Some(key), name);
+        let invalid = |msg: String| ConfigError::InvalidBuilderConfig(msg);
+
+        let build = map
+            .get("build")
+            .map(|v| as_str(v, &sub("build")))
+            .transpose()?
+            .map(|build| {
+                if build.contains('\n') {
+                    Err(invalid(format!("{} must be a single line", sub("build"))))
+                } else {
+                    Ok(build.to_string())
+                }
+            })
+            .transpose()?;
+
+        let build_image = map
+            .get("build_image")
+            .map(|v| as_str(v, &sub("build_image")))
+            .transpose()?
+            .map(|image| {
+                if image.is_empty() || image.contains(char::is_whitespace) {
+                    Err(invalid(format!(
+                        "{} is not an image: {image}",
+                        sub("build_image")
+                    )))
+                } else {
+                    Ok(image.to_string())
+                }
+            })
+            .transpose()?;
+
+        let output = map
+            .get("output")
+            .map(|v| as_str(v, &sub("output")))
+            .transpose()?
+            .map(|output| relative_path(output, &sub("output")))
+            .transpose()?;
+
+        let spa = map
+            .get("spa")
+            .map(|v| as_bool(v, &sub("spa")))
+            .transpose()?
+            .unwrap_or(false);
+
+        let not_found = map
+            .get("not_found")
+            .map(|v| as_str(v, &sub("not_found")))
+            .transpose()?
+            .map(|page| relative_path(page, &sub("not_found")))
+            .transpose()?;
+
+        let mut cache = BTreeMap::new();
+        if let Some(value) = map.get("cache") {
+            for (prefix, header) in as_mapping(value, &sub("cache"))? {
+                let prefix = as_str(prefix, &sub("cache"))?;
+                let header = as_str(header, &key_path(Some(&sub("cache")), prefix))?;
+
+                // Both end up in the web server's config file
+                let valid_prefix = prefix.starts_with('/')
+                    && prefix
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "/-_.".contains(c));
+                if !valid_prefix {
+                    return Err(invalid(format!(
+                        "{} keys must be URL paths starting with '/': {prefix}",
+                        sub("cache")
+                    )));
+                }
+                if header.contains(|c: char| "\"\\;${}".contains(c) || c.is_control()) {
+                    return Err(invalid(format!(
+                        "invalid Cache-Control value for {prefix}: {header}"
+                    )));
+                }
+
+                cache.insert(prefix.to_string(), header.to_string());
+            }
+        }
+
+        Ok(StaticConfig {
+            build,
+            build_image,
+            output,
+            spa,
+            not_found,
+            cache,
+        })
+    }
+}
+
+/// Checks that a path stays inside the project, i.e. is relative and
+/// doesn't contain `..`.
+fn relative_path(path: &str, key: &str) -> Result<String, ConfigError> {
+    let inside = Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    if inside {
+        Ok(path.to_string())
+    } else {
+        Err(ConfigError::InvalidBuilderConfig(format!(
+            "{key} must be a path inside the project: {path}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NimbleConfig;
+
+    fn builder(yaml: &str) -> Result<BuilderConfig, ConfigError> {
+        NimbleConfig::from_str(yaml).map(|config| config.builder.unwrap())
+    }
+
+    #[test]
+    fn short_form_uses_defaults() {
+        for (name, builder_type) in [
+            ("dockerfile", BuilderType::Dockerfile),
+            ("go", BuilderType::Go),
+            ("node", BuilderType::Node),
+            ("python", BuilderType::Python),
+            ("rust", BuilderType::Rust),
+            ("static", BuilderType::Static),
+            ("plugin:hugo", BuilderType::Plugin("hugo".to_string())),
+        ] {
+            let config = builder(&format!("builder: {name}")).unwrap();
+            assert_eq!(config, BuilderConfig::new(builder_type.clone()));
+            assert_eq!(config.builder_type(), builder_type);
+            // The long form without settings is the same
+            assert_eq!(builder(&format!("builder:\n  type: {name}\n")), Ok(config));
+        }
+    }
+
+    #[test]
+    fn dockerfile_settings_are_parsed() {
+        let config = builder(
+            "builder:\n  type: dockerfile\n  dockerfile: docker/Dockerfile.prod\n  context: ./app\n  \
+             target: release\n  build_args:\n    VERSION: 1.2\n  build_secrets: [NPM_TOKEN]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            BuilderConfig::Dockerfile(DockerfileConfig {
+                dockerfile: Some("docker/Dockerfile.prod".to_string()),
+                context: Some("./app".to_string()),
+                target: Some("release".to_string()),
+                build_args: BTreeMap::from([("VERSION".to_string(), "1.2".to_string())]),
+                build_secrets: BTreeMap::from([("NPM_TOKEN".to_string(), "NPM_TOKEN".to_string())]),
+            })
+        );
+    }
+
+    #[test]
+    fn build_secrets_map_ids_to_env_vars() {
+        let BuilderConfig::Dockerfile(config) =
+            builder("builder:\n  type: dockerfile\n  build_secrets:\n    npm_token: NPM_TOKEN\n")
+                .unwrap()
+        else {
+            panic!("expected the dockerfile builder");
+        };
+
+        assert_eq!(config.build_secrets["npm_token"], "NPM_TOKEN");
+    }
+
+    #[test]
+    fn invalid_dockerfile_settings_are_rejected() {
+        for settings in [
+            "build_args:\n    'A=B': c",
+            "build_secrets: ['token=x']",
+            "build_secrets:\n    id: 'not valid'",
+        ] {
+            let err =
+                builder(&format!("builder:\n  type: dockerfile\n  {settings}\n")).unwrap_err();
+            assert!(
+                matches!(err, ConfigError::InvalidBuilderConfig(_)),
+                "{settings}: {err:?}"
+            );
+        }
+    }
+}
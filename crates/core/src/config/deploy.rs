@@ -0,0 +1,620 @@
+use std::{collections::BTreeMap, fmt, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::config::{
+    ConfigError, as_mapping, as_sequence, as_str, as_u32, check_keys, key_path, parse_duration,
+    scalar_to_string, string_list,
+};
+
+/// Target environment to deploy the built image to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeployTarget {
+    /// Run the image as a container on the agent's local Docker engine
+    #[serde(rename = "local:docker")]
+    LocalDocker,
+}
+
+impl DeployTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeployTarget::LocalDocker => "local:docker",
+        }
+    }
+}
+
+impl fmt::Display for DeployTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DeployTarget {
+    type Err = ConfigError;
+
+    /// Parses a DeployTarget from a `<location>:<runtime>` string (case-insensitive).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local:docker" => Ok(DeployTarget::LocalDocker),
+            _ => Err(ConfigError::InvalidDeployTarget(s.to_string())),
+        }
+    }
+}
+
+/// Transport protocol of a published port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+/// A container port to publish, optionally bound to a fixed host port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortMapping {
+    /// Port the application listens on inside the container
+    pub container: u16,
+    /// Host port to bind; if unset, the runtime picks a free port
+    pub host: Option<u16>,
+    pub protocol: Protocol,
+}
+
+impl FromStr for PortMapping {
+    type Err = ConfigError;
+
+    /// Parses a Docker-style port spec: `<container>`, `<host>:<container>`,
+    /// optionally suffixed with `/tcp` or `/udp`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConfigError::InvalidPort(s.to_string());
+        let parse_port = |p: &str| {
+            p.parse::<u16>()
+                .ok()
+                .filter(|p| *p != 0)
+                .ok_or_else(invalid)
+        };
+
+        let (ports, protocol) = match s.split_once('/') {
+            Some((ports, "tcp")) => (ports, Protocol::Tcp),
+            Some((ports, "udp")) => (ports, Protocol::Udp),
+            Some(_) => return Err(invalid()),
+            None => (s, Protocol::Tcp),
+        };
+
+        let (host, container) = match ports.split_once(':') {
+            Some((host, container)) => (Some(parse_port(host)?), parse_port(container)?),
+            None => (None, parse_port(ports)?),
+        };
+
+        Ok(PortMapping {
+            container,
+            host,
+            protocol,
+        })
+    }
+}
+
+/// Restart policy for deployed containers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    No,
+    Always,
+    OnFailure {
+        max_retries: Option<u32>,
+    },
+    #[default]
+    UnlessStopped,
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPolicy::No => write!(f, "no"),
+            RestartPolicy::Always => write!(f, "always"),
+            RestartPolicy::OnFailure { max_retries: None } => write!(f, "on-failure"),
+            RestartPolicy::OnFailure {
+                max_retries: Some(n),
+            } => write!(f, "on-failure:{n}"),
+            RestartPolicy::UnlessStopped => write!(f, "unless-stopped"),
+        }
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConfigError::InvalidRestartPolicy(s.to_string());
+
+        match s.to_lowercase().as_str() {
+            "no" => Ok(RestartPolicy::No),
+            "always" => Ok(RestartPolicy::Always),
+            "on-failure" => Ok(RestartPolicy::OnFailure { max_retries: None }),
+            "unless-stopped" => Ok(RestartPolicy::UnlessStopped),
+            other => match other.strip_prefix("on-failure:") {
+                Some(n) => Ok(RestartPolicy::OnFailure {
+                    max_retries: Some(n.parse().map_err(|_| invalid())?),
+                }),
+                None => Err(invalid()),
+            },
+        }
+    }
+}
+
+/// How to probe a container to decide if it is healthy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckKind {
+    /// Expect a 2xx/3xx response to `GET <path>`
+    Http { path: String },
+    /// Expect the port to accept TCP connections
+    Tcp,
+}
+
+/// Health check run against a newly deployed container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    pub kind: HealthCheckKind,
+    /// Container port to probe; defaults to the first published TCP port
+    pub port: Option<u16>,
+    /// Time between probes
+    pub interval: Duration,
+    /// Time to wait for a single probe
+    pub timeout: Duration,
+    /// Number of failed probes before the container is considered unhealthy
+    pub retries: u32,
+}
+
+const HEALTH_CHECK_KEYS: &[&str] = &["type", "path", "port", "interval", "timeout", "retries"];
+
+impl HealthCheck {
+    const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+    const DEFAULT_RETRIES: u32 = 15;
+
+    fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        let map = as_mapping(value, key)?;
+        check_keys(map, HEALTH_CHECK_KEYS, Some(key))?;
+
+        let path = map
+            .get("path")
+            .map(|v| as_str(v, &key_path(Some(key), "path")))
+            .transpose()?;
+
+        let kind = match map
+            .get("type")
+            .map(|v| as_str(v, &key_path(Some(key), "type")))
+            .transpose()?
+        {
+            Some("http") => {
+                let path = path.unwrap_or("/");
+                if !path.starts_with('/') {
+                    return Err(ConfigError::InvalidHealthCheck(format!(
+                        "path must start with '/': {path}"
+                    )));
+                }
+                HealthCheckKind::Http {
+                    path: path.to_string(),
+                }
+            }
+            Some("tcp") => {
+                if path.is_some() {
+                    return Err(ConfigError::InvalidHealthCheck(
+                        "path is only valid for http health checks".to_string(),
+                    ));
+                }
+                HealthCheckKind::Tcp
+            }
+            Some(other) => {
+                return Err(ConfigError::InvalidHealthCheck(format!(
+                    "unknown type {other}. Valid options: http, tcp"
+                )));
+            }
+            None => {
+                return Err(ConfigError::MissingField(key_path(Some(key), "type")));
+            }
+        };
+
+        let port = map
+            .get("port")
+            .map(|v| as_port(v, &key_path(Some(key), "port")))
+            .transpose()?;
+
+        let interval = map
+            .get("interval")
+            .map(|v| parse_duration(v, &key_path(Some(key), "interval")))
+            .transpose()?
+            .unwrap_or(Self::DEFAULT_INTERVAL);
+
+        let timeout = map
+            .get("timeout")
+            .map(|v| parse_duration(v, &key_path(Some(key), "timeout")))
+            .transpose()?
+            .unwrap_or(Self::DEFAULT_TIMEOUT);
+
+        let retries = map
+            .get("retries")
+            .map(|v| as_u32(v, &key_path(Some(key), "retries")))
+            .transpose()?
+            .unwrap_or(Self::DEFAULT_RETRIES);
+
+        Ok(HealthCheck {
+            kind,
+            port,
+            interval,
+            timeout,
+            retries,
+        })
+    }
+}
+
+/// A hostname (and optional path prefix) that the agent's proxy routes to the app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Route {
+    /// Hostname to match against the request's `Host` header
+    pub host: String,
+    /// Path prefix to match; if unset, all paths on the host match
+    pub path: Option<String>,
+    /// Container port to send requests to; defaults to the health check
+    /// port, then the first published TCP port
+    pub port: Option<u16>,
+}
+
+const ROUTE_KEYS: &[&str] = &["host", "path", "port"];
+
+impl FromStr for Route {
+    type Err = ConfigError;
+
+    /// Parses a route written as `<host>` or `<host>/<path prefix>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, path) = match s.find('/') {
+            Some(i) => (&s[..i], Some(&s[i..])),
+            None => (s, None),
+        };
+        Route::new(host, path, None)
+    }
+}
+
+impl Route {
+    fn new(host: &str, path: Option<&str>, port: Option<u16>) -> Result<Self, ConfigError> {
+        let host = host.to_lowercase();
+        let valid_host = !host.is_empty()
+            && host.split('.').all(|label| {
+                !label.is_empty()
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        if !valid_host {
+            return Err(ConfigError::InvalidRoute(format!(
+                "invalid hostname: {host}"
+            )));
+        }
+
+        // Normalise the prefix so that `/api/` and `/api` match the same paths
+        let path = match path.map(|p| p.trim_end_matches('/')) {
+            Some(p) if !p.is_empty() && !p.starts_with('/') => {
+                return Err(ConfigError::InvalidRoute(format!(
+                    "path must start with '/': {p}"
+                )));
+            }
+            Some("") | None => None,
+            Some(p) => Some(p.to_string()),
+        };
+
+        Ok(Route { host, path, port })
+    }
+
+    fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        if let Value::String(route) = value {
+            return Route::from_str(route);
+        }
+
+        let map = as_mapping(value, key)?;
+        check_keys(map, ROUTE_KEYS, Some(key))?;
+
+        let host = map
+            .get("host")
+            .ok_or_else(|| ConfigError::MissingField(key_path(Some(key), "host")))
+            .and_then(|v| as_str(v, &key_path(Some(key), "host")))?;
+
+        let path = map
+            .get("path")
+            .map(|v| as_str(v, &key_path(Some(key), "path")))
+            .transpose()?;
+
+        let port = map
+            .get("port")
+            .map(|v| as_port(v, &key_path(Some(key), "port")))
+            .transpose()?;
+
+        Route::new(host, path, port)
+    }
+}
+
+/// Parses a non-zero port number.
+fn as_port(value: &Value, key: &str) -> Result<u16, ConfigError> {
+    as_u32(value, key).and_then(|p| {
+        u16::try_from(p)
+            .ok()
+            .filter(|p| *p != 0)
+            .ok_or_else(|| ConfigError::InvalidPort(p.to_string()))
+    })
+}
+
+/// DeployConfig represents the `deploy` section of a nimble.yaml file.
+///
+/// The section can either be a bare target string (`deploy: local:docker`)
+/// or a mapping with a `target` key and optional runtime settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployConfig {
+    /// Where to deploy the image
+    pub target: DeployTarget,
+    /// Container ports to publish
+    pub ports: Vec<PortMapping>,
+    /// Environment variables to set in the container
+    pub env: BTreeMap<String, String>,
+    /// Override for the image's default command
+    pub command: Option<Vec<String>>,
+    /// Override for the image's entrypoint
+    pub entrypoint: Option<Vec<String>>,
+    /// Container restart policy
+    pub restart: RestartPolicy,
+    /// Health check used to decide if a new container is ready
+    pub healthcheck: Option<HealthCheck>,
+    /// Extra hostnames the agent's proxy routes to the app
+    #[serde(default)]
+    pub routes: Vec<Route>,
+}
+
+const DEPLOY_KEYS: &[&str] = &[
+    "target",
+    "ports",
+    "env",
+    "command",
+    "entrypoint",
+    "restart",
+    "healthcheck",
+    "routes",
+];
+
+impl DeployConfig {
+    /// Creates a DeployConfig for the given target with default settings.
+    pub fn new(target: DeployTarget) -> Self {
+        Self {
+            target,
+            ports: Vec::new(),
+            env: BTreeMap::new(),
+            command: None,
+            entrypoint: None,
+            restart: RestartPolicy::default(),
+            healthcheck: None,
+            routes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        if let Value::String(target) = value {
+            return Ok(Self::new(DeployTarget::from_str(target)?));
+        }
+
+        let map = as_mapping(value, key)?;
+        check_keys(map, DEPLOY_KEYS, Some(key))?;
+        let sub = |name: &str| key_path(Some(key), name);
+
+        let target = map
+            .get("target")
+            .ok_or_else(|| ConfigError::MissingField(sub("target")))
+            .and_then(|v| as_str(v, &sub("target")))?;
+        let mut config = Self::new(DeployTarget::from_str(target)?);
+
+        if let Some(ports) = map.get("ports") {
+            config.ports = as_sequence(ports, &sub("ports"))?
+                .iter()
+                .map(|p| PortMapping::from_str(&scalar_to_string(p, &sub("ports"))?))
+                .collect::<Result<_, _>>()?;
+        }
+
+        if let Some(env) = map.get("env") {
+            for (name, value) in as_mapping(env, &sub("env"))? {
+                let name = as_str(name, &sub("env"))?;
+                let value = scalar_to_string(value, &key_path(Some(&sub("env")), name))?;
+                config.env.insert(name.to_string(), value);
+            }
+        }
+
+        config.command = map
+            .get("command")
+            .map(|v| string_list(v, &sub("command")))
+            .transpose()?;
+
+        config.entrypoint = map
+            .get("entrypoint")
+            .map(|v| string_list(v, &sub("entrypoint")))
+            .transpose()?;
+
+        if let Some(restart) = map.get("restart") {
+            config.restart = RestartPolicy::from_str(as_str(restart, &sub("restart"))?)?;
+        }
+
+        config.healthcheck = map
+            .get("healthcheck")
+            .map(|v| HealthCheck::from_value(v, &sub("healthcheck")))
+            .transpose()?;
+
+        if let Some(routes) = map.get("routes") {
+            config.routes = as_sequence(routes, &sub("routes"))?
+                .iter()
+                .map(|r| Route::from_value(r, &sub("routes")))
+                .collect::<Result<_, _>>()?;
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NimbleConfig;
+
+    fn deploy(yaml: &str) -> DeployConfig {
+        NimbleConfig::from_str(&format!("deploy:\n  target: local:docker\n{yaml}"))
+            .unwrap()
+            .deploy
+            .unwrap()
+    }
+
+    #[test]
+    fn short_form_uses_defaults() {
+        let config = NimbleConfig::from_str("deploy: LOCAL:DOCKER")
+            .unwrap()
+            .deploy
+            .unwrap();
+
+        assert_eq!(config, DeployConfig::new(DeployTarget::LocalDocker));
+        assert!(config.ports.is_empty());
+        assert!(config.env.is_empty());
+        assert_eq!(config.command, None);
+        assert_eq!(config.entrypoint, None);
+        assert_eq!(config.restart, RestartPolicy::UnlessStopped);
+        assert_eq!(config.healthcheck, None);
+        assert!(config.routes.is_empty());
+        assert_eq!(deploy(""), config);
+    }
+
+    #[test]
+    fn health_check_defaults() {
+        let config = deploy("  healthcheck:\n    type: http\n");
+
+        assert_eq!(
+            config.healthcheck,
+            Some(HealthCheck {
+                kind: HealthCheckKind::Http {
+                    path: "/".to_string()
+                },
+                port: None,
+                interval: Duration::from_secs(2),
+                timeout: Duration::from_secs(2),
+                retries: 15,
+            })
+        );
+    }
+
+    #[test]
+    fn health_check_settings_are_parsed() {
+        let config = deploy(
+            "  healthcheck:\n    type: tcp\n    port: 5432\n    interval: 500ms\n    timeout: 1s\n    retries: 3\n",
+        );
+
+        assert_eq!(
+            config.healthcheck,
+            Some(HealthCheck {
+                kind: HealthCheckKind::Tcp,
+                port: Some(5432),
+                interval: Duration::from_millis(500),
+                timeout: Duration::from_secs(1),
+                retries: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn ports_default_to_tcp_on_any_host_port() {
+        let config = deploy("  ports: [8080, '9090:90', 53/udp, '5353:53/udp']\n");
+
+        let port = |container, host, protocol| PortMapping {
+            container,
+            host,
+            protocol,
+        };
+        assert_eq!(
+            config.ports,
+            [
+                port(8080, None, Protocol::Tcp),
+                port(90, Some(9090), Protocol::Tcp),
+                port(53, None, Protocol::Udp),
+                port(53, Some(5353), Protocol::Udp),
+            ]
+        );
+    }
+
+    #[test]
+    fn restart_policies_are_parsed() {
+        for (policy, expected) in [
+            ("no", RestartPolicy::No),
+            ("always", RestartPolicy::Always),
+            ("on-failure", RestartPolicy::OnFailure { max_retries: None }),
+            (
+                "on-failure:3",
+                RestartPolicy::OnFailure {
+                    max_retries: Some(3),
+                },
+            ),
+            ("unless-stopped", RestartPolicy::UnlessStopped),
+        ] {
+            assert_eq!(RestartPolicy::from_str(policy), Ok(expected));
+            assert_eq!(expected.to_string(), policy);
+        }
+    }
+
+    #[test]
+    fn env_and_commands_are_parsed() {
+        let config = deploy(
+            "  env:\n    PORT: 8080\n    DEBUG: true\n  command: serve --port 8080\n  entrypoint: [/bin/sh, -c]\n",
+        );
+
+        assert_eq!(config.env["PORT"], "8080");
+        assert_eq!(config.env["DEBUG"], "true");
+        assert_eq!(
+            config.command,
+            Some(vec![
+                "serve".to_string(),
+                "--port".to_string(),
+                "8080".to_string()
+            ])
+        );
+        assert_eq!(
+            config.entrypoint,
+            Some(vec!["/bin/sh".to_string(), "-c".to_string()])
+        );
+    }
+
+    #[test]
+    fn routes_are_normalised() {
+        let config = deploy(
+            "  routes:\n    - Example.COM/api/\n    - example.com/\n    - host: admin.example.com\n      path: /\n      port: 9000\n",
+        );
+
+        assert_eq!(
+            config.routes,
+            [
+                Route {
+                    host: "example.com".to_string(),
+                    path: Some("/api".to_string()),
+                    port: None,
+                },
+                Route {
+                    host: "example.com".to_string(),
+                    path: None,
+                    port: None,
+                },
+                Route {
+                    host: "admin.example.com".to_string(),
+                    path: None,
+                    port: Some(9000),
+                },
+            ]
+        );
+    }
+}
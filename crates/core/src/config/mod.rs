@@ -0,0 +1,612 @@
+pub mod builder;
+pub mod deploy;
+pub mod registry;
+
+use std::{fs, path::Path, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+pub use crate::config::{
+    builder::{
+        BuilderConfig, BuilderType, DockerfileConfig, GoConfig, PythonConfig, RustConfig,
+        StaticConfig,
+    },
+    deploy::{
+        DeployConfig, DeployTarget, HealthCheck, HealthCheckKind, PortMapping, Protocol,
+        RestartPolicy, Route,
+    },
+    registry::RegistryConfig,
+};
+
+/// Maximum length of an app name (a DNS label)
+const MAX_APP_NAME_LEN: usize = 63;
+
+/// Checks that an app name is valid.
+///
+/// App names are used in container names and hostnames, so they must be
+/// 1-63 characters of lowercase letters, digits and hyphens, and must start
+/// with a letter and not end with a hyphen.
+///
+/// # Errors
+///
+/// Returns `ConfigError::InvalidAppName` if the name is invalid.
+pub fn validate_app_name(name: &str) -> Result<(), ConfigError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_APP_NAME_LEN
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && !name.ends_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ConfigError::InvalidAppName(name.to_string()))
+    }
+}
+
+/// NimbleConfig represents the configuration from a nimble.yaml file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NimbleConfig {
+    /// Name of the app this project deploys as
+    pub app: Option<String>,
+    /// The builder to use. If unset, the builder is detected from the
+    /// project's files.
+    pub builder: Option<BuilderConfig>,
+    /// Where and how to deploy the built image
+    pub deploy: Option<DeployConfig>,
+    /// Registry to push the built image to
+    pub registry: Option<RegistryConfig>,
+}
+
+/// Top-level keys allowed in nimble.yaml
+const KEYS: &[&str] = &["app", "builder", "deploy", "registry"];
+
+impl NimbleConfig {
+    /// Loads a NimbleConfig from a nimble.yaml file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the nimble.yaml file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|e| ConfigError::IoError(e.to_string()))?;
+
+        Self::from_str(&contents)
+    }
+}
+
+impl FromStr for NimbleConfig {
+    type Err = ConfigError;
+
+    fn from_str(yaml: &str) -> Result<Self, Self::Err> {
+        let raw: Value =
+            serde_yaml::from_str(yaml).map_err(|e| ConfigError::ParseError(e.to_string()))?;
+        let raw = as_mapping(&raw, "nimble.yaml")?;
+        check_keys(raw, KEYS, None)?;
+
+        // Extract app name (optional)
+        let app = raw
+            .get("app")
+            .map(|v| as_str(v, "app"))
+            .transpose()?
+            .map(|name| validate_app_name(name).map(|_| name.to_string()))
+            .transpose()?;
+
+        // Extract builder (optional)
+        let builder = raw
+            .get("builder")
+            .map(|v| BuilderConfig::from_value(v, "builder"))
+            .transpose()?;
+
+        // Extract deploy section (optional)
+        let deploy = raw
+            .get("deploy")
+            .map(|v| DeployConfig::from_value(v, "deploy"))
+            .transpose()?;
+
+        // Extract registry section (optional)
+        let registry = raw
+            .get("registry")
+            .map(|v| RegistryConfig::from_value(v, "registry"))
+            .transpose()?;
+
+        Ok(NimbleConfig {
+            app,
+            builder,
+            deploy,
+            registry,
+        })
+    }
+}
+
+// YAML helpers shared by the config parsers.
+
+/// Joins a parent key path and a child key, e.g. `deploy` + `ports` -> `deploy.ports`.
+pub(crate) fn key_path(prefix: Option<&str>, key: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{prefix}.{key}"),
+        None => key.to_string(),
+    }
+}
+
+/// Returns an error naming the first key in `map` that isn't in `allowed`.
+pub(crate) fn check_keys(
+    map: &Mapping,
+    allowed: &[&str],
+    prefix: Option<&str>,
+) -> Result<(), ConfigError> {
+    for key in map.keys() {
+        let name = match key {
+            Value::String(s) => s.clone(),
+            other => serde_yaml::to_string(other)
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default(),
+        };
+        if !allowed.contains(&name.as_str()) {
+            return Err(ConfigError::UnknownKey(key_path(prefix, &name)));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn as_mapping<'a>(value: &'a Value, key: &str) -> Result<&'a Mapping, ConfigError> {
+    value.as_mapping().ok_or_else(|| ConfigError::InvalidType {
+        key: key.to_string(),
+        expected: "a mapping",
+    })
+}
+
+pub(crate) fn as_str<'a>(value: &'a Value, key: &str) -> Result<&'a str, ConfigError> {
+    value.as_str().ok_or_else(|| ConfigError::InvalidType {
+        key: key.to_string(),
+        expected: "a string",
+    })
+}
+
+pub(crate) fn as_sequence<'a>(value: &'a Value, key: &str) -> Result<&'a [Value], ConfigError> {
+    value
+        .as_sequence()
+        .map(Vec::as_slice)
+        .ok_or_else(|| ConfigError::InvalidType {
+            key: key.to_string(),
+            expected: "a list",
+        })
+}
+
+pub(crate) fn as_bool(value: &Value, key: &str) -> Result<bool, ConfigError> {
+    value.as_bool().ok_or_else(|| ConfigError::InvalidType {
+        key: key.to_string(),
+        expected: "a boolean",
+    })
+}
+
+pub(crate) fn as_u32(value: &Value, key: &str) -> Result<u32, ConfigError> {
+    value
+        .as_u64()
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or_else(|| ConfigError::InvalidType {
+            key: key.to_string(),
+            expected: "a non-negative integer",
+        })
+}
+
+/// Converts a scalar (string, number or bool) to a string.
+pub(crate) fn scalar_to_string(value: &Value, key: &str) -> Result<String, ConfigError> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(ConfigError::InvalidType {
+            key: key.to_string(),
+            expected: "a string, number or boolean",
+        }),
+    }
+}
+
+/// Parses either a list of strings or a single whitespace-separated string.
+pub(crate) fn string_list(value: &Value, key: &str) -> Result<Vec<String>, ConfigError> {
+    match value {
+        Value::String(s) => Ok(s.split_whitespace().map(str::to_string).collect()),
+        Value::Sequence(items) => items
+            .iter()
+            .map(|item| scalar_to_string(item, key))
+            .collect(),
+        _ => Err(ConfigError::InvalidType {
+            key: key.to_string(),
+            expected: "a string or a list of strings",
+        }),
+    }
+}
+
+/// Parses a duration such as `500ms`, `5s`, `2m` or `1h`. A bare number is
+/// taken as seconds.
+///
+/// # Errors
+///
+/// Returns `ConfigError::InvalidDuration` if the duration is malformed or
+/// too long to represent.
+pub fn parse_duration_str(s: &str) -> Result<Duration, ConfigError> {
+    let invalid = || ConfigError::InvalidDuration(s.to_string());
+
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "s" | "" => Ok(Duration::from_secs(amount)),
+        "m" => amount
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(invalid),
+        "h" => amount
+            .checked_mul(60 * 60)
+            .map(Duration::from_secs)
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a duration value from nimble.yaml, see [`parse_duration_str`].
+pub(crate) fn parse_duration(value: &Value, key: &str) -> Result<Duration, ConfigError> {
+    let s = scalar_to_string(value, key)?;
+    parse_duration_str(&s).map_err(|_| ConfigError::InvalidDuration(format!("{key}: {s}")))
+}
+
+/// Errors that can occur when loading or parsing a NimbleConfig.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// I/O error reading the file
+    IoError(String),
+    /// Error parsing the YAML
+    ParseError(String),
+    /// Required field is missing
+    MissingField(String),
+    /// Invalid builder type
+    InvalidBuilder(String),
+    /// Invalid builder setting
+    InvalidBuilderConfig(String),
+    /// Invalid app name
+    InvalidAppName(String),
+    /// Key is not part of the nimble.yaml schema
+    UnknownKey(String),
+    /// Value has the wrong YAML type for its key
+    InvalidType { key: String, expected: &'static str },
+    /// Invalid deploy target
+    InvalidDeployTarget(String),
+    /// Invalid port mapping
+    InvalidPort(String),
+    /// Invalid restart policy
+    InvalidRestartPolicy(String),
+    /// Invalid health check definition
+    InvalidHealthCheck(String),
+    /// Invalid duration string
+    InvalidDuration(String),
+    /// Invalid proxy route
+    InvalidRoute(String),
+    /// Invalid registry setting
+    InvalidRegistry(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::IoError(msg) => write!(f, "I/O error: {msg}"),
+            ConfigError::ParseError(msg) => write!(f, "Parse error: {msg}"),
+            ConfigError::MissingField(field) => write!(f, "Missing required field: {field}"),
+            ConfigError::InvalidBuilder(builder) => {
+                write!(
+                    f,
+                    "Invalid builder type: {builder}. Valid options: dockerfile, go, node, python, rust, static, plugin:<name>"
+                )
+            }
+            ConfigError::InvalidBuilderConfig(msg) => write!(f, "Invalid builder config: {msg}"),
+            ConfigError::InvalidAppName(name) => {
+                write!(
+                    f,
+                    "Invalid app name: {name}. App names must be 1-63 lowercase letters, digits or hyphens, starting with a letter"
+                )
+            }
+            ConfigError::UnknownKey(key) => write!(f, "Unknown key: {key}"),
+            ConfigError::InvalidType { key, expected } => {
+                write!(f, "Invalid value for {key}: expected {expected}")
+            }
+            ConfigError::InvalidDeployTarget(target) => {
+                write!(
+                    f,
+                    "Invalid deploy target: {target}. Valid options: local:docker"
+                )
+            }
+            ConfigError::InvalidPort(port) => write!(f, "Invalid port mapping: {port}"),
+            ConfigError::InvalidRestartPolicy(policy) => {
+                write!(
+                    f,
+                    "Invalid restart policy: {policy}. Valid options: no, always, on-failure[:N], unless-stopped"
+                )
+            }
+            ConfigError::InvalidHealthCheck(msg) => write!(f, "Invalid health check: {msg}"),
+            ConfigError::InvalidDuration(duration) => write!(f, "Invalid duration: {duration}"),
+            ConfigError::InvalidRoute(msg) => write!(f, "Invalid route: {msg}"),
+            ConfigError::InvalidRegistry(msg) => write!(f, "Invalid registry config: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<NimbleConfig, ConfigError> {
+        NimbleConfig::from_str(yaml)
+    }
+
+    #[test]
+    fn full_config_is_parsed() {
+        let config = parse(
+            "app: web\nbuilder: node\ndeploy: local:docker\nregistry:\n  url: localhost:5000\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.app.as_deref(), Some("web"));
+        assert_eq!(config.builder, Some(BuilderConfig::Node));
+        assert_eq!(
+            config.deploy,
+            Some(DeployConfig::new(DeployTarget::LocalDocker))
+        );
+        assert!(config.registry.is_some());
+        assert_eq!(parse("{}").unwrap(), NimbleConfig::default());
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let err = NimbleConfig::from_file("/nonexistent/nimble.yaml").unwrap_err();
+        assert!(matches!(err, ConfigError::IoError(_)), "{err:?}");
+    }
+
+    #[test]
+    fn malformed_yaml_is_a_parse_error() {
+        let err = parse("deploy: [local:docker").unwrap_err();
+        assert!(matches!(err, ConfigError::ParseError(_)), "{err:?}");
+    }
+
+    #[test]
+    fn missing_required_fields_are_named_by_path() {
+        assert_eq!(
+            parse("deploy:\n  ports: [8080]\n"),
+            Err(ConfigError::MissingField("deploy.target".to_string()))
+        );
+        assert_eq!(
+            parse("deploy:\n  target: local:docker\n  healthcheck:\n    port: 8080\n"),
+            Err(ConfigError::MissingField(
+                "deploy.healthcheck.type".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("deploy:\n  target: local:docker\n  routes:\n    - path: /api\n"),
+            Err(ConfigError::MissingField("deploy.routes.host".to_string()))
+        );
+        assert_eq!(
+            parse("builder:\n  main: ./cmd/server\n"),
+            Err(ConfigError::MissingField("builder.type".to_string()))
+        );
+    }
+
+    #[test]
+    fn unknown_builder_is_rejected() {
+        assert_eq!(
+            parse("builder: cobol"),
+            Err(ConfigError::InvalidBuilder("cobol".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_builder_settings_are_rejected() {
+        for yaml in [
+            "builder:\n  type: dockerfile\n  context: ../outside\n",
+            "builder:\n  type: dockerfile\n  dockerfile: /etc/Dockerfile\n",
+            "builder:\n  type: dockerfile\n  target: two words\n",
+        ] {
+            let err = parse(yaml).unwrap_err();
+            assert!(
+                matches!(err, ConfigError::InvalidBuilderConfig(_)),
+                "{yaml}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_app_names_are_rejected() {
+        for name in ["My-App", "1app", "app-", "my_app", &"a".repeat(64)] {
+            assert_eq!(
+                parse(&format!("app: {name}")),
+                Err(ConfigError::InvalidAppName(name.to_string()))
+            );
+        }
+        assert_eq!(validate_app_name(&"a".repeat(63)), Ok(()));
+    }
+
+    #[test]
+    fn unknown_keys_are_named_by_dotted_path() {
+        for (yaml, key) in [
+            ("name: web\n", "name"),
+            (
+                "deploy:\n  target: local:docker\n  replicas: 2\n",
+                "deploy.replicas",
+            ),
+            (
+                "deploy:\n  target: local:docker\n  healthcheck:\n    type: tcp\n    command: true\n",
+                "deploy.healthcheck.command",
+            ),
+            (
+                "deploy:\n  target: local:docker\n  routes:\n    - host: example.com\n      weight: 2\n",
+                "deploy.routes.weight",
+            ),
+            ("builder:\n  type: go\n  binary: server\n", "builder.binary"),
+            ("builder:\n  type: node\n  version: 20\n", "builder.version"),
+            ("registry:\n  password: hunter2\n", "registry.password"),
+        ] {
+            assert_eq!(
+                parse(yaml),
+                Err(ConfigError::UnknownKey(key.to_string())),
+                "{yaml}"
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_value_types_are_named_by_path() {
+        assert_eq!(
+            parse("- web"),
+            Err(ConfigError::InvalidType {
+                key: "nimble.yaml".to_string(),
+                expected: "a mapping",
+            })
+        );
+        assert_eq!(
+            parse("deploy:\n  target: local:docker\n  ports: 8080\n"),
+            Err(ConfigError::InvalidType {
+                key: "deploy.ports".to_string(),
+                expected: "a list",
+            })
+        );
+        assert_eq!(
+            parse("deploy:\n  target: local:docker\n  env:\n    PORT: [8080]\n"),
+            Err(ConfigError::InvalidType {
+                key: "deploy.env.PORT".to_string(),
+                expected: "a string, number or boolean",
+            })
+        );
+        assert_eq!(
+            parse(
+                "deploy:\n  target: local:docker\n  healthcheck:\n    type: tcp\n    retries: -1\n"
+            ),
+            Err(ConfigError::InvalidType {
+                key: "deploy.healthcheck.retries".to_string(),
+                expected: "a non-negative integer",
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_deploy_target_is_rejected() {
+        assert_eq!(
+            parse("deploy: remote:k8s"),
+            Err(ConfigError::InvalidDeployTarget("remote:k8s".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_ports_are_rejected() {
+        for port in ["0", "70000", "http", "8080/sctp", "80:0"] {
+            assert_eq!(
+                parse(&format!(
+                    "deploy:\n  target: local:docker\n  ports: ['{port}']\n"
+                )),
+                Err(ConfigError::InvalidPort(port.to_string()))
+            );
+        }
+        assert_eq!(
+            parse(
+                "deploy:\n  target: local:docker\n  routes:\n    - host: example.com\n      port: 70000\n"
+            ),
+            Err(ConfigError::InvalidPort("70000".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_restart_policies_are_rejected() {
+        for policy in ["sometimes", "on-failure:many"] {
+            assert_eq!(
+                parse(&format!(
+                    "deploy:\n  target: local:docker\n  restart: {policy}\n"
+                )),
+                Err(ConfigError::InvalidRestartPolicy(policy.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_health_checks_are_rejected() {
+        for healthcheck in [
+            "type: grpc",
+            "type: tcp\n    path: /health",
+            "type: http\n    path: health",
+        ] {
+            let err = parse(&format!(
+                "deploy:\n  target: local:docker\n  healthcheck:\n    {healthcheck}\n"
+            ))
+            .unwrap_err();
+            assert!(
+                matches!(err, ConfigError::InvalidHealthCheck(_)),
+                "{healthcheck}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_durations_are_rejected() {
+        assert_eq!(
+            parse(
+                "deploy:\n  target: local:docker\n  healthcheck:\n    type: tcp\n    interval: 5 minutes\n"
+            ),
+            Err(ConfigError::InvalidDuration(
+                "deploy.healthcheck.interval: 5 minutes".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_duration_str("1d"),
+            Err(ConfigError::InvalidDuration("1d".to_string()))
+        );
+    }
+
+    #[test]
+    fn durations_take_a_unit() {
+        assert_eq!(parse_duration_str("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration_str("5"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration_str("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration_str("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration_str("1h"), Ok(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn overlong_durations_are_rejected() {
+        for duration in ["999999999999999999h", "999999999999999999m"] {
+            assert_eq!(
+                parse_duration_str(duration),
+                Err(ConfigError::InvalidDuration(duration.to_string()))
+            );
+        }
+        assert_eq!(
+            parse_duration_str("18446744073709551615s"),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn invalid_routes_are_rejected() {
+        for route in ["exa_mple.com", "-example.com", "example..com"] {
+            let err = parse(&format!(
+                "deploy:\n  target: local:docker\n  routes: ['{route}']\n"
+            ))
+            .unwrap_err();
+            assert!(
+                matches!(err, ConfigError::InvalidRoute(_)),
+                "{route}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_registry_is_rejected() {
+        let err = parse("registry:\n  url: registry.example.com/team\n").unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidRegistry(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::config::{ConfigError, as_mapping, as_str, check_keys, key_path};
+
+/// Registry to push built images to, from nimble.yaml. Unset fields fall back
+/// to the agent's registry settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryConfig {
+    /// Registry host, with an optional port (e.g. "registry.example.com" or
+    /// "localhost:5000")
+    pub url: Option<String>,
+    /// Repository in the registry to push to (e.g. "team/api")
+    pub repository: Option<String>,
+    /// Name of the app's secret env var that holds the registry credentials,
+    /// as `<username>:<password>`
+    pub credentials: Option<String>,
+}
+
+const REGISTRY_KEYS: &[&str] = &["url", "repository", "credentials"];
+
+impl RegistryConfig {
+    pub(crate) fn from_value(value: &Value, key: &str) -> Result<Self, ConfigError> {
+        let map = as_mapping(value, key)?;
+        check_keys(map, REGISTRY_KEYS, Some(key))?;
+        let sub = |name: &str| key_path(Some(key), name);
+
+        let url = map
+            .get("url")
+            .map(|v| as_str(v, &sub("url")))
+            .transpose()?
+            .map(parse_registry_url)
+            .transpose()?;
+
+        let repository = map
+            .get("repository")
+            .map(|v| as_str(v, &sub("repository")))
+            .transpose()?
+            .map(|repository| {
+                validate_repository(repository)?;
+                Ok::<_, ConfigError>(repository.to_string())
+            })
+            .transpose()?;
+
+        let credentials = map
+            .get("credentials")
+            .map(|v| as_str(v, &sub("credentials")))
+            .transpose()?
+            .map(|name| {
+                let valid = !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+                if valid {
+                    Ok(name.to_string())
+                } else {
+                    Err(ConfigError::InvalidRegistry(format!(
+                        "{} must name an env var: {name}",
+                        sub("credentials")
+                    )))
+                }
+            })
+            .transpose()?;
+
+        Ok(RegistryConfig {
+            url,
+            repository,
+            credentials,
+        })
+    }
+}
+
+/// Parses a registry address, which is a host with an optional port. An
+/// `https://` scheme and trailing slashes are dropped, since image references
+/// don't include them.
+pub fn parse_registry_url(url: &str) -> Result<String, ConfigError> {
+    let host = url
+        .strip_prefix("https://")
+        .unwrap_or(url)
+        .trim_end_matches('/');
+
+    let valid = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-.:[]".contains(c));
+    if valid {
+        Ok(host.to_lowercase())
+    } else {
+        Err(ConfigError::InvalidRegistry(format!(
+            "invalid registry URL: {url}. Use a host with an optional port, e.g. registry.example.com:5000"
+        )))
+    }
+}
+
+/// Checks that a repository is a valid image repository: path components of
+/// lowercase letters and digits, separated by `.`, `_`, `__` or `-`.
+pub fn validate_repository(repository: &str) -> Result<(), ConfigError> {
+    let valid_component = |component: &str| {
+        !component.is_empty()
+            && component.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && component.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && component
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c))
+    };
+
+    if repository.split('/').all(valid_component) {
+        Ok(())
+    } else {
+        Err(ConfigError::InvalidRegistry(format!(
+            "invalid repository: {repository}. Repositories are lowercase letters, digits, \
+             '.', '_' and '-', with '/' between path components"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::config::NimbleConfig;
+
+    fn parse(yaml: &str) -> Result<Option<RegistryConfig>, ConfigError> {
+        NimbleConfig::from_str(&format!("builder: dockerfile\n{yaml}")).map(|cfg| cfg.registry)
+    }
+
+    #[test]
+    fn registry_url_is_a_host_with_optional_port() {
+        let parsed = |url| parse_registry_url(url).unwrap();
+        assert_eq!(parsed("registry.example.com"), "registry.example.com");
+        assert_eq!(parsed("localhost:5000"), "localhost:5000");
+        assert_eq!(parsed("[::1]:5000"), "[::1]:5000");
+        assert_eq!(
+            parsed("https://Registry.Example.com/"),
+            "registry.example.com"
+        );
+    }
+
+    #[test]
+    fn invalid_registry_urls_are_rejected() {
+        for url in [
+            "",
+            "https://",
+            "http://registry.example.com",
+            "registry.example.com/team",
+        ] {
+            assert!(
+                matches!(
+                    parse_registry_url(url),
+                    Err(ConfigError::InvalidRegistry(_))
+                ),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn repository_may_have_namespaces() {
+        for repository in [
+            "api",
+            "team/api",
+            "org/team/api",
+            "my-team/api_v2.1",
+            "a__b",
+        ] {
+            assert_eq!(validate_repository(repository), Ok(()), "{repository}");
+        }
+    }
+
+    #[test]
+    fn invalid_repositories_are_rejected() {
+        for repository in [
+            "",
+            "Team/api",
+            "team//api",
+            "/api",
+            "team/",
+            "-api",
+            "api-",
+            "a b",
+        ] {
+            assert!(
+                matches!(
+                    validate_repository(repository),
+                    Err(ConfigError::InvalidRegistry(_))
+                ),
+                "{repository}"
+            );
+        }
+    }
+
+    #[test]
+    fn registry_section_is_parsed() {
+        let registry = parse(
+            "registry:\n  url: https://localhost:5000/\n  repository: team/api\n  credentials: REGISTRY_AUTH\n",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            registry,
+            RegistryConfig {
+                url: Some("localhost:5000".to_string()),
+                repository: Some("team/api".to_string()),
+                credentials: Some("REGISTRY_AUTH".to_string()),
+            }
+        );
+        assert_eq!(parse("").unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_registry_section_is_rejected() {
+        assert!(matches!(
+            parse("registry:\n  repository: Team/API\n"),
+            Err(ConfigError::InvalidRegistry(_))
+        ));
+        assert!(matches!(
+            parse("registry:\n  credentials: user:password\n"),
+            Err(ConfigError::InvalidRegistry(_))
+        ));
+        assert_eq!(
+            parse("registry:\n  username: deploy\n"),
+            Err(ConfigError::UnknownKey("registry.username".to_string()))
+        );
+    }
+}
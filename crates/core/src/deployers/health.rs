@@ -0,0 +1,206 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep, timeout},
+};
+
+use crate::{
+    config::{HealthCheck, HealthCheckKind, Protocol},
+    deployers::Container,
+};
+
+/// How long a TCP check waits for the connection to be closed again
+const TCP_SETTLE_TIME: Duration = Duration::from_millis(250);
+
+/// Returns the host port to probe a container's health check on.
+///
+/// Uses the health check's `port` if set, otherwise the container's first
+/// published TCP port.
+pub fn probe_port(check: &HealthCheck, container: &Container) -> anyhow::Result<u16> {
+    match check.port {
+        Some(port) => container
+            .host_port(port, Protocol::Tcp)
+            .with_context(|| format!("health check port {port}/tcp is not published")),
+        None => container
+            .ports
+            .iter()
+            .find(|p| p.protocol == Protocol::Tcp)
+            .map(|p| p.host)
+            .context("container has no published TCP port to health check"),
+    }
+}
+
+/// Probes a port on the local host until the health check passes.
+///
+/// # Errors
+///
+/// Returns the last probe's error once `check.retries` probes have failed.
+pub async fn wait_until_healthy(check: &HealthCheck, host_port: u16) -> anyhow::Result<()> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, host_port));
+    let attempts = check.retries.max(1);
+    let mut last_error = None;
+
+    for attempt in 1..=attempts {
+        match timeout(check.timeout, probe(check, addr)).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) => last_error = Some(e),
+            Err(_) => last_error = Some(anyhow::anyhow!("timed out after {:?}", check.timeout)),
+        }
+
+        if attempt < attempts {
+            sleep(check.interval).await;
+        }
+    }
+
+    let reason = last_error.map(|e| e.to_string()).unwrap_or_default();
+    bail!("Health check failed after {attempts} attempts: {reason}")
+}
+
+async fn probe(check: &HealthCheck, addr: SocketAddr) -> anyhow::Result<()> {
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .with_context(|| format!("failed to connect to {addr}"))?;
+
+    let HealthCheckKind::Http { path } = &check.kind else {
+        // Docker's userland proxy accepts connections on a published port
+        // even before the app listens, and closes them once it fails to
+        // reach the app. So the connection has to stay open for a moment, or
+        // the app has to send something, for the port to count as healthy.
+        let settle = TCP_SETTLE_TIME.min(check.timeout / 2);
+        let mut buf = [0u8; 1];
+        return match timeout(settle, stream.read(&mut buf)).await {
+            Err(_) | Ok(Ok(1..)) => Ok(()),
+            Ok(Ok(0)) => bail!("{addr} closed the connection, the app isn't listening yet"),
+            Ok(Err(e)) => Err(e).with_context(|| format!("{addr} reset the connection")),
+        };
+    };
+
+    let request = format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .context("failed to send health check request")?;
+
+    // Only the status line matters, e.g. `HTTP/1.1 200 OK`
+    let mut response = Vec::new();
+    let mut buf = [0u8; 512];
+    while !response.windows(2).any(|w| w == b"\r\n") {
+        let n = stream
+            .read(&mut buf)
+            .await
+            .context("failed to read health check response")?;
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .with_context(|| format!("invalid HTTP response: {status_line:?}"))?;
+
+    if !(200..400).contains(&status) {
+        bail!("GET {path} returned HTTP {status}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn check(kind: HealthCheckKind) -> HealthCheck {
+        HealthCheck {
+            kind,
+            port: None,
+            interval: Duration::from_millis(10),
+            timeout: Duration::from_secs(1),
+            retries: 1,
+        }
+    }
+
+    /// Listens on a free local port, handing each connection to `handle`.
+    async fn serve<F, Fut>(handle: F) -> u16
+    where
+        F: Fn(TcpStream) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                handle(stream).await;
+            }
+        });
+        port
+    }
+
+    async fn respond(mut stream: TcpStream, status: &str) {
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf).await;
+        let response = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n");
+        let _ = stream.write_all(response.as_bytes()).await;
+    }
+
+    #[tokio::test]
+    async fn tcp_check_passes_while_connection_stays_open() {
+        let port = serve(|stream| async move {
+            tokio::spawn(async move {
+                sleep(Duration::from_secs(5)).await;
+                drop(stream);
+            });
+        })
+        .await;
+
+        wait_until_healthy(&check(HealthCheckKind::Tcp), port)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn tcp_check_fails_when_connection_is_closed_right_away() {
+        // Like docker-proxy when nothing listens in the container
+        let port = serve(|stream| async move { drop(stream) }).await;
+
+        let err = wait_until_healthy(&check(HealthCheckKind::Tcp), port)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("isn't listening"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn http_check_accepts_success_and_redirects() {
+        let ok = serve(|stream| respond(stream, "200 OK")).await;
+        let redirect = serve(|stream| respond(stream, "302 Found")).await;
+        let http = check(HealthCheckKind::Http {
+            path: "/healthz".to_string(),
+        });
+
+        wait_until_healthy(&http, ok).await.unwrap();
+        wait_until_healthy(&http, redirect).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn http_check_fails_on_server_errors() {
+        let port = serve(|stream| respond(stream, "503 Service Unavailable")).await;
+        let http = check(HealthCheckKind::Http {
+            path: "/healthz".to_string(),
+        });
+
+        let err = wait_until_healthy(&http, port).await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 503"), "{err}");
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::Receiver;
+
+use crate::{
+    config::DeployConfig,
+    deployers::{Container, Deployer, LogLine, LogOptions, PublishedPort},
+    runtime::ContainerRuntime,
+};
+
+/// Deploys images as containers on the agent's own host, using its container
+/// runtime.
+pub struct LocalDeployer {
+    runtime: Arc<dyn ContainerRuntime>,
+}
+
+impl LocalDeployer {
+    pub fn new(runtime: Arc<dyn ContainerRuntime>) -> Self {
+        LocalDeployer { runtime }
+    }
+}
+
+#[async_trait]
+impl Deployer for LocalDeployer {
+    async fn deploy(
+        &self,
+        image_ref: &str,
+        container_name: &str,
+        config: &DeployConfig,
+    ) -> anyhow::Result<Container> {
+        self.runtime.run(image_ref, container_name, config).await
+    }
+
+    async fn stop(&self, container_id: &str) -> anyhow::Result<()> {
+        self.runtime.stop(container_id).await
+    }
+
+    async fn start(&self, container_id: &str) -> anyhow::Result<Vec<PublishedPort>> {
+        self.runtime.start(container_id).await
+    }
+
+    async fn remove(&self, container_id: &str) -> anyhow::Result<()> {
+        // Stop the container gracefully first, then remove it
+        self.runtime.stop(container_id).await?;
+        self.runtime.remove(container_id).await
+    }
+
+    async fn logs(
+        &self,
+        container_id: &str,
+        options: &LogOptions,
+    ) -> anyhow::Result<Receiver<LogLine>> {
+        self.runtime.logs(container_id, options).await
+    }
+}
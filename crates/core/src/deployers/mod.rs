@@ -0,0 +1,118 @@
+pub mod health;
+pub mod local;
+
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Receiver;
+
+use crate::{
+    config::{DeployConfig, DeployTarget, Protocol},
+    deployers::local::LocalDeployer,
+    runtime::ContainerRuntime,
+};
+
+/// Represents a container started by a deployer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    /// The runtime-assigned container ID
+    pub id: String,
+    /// The container name
+    pub name: String,
+    /// Container ports published on the host
+    pub ports: Vec<PublishedPort>,
+}
+
+impl Container {
+    /// Returns the host port that a container port is published on.
+    pub fn host_port(&self, container_port: u16, protocol: Protocol) -> Option<u16> {
+        self.ports
+            .iter()
+            .find(|p| p.container == container_port && p.protocol == protocol)
+            .map(|p| p.host)
+    }
+}
+
+/// A container port and the host port it was published on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedPort {
+    pub container: u16,
+    pub host: u16,
+    pub protocol: Protocol,
+}
+
+/// Options for reading a container's logs.
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    /// Only return lines written within this long ago
+    pub since: Option<Duration>,
+    /// Only return this many of the most recent lines
+    pub tail: Option<u64>,
+    /// Keep streaming lines as they are written
+    pub follow: bool,
+}
+
+/// A line a container wrote to stdout or stderr.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    /// When the line was written, in RFC 3339 format (e.g.
+    /// "2024-01-15T10:30:00.123456789Z"). Unset for messages from the
+    /// runtime itself, such as errors.
+    pub timestamp: Option<String>,
+    pub message: String,
+}
+
+/// Trait for deploying built images to a deploy target.
+#[async_trait::async_trait]
+pub trait Deployer: Send + Sync {
+    /// Starts a new container from the given image.
+    ///
+    /// # Arguments
+    ///
+    /// * `image_ref` - Reference of the image to run (e.g., "myapp:latest")
+    /// * `container_name` - Name to give the new container
+    /// * `config` - Deploy settings from nimble.yaml (ports, env, command, etc.)
+    ///
+    /// # Returns
+    ///
+    /// Returns the started `Container`.
+    async fn deploy(
+        &self,
+        image_ref: &str,
+        container_name: &str,
+        config: &DeployConfig,
+    ) -> anyhow::Result<Container>;
+
+    /// Stops a container previously started by this deployer, keeping it
+    /// around so it can be started again.
+    async fn stop(&self, container_id: &str) -> anyhow::Result<()>;
+
+    /// Starts a container stopped with [`Deployer::stop`] again, returning
+    /// the host ports its ports are now published on.
+    async fn start(&self, container_id: &str) -> anyhow::Result<Vec<PublishedPort>>;
+
+    /// Stops and removes a container previously started by this deployer.
+    async fn remove(&self, container_id: &str) -> anyhow::Result<()>;
+
+    /// Reads the logs of a container previously started by this deployer.
+    ///
+    /// Lines are sent to the returned receiver as they are read. The channel
+    /// closes once all requested lines have been sent or, when following,
+    /// once the container stops. Dropping the receiver stops reading.
+    async fn logs(
+        &self,
+        container_id: &str,
+        options: &LogOptions,
+    ) -> anyhow::Result<Receiver<LogLine>>;
+}
+
+/// Returns the deployer for a deploy target. Local targets run containers
+/// with `runtime`.
+pub fn select_deployer(
+    target: DeployTarget,
+    runtime: Arc<dyn ContainerRuntime>,
+) -> Box<dyn Deployer> {
+    match target {
+        DeployTarget::LocalDocker => Box::new(LocalDeployer::new(runtime)),
+    }
+}
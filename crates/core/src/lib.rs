@@ -1,2 +1,4 @@
 pub mod builders;
 pub mod config;
+pub mod deployers;
+pub mod runtime;
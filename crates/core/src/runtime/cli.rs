@@ -0,0 +1,682 @@
+use std::{
+    collections::BTreeMap,
+    fs::{DirBuilder, OpenOptions},
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use crate::{
+    builders::{BuildContext, Image, log::run_logged},
+    config::{DeployConfig, Protocol},
+    deployers::{Container, LogLine, LogOptions, PublishedPort},
+    runtime::{BuildOptions, ContainerRuntime, ImageInfo, RegistryAuth, split_reference},
+};
+
+/// Number of log lines buffered between `<cli> logs` and the reader.
+const LOG_BUFFER: usize = 256;
+
+/// Runs containers by shelling out to a Docker-compatible CLI: `docker`
+/// itself, or `podman`, which accepts the same commands and flags.
+pub struct CliRuntime {
+    program: &'static str,
+}
+
+impl CliRuntime {
+    /// Returns a runtime that uses the `docker` CLI.
+    pub fn docker() -> Self {
+        CliRuntime { program: "docker" }
+    }
+
+    /// Returns a runtime that uses the `podman` CLI.
+    pub fn podman() -> Self {
+        CliRuntime { program: "podman" }
+    }
+
+    fn command(&self) -> Command {
+        Command::new(self.program)
+    }
+
+    /// Runs a CLI subcommand to completion and returns its stdout.
+    async fn output(&self, args: &[&str]) -> anyhow::Result<String> {
+        let subcommand = args.first().copied().unwrap_or_default();
+        let output =
+            self.command().args(args).output().await.map_err(|e| {
+                anyhow::anyhow!("Failed to execute {} {subcommand}: {e}", self.program)
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!(
+                "{} {subcommand} failed: {}\nStderr: {}",
+                self.program,
+                output.status,
+                stderr
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Looks up the host ports a running container's ports were published on.
+    async fn published_ports(&self, container_id: &str) -> anyhow::Result<Vec<PublishedPort>> {
+        let output = self.output(&["port", container_id]).await?;
+
+        // Each line looks like `8080/tcp -> 0.0.0.0:32768`. Docker prints one
+        // line per host address (IPv4 and IPv6), so skip duplicates.
+        let mut ports = Vec::new();
+        for line in output.lines() {
+            let port = parse_port_line(line).ok_or_else(|| {
+                anyhow::anyhow!("Unexpected {} port output: {line}", self.program)
+            })?;
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+
+        Ok(ports)
+    }
+
+    /// Builds the `<cli> run` command for a container. The environment is
+    /// read from `env_file`, so no values end up in the process list.
+    fn run_command(
+        &self,
+        image_ref: &str,
+        container_name: &str,
+        config: &DeployConfig,
+        env_file: Option<&Path>,
+    ) -> Command {
+        let mut cmd = self.command();
+        cmd.arg("run")
+            .arg("--detach")
+            .arg("--name")
+            .arg(container_name)
+            .arg("--restart")
+            .arg(config.restart.to_string());
+
+        if config.ports.is_empty() {
+            cmd.arg("--publish-all");
+        }
+        for port in &config.ports {
+            let spec = match port.host {
+                Some(host) => format!("{host}:{}/{}", port.container, port.protocol.as_str()),
+                None => format!("{}/{}", port.container, port.protocol.as_str()),
+            };
+            cmd.arg("--publish").arg(spec);
+        }
+
+        if let Some(env_file) = env_file {
+            cmd.arg("--env-file").arg(env_file);
+        }
+        for (name, value) in config.env.iter().filter(|(_, value)| is_multiline(value)) {
+            cmd.arg("--env").arg(name).env(name, value);
+        }
+
+        // Docker only accepts the executable via --entrypoint; any extra
+        // entrypoint arguments go in front of the command.
+        let mut args = Vec::new();
+        if let Some((executable, rest)) = config.entrypoint.as_deref().and_then(|e| e.split_first())
+        {
+            cmd.arg("--entrypoint").arg(executable);
+            args.extend(rest.iter().cloned());
+        }
+        if let Some(command) = &config.command {
+            args.extend(command.iter().cloned());
+        }
+
+        cmd.arg(image_ref).args(&args);
+        cmd
+    }
+}
+
+/// A private directory holding the registry credentials for one push, in
+/// the `config.json` format both CLIs read. It's removed when dropped.
+struct AuthConfig {
+    dir: PathBuf,
+}
+
+impl AuthConfig {
+    fn create(auth: &RegistryAuth) -> std::io::Result<Self> {
+        let dir = private_dir("auth")?;
+        let config = AuthConfig { dir };
+
+        let credentials = STANDARD.encode(format!("{}:{}", auth.username, auth.password));
+        let contents = json!({ "auths": { &auth.server: { "auth": credentials } } });
+        write_private(&config.path(), contents.to_string().as_bytes())?;
+
+        Ok(config)
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join("config.json")
+    }
+}
+
+impl Drop for AuthConfig {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A private directory holding a container's environment as an `--env-file`,
+/// so secret values never appear on the command line. It's removed when
+/// dropped.
+struct EnvFile {
+    dir: PathBuf,
+}
+
+impl EnvFile {
+    /// Writes the variables whose values fit on one line; see
+    /// [`is_multiline`] for the rest.
+    fn create(env: &BTreeMap<String, String>) -> std::io::Result<Self> {
+        let mut contents = String::new();
+        for (name, value) in env.iter().filter(|(_, value)| !is_multiline(value)) {
+            contents.push_str(&format!("{name}={value}\n"));
+        }
+
+        let dir = private_dir("env")?;
+        let file = EnvFile { dir };
+        write_private(&file.path(), contents.as_bytes())?;
+
+        Ok(file)
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join("env")
+    }
+}
+
+impl Drop for EnvFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Whether an env var's value can't go in an env file, which has no quoting.
+/// These are passed by name instead, with the value in the CLI's own
+/// environment, which also keeps it out of the process list.
+fn is_multiline(value: &str) -> bool {
+    value.contains(['\n', '\r'])
+}
+
+/// Creates a new temporary directory only the current user can read.
+fn private_dir(kind: &str) -> std::io::Result<PathBuf> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "nimble-{kind}-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    DirBuilder::new().mode(0o700).create(&dir)?;
+    Ok(dir)
+}
+
+/// Writes a new file only the current user can read.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)
+}
+
+/// The fields of `<cli> image inspect` output that Nimble uses.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedImage {
+    id: String,
+    #[serde(default)]
+    repo_digests: Vec<String>,
+    #[serde(default)]
+    size: u64,
+}
+
+fn parse_port_line(line: &str) -> Option<PublishedPort> {
+    let (container, host) = line.split_once(" -> ")?;
+    let (container, protocol) = container.trim().split_once('/')?;
+    let protocol = match protocol {
+        "tcp" => Protocol::Tcp,
+        "udp" => Protocol::Udp,
+        _ => return None,
+    };
+    let (_, host) = host.trim().rsplit_once(':')?;
+
+    Some(PublishedPort {
+        container: container.parse().ok()?,
+        host: host.parse().ok()?,
+        protocol,
+    })
+}
+
+/// Splits a line from `<cli> logs --timestamps` into its timestamp and message.
+pub(crate) fn parse_log_line(line: &str) -> LogLine {
+    // Lines look like `2024-01-15T10:30:00.123456789Z message` (Podman may
+    // use a UTC offset instead of `Z`). Anything else (e.g. an error from the
+    // daemon) is passed on untimestamped.
+    let (timestamp, message) = line.split_once(' ').unwrap_or((line, ""));
+    let is_timestamp = timestamp.len() >= 20
+        && timestamp.as_bytes()[10] == b'T'
+        && timestamp.starts_with(|c: char| c.is_ascii_digit())
+        && (timestamp.ends_with('Z')
+            || timestamp
+                .get(19..)
+                .is_some_and(|offset| offset.contains(['+', '-'])));
+
+    if is_timestamp {
+        LogLine {
+            timestamp: Some(timestamp.to_string()),
+            message: message.to_string(),
+        }
+    } else {
+        LogLine {
+            timestamp: None,
+            message: line.to_string(),
+        }
+    }
+}
+
+/// Sends each line read from `reader` to the channel, until the reader ends
+/// or the receiver is dropped.
+async fn forward_log_lines(reader: impl AsyncRead + Unpin, tx: Sender<LogLine>) {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        let read = tokio::select! {
+            read = reader.read_until(b'\n', &mut buf) => read,
+            _ = tx.closed() => break,
+        };
+        if !matches!(read, Ok(n) if n > 0) {
+            break;
+        }
+
+        let line = String::from_utf8_lossy(&buf);
+        let line = parse_log_line(line.trim_end_matches(['\r', '\n']));
+        if tx.send(line).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[async_trait]
+impl ContainerRuntime for CliRuntime {
+    async fn build(&self, options: &BuildOptions, ctx: &BuildContext) -> anyhow::Result<Image> {
+        // Run the build, streaming its output to the build log. BuildKit
+        // redraws its progress in place unless asked for plain output.
+        let mut cmd = self.command();
+        cmd.env("BUILDKIT_PROGRESS", "plain")
+            .arg("build")
+            .arg("--tag")
+            .arg(&options.tag)
+            .arg("--file")
+            .arg(&options.dockerfile);
+        if options.no_cache {
+            cmd.arg("--no-cache").arg("--pull=always");
+        }
+        if let Some(target) = &options.target {
+            cmd.arg("--target").arg(target);
+        }
+        for (name, value) in &options.build_args {
+            cmd.arg("--build-arg").arg(format!("{name}={value}"));
+        }
+        // Secret values are passed through the environment, so they don't
+        // show up in the process list. The variable names are our own, so a
+        // secret can't override one the CLI reads.
+        for (i, secret) in options.secrets.iter().enumerate() {
+            let var = format!("NIMBLE_BUILD_SECRET_{i}");
+            cmd.arg("--secret")
+                .arg(format!("id={},env={var}", secret.id))
+                .env(var, &secret.value);
+        }
+        cmd.arg(&options.context);
+
+        let status = run_logged(cmd, ctx)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to execute {} build: {e}", self.program))?;
+
+        if !status.success() {
+            anyhow::bail!(
+                "{} build failed: {status} (see the build logs for details)",
+                self.program
+            );
+        }
+
+        // Prefer the registry digest, falling back to the image ID
+        let digest = self
+            .inspect_image(&options.tag)
+            .await
+            .ok()
+            .map(|info| info.digest.unwrap_or(info.id));
+
+        Ok(Image {
+            reference: options.tag.clone(),
+            digest,
+        })
+    }
+
+    async fn inspect_image(&self, image_ref: &str) -> anyhow::Result<ImageInfo> {
+        let output = self.output(&["image", "inspect", image_ref]).await?;
+        let images: Vec<InspectedImage> = serde_json::from_str(&output)
+            .map_err(|e| anyhow::anyhow!("Unexpected {} inspect output: {e}", self.program))?;
+        let Some(image) = images.into_iter().next() else {
+            anyhow::bail!("No such image: {image_ref}");
+        };
+
+        // Podman leaves the algorithm off image IDs
+        let id = if image.id.contains(':') {
+            image.id
+        } else {
+            format!("sha256:{}", image.id)
+        };
+        // Repo digests look like `image@sha256:abc123...`
+        let digest = image
+            .repo_digests
+            .first()
+            .and_then(|repo_digest| repo_digest.split_once('@'))
+            .map(|(_, digest)| digest.to_string());
+
+        Ok(ImageInfo {
+            id,
+            digest,
+            size: image.size,
+        })
+    }
+
+    async fn tag(&self, image_ref: &str, target: &str) -> anyhow::Result<()> {
+        self.output(&["tag", image_ref, target]).await?;
+        Ok(())
+    }
+
+    async fn push(
+        &self,
+        image_ref: &str,
+        auth: Option<&RegistryAuth>,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<String> {
+        let mut cmd = self.command();
+        cmd.arg("push").arg(image_ref);
+
+        // Credentials go in an auth file used just for this push, rather than
+        // `<cli> login`, which would keep them for everything else the CLI
+        // runs. Docker reads it from DOCKER_CONFIG, Podman from
+        // REGISTRY_AUTH_FILE.
+        let auth_config = auth
+            .map(AuthConfig::create)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Failed to write registry credentials: {e}"))?;
+        if let Some(auth_config) = &auth_config {
+            cmd.env("DOCKER_CONFIG", &auth_config.dir)
+                .env("REGISTRY_AUTH_FILE", auth_config.path());
+        }
+
+        let status = run_logged(cmd, ctx)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to execute {} push: {e}", self.program))?;
+        drop(auth_config);
+
+        if !status.success() {
+            anyhow::bail!(
+                "{} push failed: {status} (see the build logs for details)",
+                self.program
+            );
+        }
+
+        // Pushing records the registry's digest as a repo digest, like
+        // `localhost:5000/app@sha256:abc123...`
+        let output = self.output(&["image", "inspect", image_ref]).await?;
+        let images: Vec<InspectedImage> = serde_json::from_str(&output)
+            .map_err(|e| anyhow::anyhow!("Unexpected {} inspect output: {e}", self.program))?;
+        let (repository, _) = split_reference(image_ref);
+        images
+            .iter()
+            .flat_map(|image| &image.repo_digests)
+            .find_map(|repo_digest| {
+                let (name, digest) = repo_digest.split_once('@')?;
+                (name == repository).then(|| digest.to_string())
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Pushed {image_ref}, but {} didn't report its digest",
+                    self.program
+                )
+            })
+    }
+
+    async fn run(
+        &self,
+        image_ref: &str,
+        container_name: &str,
+        config: &DeployConfig,
+    ) -> anyhow::Result<Container> {
+        // Kept until the container has been created, which reads it
+        let env_file =
+            if config.env.is_empty() {
+                None
+            } else {
+                Some(EnvFile::create(&config.env).map_err(|e| {
+                    anyhow::anyhow!("Failed to write the container's environment: {e}")
+                })?)
+            };
+        let env_path = env_file.as_ref().map(EnvFile::path);
+
+        let output = self
+            .run_command(image_ref, container_name, config, env_path.as_deref())
+            .output()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to execute {} run: {e}", self.program))?;
+        drop(env_file);
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!(
+                "{} run failed: {}\nStderr: {}",
+                self.program,
+                output.status,
+                stderr
+            );
+        }
+
+        let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if id.is_empty() {
+            anyhow::bail!("{} run did not return a container ID", self.program);
+        }
+
+        let ports = match self.published_ports(&id).await {
+            Ok(ports) => ports,
+            Err(e) => {
+                // Don't leave a container behind that nothing tracks
+                let _ = self.stop(&id).await;
+                let _ = self.remove(&id).await;
+                return Err(e);
+            }
+        };
+
+        Ok(Container {
+            id,
+            name: container_name.to_string(),
+            ports,
+        })
+    }
+
+    async fn stop(&self, container_id: &str) -> anyhow::Result<()> {
+        self.output(&["stop", container_id]).await?;
+        Ok(())
+    }
+
+    async fn start(&self, container_id: &str) -> anyhow::Result<Vec<PublishedPort>> {
+        self.output(&["start", container_id]).await?;
+        self.published_ports(container_id).await
+    }
+
+    async fn remove(&self, container_id: &str) -> anyhow::Result<()> {
+        self.output(&["rm", container_id]).await?;
+        Ok(())
+    }
+
+    async fn logs(
+        &self,
+        container_id: &str,
+        options: &LogOptions,
+    ) -> anyhow::Result<Receiver<LogLine>> {
+        let mut cmd = self.command();
+        cmd.arg("logs").arg("--timestamps");
+        if let Some(since) = options.since {
+            cmd.arg("--since").arg(format!("{}s", since.as_secs()));
+        }
+        if let Some(tail) = options.tail {
+            cmd.arg("--tail").arg(tail.to_string());
+        }
+        if options.follow {
+            cmd.arg("--follow");
+        }
+
+        let mut child = cmd
+            .arg(container_id)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to execute {} logs: {e}", self.program))?;
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let (tx, rx) = mpsc::channel(LOG_BUFFER);
+
+        tokio::spawn(async move {
+            tokio::join!(
+                forward_log_lines(stdout, tx.clone()),
+                forward_log_lines(stderr, tx)
+            );
+            // Kills `<cli> logs` if it's still following
+            drop(child);
+        });
+
+        Ok(rx)
+    }
+
+    async fn save(&self, image_ref: &str, path: &Path) -> anyhow::Result<()> {
+        let path = path.to_string_lossy();
+        self.output(&["save", "--output", &path, image_ref]).await?;
+        Ok(())
+    }
+
+    async fn load(&self, path: &Path) -> anyhow::Result<String> {
+        let path = path.to_string_lossy();
+        let output = self.output(&["load", "--input", &path]).await?;
+
+        // Both print `Loaded image: <reference>` (or `Loaded image ID: <id>`
+        // for untagged images) as the last line; older Podman releases say
+        // `Loaded image(s):`
+        output
+            .lines()
+            .rev()
+            .find_map(|line| {
+                let (label, image) = line.split_once(": ")?;
+                label
+                    .starts_with("Loaded image")
+                    .then(|| image.trim().to_string())
+            })
+            .ok_or_else(|| anyhow::anyhow!("Unexpected {} load output: {output}", self.program))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::config::DeployTarget;
+
+    fn env() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("API_TOKEN".to_string(), "s3cr3t".to_string()),
+            ("GREETING".to_string(), "hello = world".to_string()),
+        ])
+    }
+
+    #[test]
+    fn run_command_keeps_env_values_off_the_command_line() {
+        let mut config = DeployConfig::new(DeployTarget::LocalDocker);
+        config.env = env();
+        let env_file = Path::new("/tmp/nimble-env-test/env");
+        let cmd = CliRuntime::docker().run_command("app:1", "app-1", &config, Some(env_file));
+
+        let args: Vec<_> = cmd
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        assert!(
+            args.windows(2)
+                .any(|pair| pair[0] == "--env-file" && pair[1] == "/tmp/nimble-env-test/env")
+        );
+        for arg in &args {
+            assert!(!arg.contains("s3cr3t"), "value leaked into {arg}");
+            assert!(!arg.contains("API_TOKEN"), "name leaked into {arg}");
+            assert!(!arg.contains("hello"), "value leaked into {arg}");
+        }
+        assert!(
+            cmd.as_std()
+                .get_envs()
+                .all(|(_, value)| value.is_none_or(|v| v != "s3cr3t"))
+        );
+    }
+
+    #[test]
+    fn env_file_is_private_and_removed_on_drop() {
+        let file = EnvFile::create(&env()).unwrap();
+        let path = file.path();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "API_TOKEN=s3cr3t\nGREETING=hello = world\n"
+        );
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn multiline_values_are_passed_through_the_environment() {
+        let mut config = DeployConfig::new(DeployTarget::LocalDocker);
+        config.env = env();
+        config
+            .env
+            .insert("TLS_KEY".to_string(), "-----BEGIN\nsecret\n".to_string());
+
+        let file = EnvFile::create(&config.env).unwrap();
+        assert!(
+            !std::fs::read_to_string(file.path())
+                .unwrap()
+                .contains("TLS_KEY")
+        );
+
+        let cmd = CliRuntime::docker().run_command("app:1", "app-1", &config, Some(&file.path()));
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.windows(2).any(|pair| pair == ["--env", "TLS_KEY"]));
+        assert!(
+            args.iter()
+                .all(|arg| !arg.to_string_lossy().contains("secret"))
+        );
+        assert!(cmd.as_std().get_envs().any(
+            |(name, value)| name == "TLS_KEY" && value == Some("-----BEGIN\nsecret\n".as_ref())
+        ));
+    }
+}
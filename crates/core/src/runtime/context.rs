@@ -0,0 +1,376 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Component, Path},
+};
+
+/// Name the Dockerfile is sent under when it's outside the build context
+const EXTERNAL_DOCKERFILE: &str = ".nimble.external.Dockerfile";
+
+/// Returns the path of the Dockerfile within the archive written by
+/// [`write_context`].
+pub(crate) fn dockerfile_name(context: &Path, dockerfile: &Path) -> String {
+    match dockerfile.strip_prefix(context) {
+        Ok(relative) => slash_path(relative),
+        Err(_) => EXTERNAL_DOCKERFILE.to_string(),
+    }
+}
+
+/// Writes a build context to `writer` as a tar archive, leaving out the
+/// files excluded by its ignore file.
+///
+/// Like BuildKit, the ignore file is `<dockerfile>.dockerignore` if there is
+/// one, and otherwise `.dockerignore` in the context. The Dockerfile itself
+/// is always sent, since the daemon needs to read it.
+pub(crate) fn write_context(
+    context: &Path,
+    dockerfile: &Path,
+    writer: impl Write,
+) -> io::Result<()> {
+    let dockerfile_ignore = format!("{}.dockerignore", dockerfile.display());
+    let ignore = fs::read_to_string(dockerfile_ignore)
+        .or_else(|_| fs::read_to_string(context.join(".dockerignore")))
+        .unwrap_or_default();
+    let ignore = IgnoreRules::parse(&ignore);
+
+    let mut archive = tar::Builder::new(writer);
+    archive.follow_symlinks(false);
+    append_dir(&mut archive, context, "", &ignore)?;
+
+    let name = dockerfile_name(context, dockerfile);
+    if name == EXTERNAL_DOCKERFILE || ignore.is_excluded(&name) {
+        archive.append_path_with_name(dockerfile, &name)?;
+    }
+
+    archive.into_inner()?.flush()
+}
+
+/// Adds the contents of `dir`, whose path in the archive is `prefix`, in a
+/// stable order.
+fn append_dir<W: Write>(
+    archive: &mut tar::Builder<W>,
+    dir: &Path,
+    prefix: &str,
+    ignore: &IgnoreRules,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        let name = if prefix.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{prefix}/{file_name}")
+        };
+        let file_type = entry.file_type()?;
+        let excluded = ignore.is_excluded(&name);
+
+        if file_type.is_dir() {
+            // Files in an excluded directory can only come back through an
+            // exception, so only look inside if there are any
+            if excluded && !ignore.has_exceptions() {
+                continue;
+            }
+            if !excluded {
+                archive.append_dir(&name, entry.path())?;
+            }
+            append_dir(archive, &entry.path(), &name, ignore)?;
+        } else if !excluded {
+            archive.append_path_with_name(entry.path(), &name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Formats a relative path with `/` separators, as used in the archive.
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Patterns from a .dockerignore file.
+struct IgnoreRules {
+    patterns: Vec<IgnorePattern>,
+}
+
+struct IgnorePattern {
+    /// The pattern's path segments, which may contain wildcards
+    segments: Vec<String>,
+    /// Whether the pattern starts with `!`, re-including what it matches
+    exception: bool,
+}
+
+impl IgnoreRules {
+    fn parse(contents: &str) -> Self {
+        let patterns = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (exception, pattern) = match line.strip_prefix('!') {
+                    Some(pattern) => (true, pattern.trim()),
+                    None => (false, line),
+                };
+                // Patterns are relative to the context root, and are cleaned
+                // like paths
+                let segments = pattern
+                    .split('/')
+                    .filter(|segment| !segment.is_empty() && *segment != ".")
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                (!segments.is_empty()).then_some(IgnorePattern {
+                    segments,
+                    exception,
+                })
+            })
+            .collect();
+
+        IgnoreRules { patterns }
+    }
+
+    fn has_exceptions(&self) -> bool {
+        self.patterns.iter().any(|pattern| pattern.exception)
+    }
+
+    /// Whether the file at `path` (relative to the context root, with `/`
+    /// separators) is left out. The last matching pattern decides.
+    fn is_excluded(&self, path: &str) -> bool {
+        let path = path.split('/').collect::<Vec<_>>();
+        let mut excluded = false;
+        for pattern in &self.patterns {
+            if pattern.matches(&path) {
+                excluded = !pattern.exception;
+            }
+        }
+        excluded
+    }
+}
+
+impl IgnorePattern {
+    /// Whether the pattern matches the path or one of its parent
+    /// directories, since excluding a directory excludes everything in it.
+    fn matches(&self, path: &[&str]) -> bool {
+        (1..=path.len()).any(|len| match_segments(&self.segments, &path[..len]))
+    }
+}
+
+/// Matches path segments against pattern segments, where a `**` segment
+/// matches any number of path segments.
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => {
+                match_glob(first.as_bytes(), segment.as_bytes()) && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Matches a single path segment against a glob with `*`, `?`, `[...]`
+/// character classes and `\` escapes.
+fn match_glob(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| match_glob(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && match_glob(rest, &name[1..]),
+        Some((b'[', rest)) => {
+            let Some((&c, name_rest)) = name.split_first() else {
+                return false;
+            };
+            match match_class(rest, c) {
+                Some((true, rest)) => match_glob(rest, name_rest),
+                // An unterminated class is matched literally
+                None => c == b'[' && match_glob(rest, name_rest),
+                Some((false, _)) => false,
+            }
+        }
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            name.first() == rest.first() && match_glob(&rest[1..], &name[1..])
+        }
+        Some((&p, rest)) => name.first() == Some(&p) && match_glob(rest, &name[1..]),
+    }
+}
+
+/// Matches a character against the class at the start of `pattern` (just
+/// after its `[`), returning whether it matched and the rest of the pattern.
+/// A `\` escapes the character after it, e.g. `[\]]`.
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut pattern) = match pattern.first() {
+        Some(b'^' | b'!') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match pattern {
+            [] => return None,
+            [b']', rest @ ..] if !first => return Some((matched != negated, rest)),
+            [b'\\', x, rest @ ..] => {
+                matched |= *x == c;
+                pattern = rest;
+            }
+            [lo, b'-', hi, rest @ ..] if *hi != b']' => {
+                matched |= (*lo..=*hi).contains(&c);
+                pattern = rest;
+            }
+            [x, rest @ ..] => {
+                matched |= *x == c;
+                pattern = rest;
+            }
+        }
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excluded(ignore: &str, path: &str) -> bool {
+        IgnoreRules::parse(ignore).is_excluded(path)
+    }
+
+    /// Writes `files` into a temporary context and returns the paths of the
+    /// files sent in its archive.
+    fn sent(files: &[(&str, &str)], dockerfile: &str) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let mut archive = Vec::new();
+        write_context(dir.path(), &dir.path().join(dockerfile), &mut archive).unwrap();
+        tar::Archive::new(archive.as_slice())
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.header().entry_type().is_file())
+            .map(|entry| entry.path().unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn patterns_match_from_the_context_root() {
+        assert!(excluded("target", "target"));
+        assert!(excluded("/target", "target/debug/app"));
+        assert!(excluded("./target/", "target"));
+        assert!(!excluded("target", "crates/target"));
+        assert!(excluded("*.log", "build.log"));
+        assert!(!excluded("*.log", "logs/build.log"));
+        assert!(excluded("docs/?.md", "docs/a.md"));
+        assert!(!excluded("docs/?.md", "docs/ab.md"));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        assert!(excluded("**/node_modules", "node_modules"));
+        assert!(excluded(
+            "**/node_modules",
+            "packages/web/node_modules/react/index.js"
+        ));
+        assert!(excluded("**/*.pyc", "app/__pycache__/main.pyc"));
+        assert!(excluded("src/**/test", "src/test"));
+        assert!(excluded("src/**/test", "src/a/b/test"));
+        assert!(!excluded("src/**/test", "lib/test"));
+        assert!(excluded("**", "anything/at/all"));
+    }
+
+    #[test]
+    fn last_matching_pattern_wins() {
+        let ignore = "*.md\n!README.md\n";
+        assert!(excluded(ignore, "CHANGELOG.md"));
+        assert!(!excluded(ignore, "README.md"));
+
+        let ignore = "!README.md\n*.md\n";
+        assert!(excluded(ignore, "README.md"));
+    }
+
+    #[test]
+    fn exceptions_reinclude_files_in_excluded_directories() {
+        let files = [
+            ("Dockerfile", ""),
+            ("config/dev.yaml", ""),
+            ("config/prod.yaml", ""),
+            ("config/secrets/key", ""),
+            ("node_modules/react/index.js", ""),
+            (".dockerignore", "config\n!config/prod.yaml\nnode_modules\n"),
+        ];
+        assert_eq!(
+            sent(&files, "Dockerfile"),
+            [".dockerignore", "Dockerfile", "config/prod.yaml"]
+        );
+    }
+
+    #[test]
+    fn character_classes_match_one_character() {
+        assert!(excluded("file[0-9].txt", "file7.txt"));
+        assert!(!excluded("file[0-9].txt", "filex.txt"));
+        assert!(excluded("file[abc].txt", "fileb.txt"));
+        assert!(!excluded("file[^abc].txt", "fileb.txt"));
+        assert!(excluded("file[!abc].txt", "filed.txt"));
+        // `]` first in a class is literal
+        assert!(excluded("file[]x].txt", "file].txt"));
+        assert!(excluded("file[\\]].txt", "file].txt"));
+        // An unterminated class is matched literally
+        assert!(excluded("file[0-9", "file[0-9"));
+    }
+
+    #[test]
+    fn escapes_match_wildcards_literally() {
+        assert!(excluded("\\*.txt", "*.txt"));
+        assert!(!excluded("\\*.txt", "a.txt"));
+        assert!(excluded("what\\?", "what?"));
+        assert!(!excluded("what\\?", "whats"));
+        assert!(excluded("\\[draft\\]*", "[draft] notes"));
+    }
+
+    #[test]
+    fn dockerfile_ignore_file_takes_precedence() {
+        let files = [
+            ("Dockerfile", ""),
+            ("app.Dockerfile", ""),
+            ("app.Dockerfile.dockerignore", "*.log\n"),
+            (".dockerignore", "*.txt\n"),
+            ("build.log", ""),
+            ("notes.txt", ""),
+        ];
+        // Only the Dockerfile's own ignore file applies
+        let sent_for_app = sent(&files, "app.Dockerfile");
+        assert!(sent_for_app.contains(&"notes.txt".to_string()));
+        assert!(!sent_for_app.contains(&"build.log".to_string()));
+
+        // Other Dockerfiles fall back to .dockerignore
+        let sent_for_default = sent(&files, "Dockerfile");
+        assert!(!sent_for_default.contains(&"notes.txt".to_string()));
+        assert!(sent_for_default.contains(&"build.log".to_string()));
+    }
+
+    #[test]
+    fn ignored_dockerfile_is_still_sent() {
+        let files = [
+            (".nimble.Dockerfile", ""),
+            (".nimble.Dockerfile.dockerignore", ".nimble.*\n"),
+            ("main.go", ""),
+        ];
+        assert_eq!(
+            sent(&files, ".nimble.Dockerfile"),
+            ["main.go", ".nimble.Dockerfile"]
+        );
+    }
+}
@@ -0,0 +1,1318 @@
+use std::{collections::BTreeMap, fmt, path::Path, time::SystemTime};
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::URL_SAFE};
+use bytes::{Buf, Bytes, BytesMut};
+use http_body_util::{BodyExt, Full, StreamBody, combinators::BoxBody};
+use hyper::{
+    Method, Request, Response, StatusCode,
+    body::{Frame, Incoming},
+    header,
+};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UnixStream},
+    sync::mpsc::{self, Receiver, Sender},
+};
+use tokio_util::io::{ReaderStream, SyncIoBridge};
+
+use crate::{
+    builders::{BuildContext, Image},
+    config::{DeployConfig, Protocol, RestartPolicy},
+    deployers::{Container, LogLine, LogOptions, PublishedPort},
+    runtime::{
+        BuildOptions, ContainerRuntime, ImageInfo, RegistryAuth,
+        cli::parse_log_line,
+        context::{dockerfile_name, write_context},
+        split_reference,
+    },
+};
+
+/// Engine API version requested, supported by Docker 20.10 and later and by
+/// Podman's Docker-compatible API
+const API_VERSION: &str = "v1.41";
+
+/// Socket the Docker daemon listens on by default
+const DEFAULT_DOCKER_HOST: &str = "unix:///var/run/docker.sock";
+
+/// Number of log lines buffered between the daemon and the reader.
+const LOG_BUFFER: usize = 256;
+
+/// Size of the pipe the build context is streamed through.
+const CONTEXT_BUFFER: usize = 64 * 1024;
+
+/// Seconds a container gets to exit after SIGTERM before it's killed, as with
+/// `docker stop`
+const STOP_TIMEOUT: u32 = 10;
+
+type RequestBody = BoxBody<Bytes, std::io::Error>;
+
+/// Errors returned by the Docker Engine API client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    /// The daemon couldn't be reached, or the connection failed
+    Connection(String),
+    /// The daemon answered a request with an error status
+    Api { status: u16, message: String },
+    /// The daemon reported an error partway through a streamed response,
+    /// e.g. a failed build step
+    Stream(String),
+    /// The daemon's response couldn't be understood
+    InvalidResponse(String),
+    /// The Docker host isn't a supported address
+    InvalidHost(String),
+}
+
+impl EngineError {
+    /// Whether the daemon said the image or container doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, EngineError::Api { status: 404, .. })
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Connection(msg) => write!(f, "Failed to talk to Docker: {msg}"),
+            EngineError::Api { status, message } => {
+                write!(f, "Docker returned an error ({status}): {message}")
+            }
+            EngineError::Stream(msg) => write!(f, "Docker reported an error: {msg}"),
+            EngineError::InvalidResponse(msg) => {
+                write!(f, "Unexpected response from Docker: {msg}")
+            }
+            EngineError::InvalidHost(host) => write!(
+                f,
+                "Invalid Docker host: {host}. Use unix://<socket path> or tcp://<host>:<port>"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// Where the daemon listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Endpoint {
+    Unix(String),
+    Tcp(String),
+}
+
+/// Runs containers through the Docker Engine API, over the daemon's Unix
+/// socket or a TCP address.
+pub struct EngineRuntime {
+    endpoint: Endpoint,
+}
+
+impl EngineRuntime {
+    /// Creates a client for the daemon at `host`, in `DOCKER_HOST` syntax:
+    /// `unix:///var/run/docker.sock` or `tcp://127.0.0.1:2375`. TLS isn't
+    /// supported.
+    pub fn new(host: &str) -> Result<Self, EngineError> {
+        let endpoint = if let Some(path) = host.strip_prefix("unix://") {
+            Endpoint::Unix(path.to_string())
+        } else if let Some(addr) = host.strip_prefix("tcp://") {
+            Endpoint::Tcp(addr.trim_end_matches('/').to_string())
+        } else {
+            return Err(EngineError::InvalidHost(host.to_string()));
+        };
+
+        Ok(EngineRuntime { endpoint })
+    }
+
+    /// Creates a client for the daemon named by `DOCKER_HOST`, or the default
+    /// Unix socket if it's unset.
+    pub fn from_env() -> Result<Self, EngineError> {
+        let host = std::env::var("DOCKER_HOST")
+            .ok()
+            .filter(|host| !host.is_empty())
+            .unwrap_or_else(|| DEFAULT_DOCKER_HOST.to_string());
+        Self::new(&host)
+    }
+
+    /// Sends a request on a new connection to the daemon.
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: RequestBody,
+        headers: &[(&str, &str)],
+    ) -> Result<Response<Incoming>, EngineError> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("/{API_VERSION}{path}"))
+            .header(header::HOST, "docker");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request
+            .body(body)
+            .map_err(|e| EngineError::Connection(e.to_string()))?;
+
+        let response = match &self.endpoint {
+            Endpoint::Unix(path) => {
+                let stream = UnixStream::connect(path)
+                    .await
+                    .map_err(|e| EngineError::Connection(format!("{path}: {e}")))?;
+                send_on(stream, request).await?
+            }
+            Endpoint::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)
+                    .await
+                    .map_err(|e| EngineError::Connection(format!("{addr}: {e}")))?;
+                send_on(stream, request).await?
+            }
+        };
+
+        if response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(response);
+        }
+
+        // Errors come as `{"message": "..."}`
+        let status = response.status().as_u16();
+        let body = read_body(response).await?;
+        let message = serde_json::from_slice::<ErrorBody>(&body)
+            .map(|error| error.message)
+            .unwrap_or_else(|_| String::from_utf8_lossy(&body).trim().to_string());
+        Err(EngineError::Api { status, message })
+    }
+
+    /// Sends a request without a body and returns the response body.
+    async fn call(&self, method: Method, path: &str) -> Result<Bytes, EngineError> {
+        let response = self.send(method, path, empty(), &[]).await?;
+        read_body(response).await
+    }
+
+    /// Sends a request with a JSON body and parses the JSON response.
+    async fn call_json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<T, EngineError> {
+        let body = Full::new(Bytes::from(body.to_string()))
+            .map_err(|never| match never {})
+            .boxed();
+        let response = self
+            .send(method, path, body, &[("content-type", "application/json")])
+            .await?;
+        parse_json(&read_body(response).await?)
+    }
+
+    /// Looks up the host ports a container's ports were published on.
+    async fn published_ports(&self, container_id: &str) -> Result<Vec<PublishedPort>, EngineError> {
+        let body = self
+            .call(Method::GET, &format!("/containers/{container_id}/json"))
+            .await?;
+        let inspected: InspectedContainer = parse_json(&body)?;
+
+        let mut ports = Vec::new();
+        for (spec, bindings) in inspected.network_settings.ports {
+            let Some((container, protocol)) = spec.split_once('/') else {
+                continue;
+            };
+            let protocol = match protocol {
+                "tcp" => Protocol::Tcp,
+                "udp" => Protocol::Udp,
+                _ => continue,
+            };
+            let Ok(container) = container.parse() else {
+                continue;
+            };
+            // There's a binding per host address (IPv4 and IPv6)
+            for binding in bindings.unwrap_or_default() {
+                let Ok(host) = binding.host_port.parse() else {
+                    continue;
+                };
+                let port = PublishedPort {
+                    container,
+                    host,
+                    protocol,
+                };
+                if !ports.contains(&port) {
+                    ports.push(port);
+                }
+            }
+        }
+
+        Ok(ports)
+    }
+}
+
+/// Sends a request over a fresh HTTP/1 connection on `stream`.
+async fn send_on<S>(
+    stream: S,
+    request: Request<RequestBody>,
+) -> Result<Response<Incoming>, EngineError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| EngineError::Connection(e.to_string()))?;
+    tokio::spawn(connection);
+
+    sender
+        .send_request(request)
+        .await
+        .map_err(|e| EngineError::Connection(e.to_string()))
+}
+
+fn empty() -> RequestBody {
+    Full::new(Bytes::new())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+async fn read_body(response: Response<Incoming>) -> Result<Bytes, EngineError> {
+    response
+        .into_body()
+        .collect()
+        .await
+        .map(|body| body.to_bytes())
+        .map_err(|e| EngineError::Connection(e.to_string()))
+}
+
+fn parse_json<T: DeserializeOwned>(body: &[u8]) -> Result<T, EngineError> {
+    serde_json::from_slice(body).map_err(|e| EngineError::InvalidResponse(e.to_string()))
+}
+
+/// Reads a response made of newline-separated JSON messages, as sent for
+/// builds and image loads.
+struct MessageStream {
+    body: Incoming,
+    buf: BytesMut,
+    done: bool,
+}
+
+impl MessageStream {
+    fn new(response: Response<Incoming>) -> Self {
+        MessageStream {
+            body: response.into_body(),
+            buf: BytesMut::new(),
+            done: false,
+        }
+    }
+
+    /// Returns the next message as it arrives, or `None` at the end of the
+    /// response. A message reporting an error is returned as an error.
+    async fn next(&mut self) -> Result<Option<StreamMessage>, EngineError> {
+        loop {
+            let line = match self.buf.iter().position(|&b| b == b'\n') {
+                Some(end) => self.buf.split_to(end + 1),
+                None if self.done => self.buf.split(),
+                None => {
+                    match self.body.frame().await {
+                        Some(frame) => {
+                            let frame =
+                                frame.map_err(|e| EngineError::Connection(e.to_string()))?;
+                            if let Ok(data) = frame.into_data() {
+                                self.buf.extend_from_slice(&data);
+                            }
+                        }
+                        None => self.done = true,
+                    }
+                    continue;
+                }
+            };
+
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                if self.done && self.buf.is_empty() {
+                    return Ok(None);
+                }
+                continue;
+            }
+            let message: StreamMessage = parse_json(line)?;
+            if let Some(error) = message.error {
+                return Err(EngineError::Stream(error));
+            }
+            return Ok(Some(message));
+        }
+    }
+}
+
+/// Body of an error response.
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+/// A message in a build, push or image load progress stream.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct StreamMessage {
+    /// Output to show, which may span several lines or part of one
+    stream: Option<String>,
+    /// Status update, e.g. while pulling a base image
+    status: Option<String>,
+    /// Layer the status is about
+    id: Option<String>,
+    /// Progress bar for the status, sent many times per layer
+    progress: Option<String>,
+    /// Set if the operation failed
+    error: Option<String>,
+    /// Extra data, such as the ID of the built image
+    aux: Option<serde_json::Value>,
+}
+
+impl StreamMessage {
+    /// Returns the status update to log, if any. Progress bar updates are
+    /// left out, since they'd flood the log.
+    fn status_line(&self) -> Option<String> {
+        if self.progress.is_some() {
+            return None;
+        }
+        let status = self.status.as_ref()?;
+        Some(match &self.id {
+            Some(id) => format!("{id}: {status}"),
+            None => status.clone(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedImage {
+    id: String,
+    #[serde(default)]
+    repo_digests: Vec<String>,
+    #[serde(default)]
+    size: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CreatedContainer {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedContainer {
+    network_settings: NetworkSettings,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct NetworkSettings {
+    #[serde(default)]
+    ports: BTreeMap<String, Option<Vec<PortBinding>>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PortBinding {
+    host_port: String,
+}
+
+/// Percent-encodes a query parameter value.
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Returns the container config for `POST /containers/create`.
+fn create_body(image_ref: &str, config: &DeployConfig) -> serde_json::Value {
+    let mut exposed = serde_json::Map::new();
+    let mut bindings = serde_json::Map::new();
+    for port in &config.ports {
+        let spec = format!("{}/{}", port.container, port.protocol.as_str());
+        // An empty host port lets Docker pick a free one
+        let host = port.host.map(|host| host.to_string()).unwrap_or_default();
+        exposed.insert(spec.clone(), json!({}));
+        bindings.insert(spec, json!([{ "HostPort": host }]));
+    }
+
+    let (policy, max_retries) = match config.restart {
+        RestartPolicy::No => ("no", 0),
+        RestartPolicy::Always => ("always", 0),
+        RestartPolicy::OnFailure { max_retries } => ("on-failure", max_retries.unwrap_or(0)),
+        RestartPolicy::UnlessStopped => ("unless-stopped", 0),
+    };
+
+    let env = config
+        .env
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>();
+
+    let mut body = json!({
+        "Image": image_ref,
+        "Env": env,
+        "ExposedPorts": exposed,
+        "HostConfig": {
+            "PortBindings": bindings,
+            "PublishAllPorts": config.ports.is_empty(),
+            "RestartPolicy": { "Name": policy, "MaximumRetryCount": max_retries },
+        },
+    });
+    if let Some(entrypoint) = &config.entrypoint {
+        body["Entrypoint"] = json!(entrypoint);
+    }
+    if let Some(command) = &config.command {
+        body["Cmd"] = json!(command);
+    }
+    body
+}
+
+/// Splits the multiplexed stream of a container's stdout and stderr into
+/// lines and sends them to the channel, until the stream ends or the receiver
+/// is dropped.
+///
+/// Each chunk of output comes in a frame with an 8-byte header: the stream
+/// it's from, three zero bytes, then the payload length as a big-endian u32.
+async fn forward_log_frames(response: Response<Incoming>, tx: Sender<LogLine>) {
+    let mut body = response.into_body();
+    let mut buf = BytesMut::new();
+    // Output of stdout and stderr, which may end partway through a line
+    let mut lines = [BytesMut::new(), BytesMut::new()];
+
+    loop {
+        let frame = tokio::select! {
+            frame = body.frame() => frame,
+            _ = tx.closed() => return,
+        };
+        let Some(Ok(frame)) = frame else {
+            break;
+        };
+        if let Ok(data) = frame.into_data() {
+            buf.extend_from_slice(&data);
+        }
+
+        while buf.len() >= 8 {
+            let len = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+            if buf.len() < 8 + len {
+                break;
+            }
+            let stream = usize::from(buf[0] == 2);
+            buf.advance(8);
+            lines[stream].extend_from_slice(&buf.split_to(len));
+
+            while let Some(end) = lines[stream].iter().position(|&b| b == b'\n') {
+                let line = lines[stream].split_to(end + 1);
+                let line = String::from_utf8_lossy(&line);
+                let line = parse_log_line(line.trim_end_matches(['\r', '\n']));
+                if tx.send(line).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    // Output that didn't end in a newline
+    for rest in lines {
+        if !rest.is_empty() {
+            let line = parse_log_line(String::from_utf8_lossy(&rest).trim_end());
+            if tx.send(line).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ContainerRuntime for EngineRuntime {
+    async fn build(&self, options: &BuildOptions, ctx: &BuildContext) -> anyhow::Result<Image> {
+        // The API builds with the classic builder, which has no secret mounts
+        if !options.secrets.is_empty() {
+            anyhow::bail!(
+                "Build secrets need BuildKit, which the Docker Engine API doesn't build with; \
+                 set NIMBLE_CONTAINER_RUNTIME=docker-cli to build with the docker CLI"
+            );
+        }
+
+        // Stream the context to the daemon as it's archived
+        let (reader, writer) = tokio::io::duplex(CONTEXT_BUFFER);
+        let (context, dockerfile) = (options.context.clone(), options.dockerfile.clone());
+        let archive = tokio::task::spawn_blocking(move || {
+            let mut writer = SyncIoBridge::new(writer);
+            write_context(&context, &dockerfile, &mut writer)?;
+            writer.shutdown()
+        });
+
+        let dockerfile = dockerfile_name(&options.context, &options.dockerfile);
+        let mut query = format!(
+            "/build?t={}&dockerfile={}&rm=1&forcerm=1",
+            encode(&options.tag),
+            encode(&dockerfile)
+        );
+        if options.no_cache {
+            query.push_str("&nocache=1&pull=1");
+        }
+        if let Some(target) = &options.target {
+            query.push_str(&format!("&target={}", encode(target)));
+        }
+        if !options.build_args.is_empty() {
+            let build_args = json!(options.build_args).to_string();
+            query.push_str(&format!("&buildargs={}", encode(&build_args)));
+        }
+
+        let body = StreamBody::new(futures_util::StreamExt::map(
+            ReaderStream::new(reader),
+            |chunk| chunk.map(Frame::data),
+        ))
+        .boxed();
+
+        let log = &ctx.log;
+        let build = async {
+            let response = self
+                .send(
+                    Method::POST,
+                    &query,
+                    body,
+                    &[("content-type", "application/x-tar")],
+                )
+                .await?;
+
+            let mut image_id = None;
+            let mut partial = String::new();
+            let mut messages = MessageStream::new(response);
+            while let Some(message) = messages.next().await? {
+                if let Some(id) = message
+                    .aux
+                    .as_ref()
+                    .and_then(|aux| aux.get("ID"))
+                    .and_then(|id| id.as_str())
+                {
+                    image_id = Some(id.to_string());
+                }
+                if let Some(status) = message.status_line() {
+                    log.line(&status).await;
+                }
+                // Output comes in pieces that don't always end with a newline
+                if let Some(stream) = message.stream {
+                    partial.push_str(&stream);
+                    while let Some(end) = partial.find('\n') {
+                        let line = partial[..end].trim_end_matches('\r').to_string();
+                        partial.drain(..=end);
+                        log.line(&line).await;
+                    }
+                }
+            }
+
+            if !partial.is_empty() {
+                log.line(&partial).await;
+            }
+            Ok::<_, EngineError>(image_id)
+        };
+
+        let image_id = tokio::select! {
+            image_id = build => image_id?,
+            // Dropping the connection makes the daemon stop the build
+            _ = ctx.cancel.cancelled() => anyhow::bail!("build cancelled"),
+        };
+        archive
+            .await?
+            .map_err(|e| anyhow::anyhow!("Failed to send build context: {e}"))?;
+
+        // Prefer the registry digest, falling back to the image ID
+        let digest = match self.inspect_image(&options.tag).await {
+            Ok(info) => Some(info.digest.unwrap_or(info.id)),
+            Err(_) => image_id,
+        };
+
+        Ok(Image {
+            reference: options.tag.clone(),
+            digest,
+        })
+    }
+
+    async fn inspect_image(&self, image_ref: &str) -> anyhow::Result<ImageInfo> {
+        let body = self
+            .call(Method::GET, &format!("/images/{}/json", encode(image_ref)))
+            .await?;
+        let image: InspectedImage = parse_json(&body)?;
+
+        // Repo digests look like `image@sha256:abc123...`
+        let digest = image
+            .repo_digests
+            .first()
+            .and_then(|repo_digest| repo_digest.split_once('@'))
+            .map(|(_, digest)| digest.to_string());
+
+        Ok(ImageInfo {
+            id: image.id,
+            digest,
+            size: image.size,
+        })
+    }
+
+    async fn tag(&self, image_ref: &str, target: &str) -> anyhow::Result<()> {
+        let (repository, tag) = split_reference(target);
+        self.call(
+            Method::POST,
+            &format!(
+                "/images/{}/tag?repo={}&tag={}",
+                encode(image_ref),
+                encode(repository),
+                encode(tag)
+            ),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn push(
+        &self,
+        image_ref: &str,
+        auth: Option<&RegistryAuth>,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<String> {
+        // Credentials are sent as base64url-encoded JSON. The daemon wants
+        // the header even for registries that don't need any.
+        let auth = match auth {
+            Some(auth) => json!({
+                "username": auth.username,
+                "password": auth.password,
+                "serveraddress": auth.server,
+            }),
+            None => json!({}),
+        };
+        let auth = URL_SAFE.encode(auth.to_string());
+        let (repository, tag) = split_reference(image_ref);
+        let path = format!("/images/{}/push?tag={}", encode(repository), encode(tag));
+
+        let log = &ctx.log;
+        let push = async {
+            let response = self
+                .send(Method::POST, &path, empty(), &[("x-registry-auth", &auth)])
+                .await?;
+
+            let mut digest = None;
+            let mut messages = MessageStream::new(response);
+            while let Some(message) = messages.next().await? {
+                if let Some(pushed) = message
+                    .aux
+                    .as_ref()
+                    .and_then(|aux| aux.get("Digest"))
+                    .and_then(|digest| digest.as_str())
+                {
+                    digest = Some(pushed.to_string());
+                }
+                if let Some(status) = message.status_line() {
+                    log.line(&status).await;
+                }
+            }
+            Ok::<_, EngineError>(digest)
+        };
+
+        let digest = tokio::select! {
+            digest = push => digest?,
+            // Dropping the connection makes the daemon stop the push
+            _ = ctx.cancel.cancelled() => anyhow::bail!("push cancelled"),
+        };
+        digest.ok_or_else(|| {
+            anyhow::anyhow!("Pushed {image_ref}, but Docker didn't report its digest")
+        })
+    }
+
+    async fn run(
+        &self,
+        image_ref: &str,
+        container_name: &str,
+        config: &DeployConfig,
+    ) -> anyhow::Result<Container> {
+        let created: CreatedContainer = self
+            .call_json(
+                Method::POST,
+                &format!("/containers/create?name={}", encode(container_name)),
+                &create_body(image_ref, config),
+            )
+            .await?;
+        let id = created.id;
+
+        let started = async {
+            self.call(Method::POST, &format!("/containers/{id}/start"))
+                .await?;
+            self.published_ports(&id).await
+        };
+        let ports = match started.await {
+            Ok(ports) => ports,
+            Err(e) => {
+                // Don't leave a container behind that nothing tracks
+                let _ = self
+                    .call(Method::DELETE, &format!("/containers/{id}?force=1"))
+                    .await;
+                return Err(e.into());
+            }
+        };
+
+        Ok(Container {
+            id,
+            name: container_name.to_string(),
+            ports,
+        })
+    }
+
+    async fn stop(&self, container_id: &str) -> anyhow::Result<()> {
+        // Stopping an already stopped container answers 304
+        self.call(
+            Method::POST,
+            &format!("/containers/{container_id}/stop?t={STOP_TIMEOUT}"),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn start(&self, container_id: &str) -> anyhow::Result<Vec<PublishedPort>> {
+        self.call(Method::POST, &format!("/containers/{container_id}/start"))
+            .await?;
+        Ok(self.published_ports(container_id).await?)
+    }
+
+    async fn remove(&self, container_id: &str) -> anyhow::Result<()> {
+        self.call(Method::DELETE, &format!("/containers/{container_id}"))
+            .await?;
+        Ok(())
+    }
+
+    async fn logs(
+        &self,
+        container_id: &str,
+        options: &LogOptions,
+    ) -> anyhow::Result<Receiver<LogLine>> {
+        let mut query = format!("/containers/{container_id}/logs?stdout=1&stderr=1&timestamps=1");
+        if let Some(since) = options.since {
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            let since = now.saturating_sub(since).as_secs();
+            query.push_str(&format!("&since={since}"));
+        }
+        if let Some(tail) = options.tail {
+            query.push_str(&format!("&tail={tail}"));
+        }
+        if options.follow {
+            query.push_str("&follow=1");
+        }
+
+        let response = self.send(Method::GET, &query, empty(), &[]).await?;
+        let (tx, rx) = mpsc::channel(LOG_BUFFER);
+        tokio::spawn(forward_log_frames(response, tx));
+
+        Ok(rx)
+    }
+
+    async fn save(&self, image_ref: &str, path: &Path) -> anyhow::Result<()> {
+        let response = self
+            .send(
+                Method::GET,
+                &format!("/images/{}/get", encode(image_ref)),
+                empty(),
+                &[],
+            )
+            .await?;
+
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create image archive: {e}"))?;
+        let mut body = response.into_body();
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(|e| EngineError::Connection(e.to_string()))?;
+            if let Ok(data) = frame.into_data() {
+                file.write_all(&data)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to write image archive: {e}"))?;
+            }
+        }
+        file.flush()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to write image archive: {e}"))?;
+
+        Ok(())
+    }
+
+    async fn load(&self, path: &Path) -> anyhow::Result<String> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to open image archive: {e}"))?;
+        let body = StreamBody::new(futures_util::StreamExt::map(
+            ReaderStream::new(file),
+            |chunk| chunk.map(Frame::data),
+        ))
+        .boxed();
+        let response = self
+            .send(
+                Method::POST,
+                "/images/load?quiet=1",
+                body,
+                &[("content-type", "application/x-tar")],
+            )
+            .await?;
+
+        // The result is reported as `Loaded image: <reference>`, or `Loaded
+        // image ID: <id>` for untagged images
+        let mut loaded = None;
+        let mut messages = MessageStream::new(response);
+        while let Some(message) = messages.next().await? {
+            let text = message.stream.or(message.status).unwrap_or_default();
+            if let Some((_, image)) = text.trim().split_once(": ") {
+                loaded = Some(image.to_string());
+            }
+        }
+
+        loaded.ok_or_else(|| anyhow::anyhow!("Docker didn't say which image it loaded"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        io::Read,
+        sync::{Arc, Mutex},
+    };
+
+    use hyper::{HeaderMap, server::conn::http1, service::service_fn};
+    use tempfile::TempDir;
+    use tokio::net::UnixListener;
+
+    use super::*;
+    use crate::{builders::log::BuildLog, runtime::BuildSecret};
+
+    type ResponseBody = BoxBody<Bytes, Infallible>;
+
+    /// A request the stub daemon received.
+    #[derive(Debug, Clone)]
+    struct Received {
+        method: Method,
+        /// Path and query, without the API version prefix
+        path: String,
+        headers: HeaderMap,
+        body: Bytes,
+    }
+
+    /// A stand-in for the Docker daemon on a Unix socket, answering each
+    /// request with the handler and recording it.
+    struct StubDaemon {
+        _dir: TempDir,
+        runtime: EngineRuntime,
+        received: Arc<Mutex<Vec<Received>>>,
+    }
+
+    impl StubDaemon {
+        fn start<F>(handler: F) -> Self
+        where
+            F: Fn(&Received) -> Response<ResponseBody> + Send + Sync + 'static,
+        {
+            let dir = tempfile::tempdir().unwrap();
+            let socket = dir.path().join("docker.sock");
+            let listener = UnixListener::bind(&socket).unwrap();
+            let handler = Arc::new(handler);
+            let received = Arc::new(Mutex::new(Vec::new()));
+
+            let recorded = Arc::clone(&received);
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let (handler, recorded) = (Arc::clone(&handler), Arc::clone(&recorded));
+                    let service = service_fn(move |request: Request<Incoming>| {
+                        let (handler, recorded) = (Arc::clone(&handler), Arc::clone(&recorded));
+                        async move {
+                            let (parts, body) = request.into_parts();
+                            let path = parts.uri.to_string();
+                            let request = Received {
+                                method: parts.method,
+                                path: path
+                                    .strip_prefix(&format!("/{API_VERSION}"))
+                                    .unwrap_or(&path)
+                                    .to_string(),
+                                headers: parts.headers,
+                                body: body.collect().await.unwrap().to_bytes(),
+                            };
+                            let response = handler(&request);
+                            recorded.lock().unwrap().push(request);
+                            Ok::<_, Infallible>(response)
+                        }
+                    });
+                    tokio::spawn(
+                        http1::Builder::new().serve_connection(TokioIo::new(stream), service),
+                    );
+                }
+            });
+
+            let runtime = EngineRuntime::new(&format!("unix://{}", socket.display())).unwrap();
+            StubDaemon {
+                _dir: dir,
+                runtime,
+                received,
+            }
+        }
+
+        /// Returns the requests received so far, in order.
+        fn received(&self) -> Vec<Received> {
+            self.received.lock().unwrap().clone()
+        }
+    }
+
+    /// Responds with the body sent in the given chunks.
+    fn respond(status: u16, chunks: Vec<Bytes>) -> Response<ResponseBody> {
+        let frames = chunks.into_iter().map(|chunk| Ok(Frame::data(chunk)));
+        Response::builder()
+            .status(status)
+            .body(StreamBody::new(futures_util::stream::iter(frames)).boxed())
+            .unwrap()
+    }
+
+    fn respond_json(status: u16, body: serde_json::Value) -> Response<ResponseBody> {
+        respond(status, vec![Bytes::from(body.to_string())])
+    }
+
+    /// Responds with a progress stream of newline-separated messages.
+    fn respond_messages(messages: &[serde_json::Value]) -> Response<ResponseBody> {
+        let chunks = messages
+            .iter()
+            .map(|message| Bytes::from(format!("{message}\n")))
+            .collect();
+        respond(200, chunks)
+    }
+
+    /// Returns a multiplexed log frame from stdout (1) or stderr (2).
+    fn log_frame(stream: u8, payload: &str) -> Vec<u8> {
+        let mut frame = vec![stream, 0, 0, 0];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(payload.as_bytes());
+        frame
+    }
+
+    /// A build context with a Dockerfile, some source and an ignored file.
+    fn build_context() -> (TempDir, BuildOptions) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Dockerfile"), "FROM scratch\nCOPY . /\n").unwrap();
+        std::fs::write(dir.path().join("app.txt"), "hello").unwrap();
+        std::fs::write(dir.path().join(".env"), "TOKEN=secret").unwrap();
+        std::fs::write(dir.path().join(".dockerignore"), ".env\n").unwrap();
+
+        let options = BuildOptions {
+            context: dir.path().to_path_buf(),
+            dockerfile: dir.path().join("Dockerfile"),
+            tag: "app:latest".to_string(),
+            no_cache: false,
+            target: Some("release".to_string()),
+            build_args: BTreeMap::from([("VERSION".to_string(), "1.2".to_string())]),
+            secrets: Vec::new(),
+        };
+        (dir, options)
+    }
+
+    /// Returns the contents of each file in a tar archive, by path.
+    fn archive_files(archive: &[u8]) -> BTreeMap<String, String> {
+        let mut files = BTreeMap::new();
+        for entry in tar::Archive::new(archive).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path().unwrap().display().to_string();
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            files.insert(path, contents);
+        }
+        files
+    }
+
+    #[tokio::test]
+    async fn build_streams_context_and_logs_output() {
+        let daemon = StubDaemon::start(|request| {
+            if request.path.starts_with("/build?") {
+                respond_messages(&[
+                    json!({ "stream": "Step 1/2 : FROM scratch\n" }),
+                    json!({ "stream": " ---> Running" }),
+                    json!({ "stream": " in 0123abcd\r\n" }),
+                    json!({ "aux": { "ID": "sha256:built" } }),
+                    json!({ "stream": "Successfully built built\n" }),
+                ])
+            } else {
+                respond_json(
+                    200,
+                    json!({
+                        "Id": "sha256:built",
+                        "RepoDigests": ["app@sha256:pushed"],
+                        "Size": 42,
+                    }),
+                )
+            }
+        });
+        let (_dir, options) = build_context();
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_path = log_dir.path().join("build.log");
+        let ctx = BuildContext {
+            log: BuildLog::create(&log_path).await.unwrap(),
+            ..Default::default()
+        };
+
+        let image = daemon.runtime.build(&options, &ctx).await.unwrap();
+
+        assert_eq!(image.reference, "app:latest");
+        // The registry digest is preferred over the image ID
+        assert_eq!(image.digest.as_deref(), Some("sha256:pushed"));
+
+        let received = daemon.received();
+        let build = &received[0];
+        assert_eq!(build.method, Method::POST);
+        assert_eq!(
+            build.path,
+            "/build?t=app%3Alatest&dockerfile=Dockerfile&rm=1&forcerm=1&target=release\
+             &buildargs=%7B%22VERSION%22%3A%221.2%22%7D"
+        );
+        assert_eq!(build.headers["content-type"], "application/x-tar");
+        let files = archive_files(&build.body);
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            [".dockerignore", "Dockerfile", "app.txt"]
+        );
+        assert_eq!(files["app.txt"], "hello");
+        assert_eq!(received[1].path, "/images/app%3Alatest/json");
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        assert_eq!(
+            log,
+            "Step 1/2 : FROM scratch\n ---> Running in 0123abcd\nSuccessfully built built\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn build_digest_falls_back_to_image_id() {
+        let daemon = StubDaemon::start(|request| {
+            if request.path.starts_with("/build?") {
+                respond_messages(&[json!({ "aux": { "ID": "sha256:built" } })])
+            } else {
+                // Local images have no repo digests
+                respond_json(200, json!({ "Id": "sha256:inspected", "Size": 42 }))
+            }
+        });
+        let (_dir, options) = build_context();
+
+        let image = daemon
+            .runtime
+            .build(&options, &BuildContext::default())
+            .await
+            .unwrap();
+
+        assert_eq!(image.digest.as_deref(), Some("sha256:inspected"));
+    }
+
+    #[tokio::test]
+    async fn build_digest_falls_back_to_built_id_if_inspect_fails() {
+        let daemon = StubDaemon::start(|request| {
+            if request.path.starts_with("/build?") {
+                respond_messages(&[json!({ "aux": { "ID": "sha256:built" } })])
+            } else {
+                respond_json(500, json!({ "message": "inspect failed" }))
+            }
+        });
+        let (_dir, options) = build_context();
+
+        let image = daemon
+            .runtime
+            .build(&options, &BuildContext::default())
+            .await
+            .unwrap();
+
+        assert_eq!(image.digest.as_deref(), Some("sha256:built"));
+    }
+
+    #[tokio::test]
+    async fn build_with_secrets_needs_the_cli() {
+        let daemon = StubDaemon::start(|_| respond_json(200, json!({})));
+        let (_dir, mut options) = build_context();
+        options.secrets.push(BuildSecret {
+            id: "npm_token".to_string(),
+            value: "s3cr3t".to_string(),
+        });
+
+        let err = daemon
+            .runtime
+            .build(&options, &BuildContext::default())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("docker-cli"), "{err}");
+        // Nothing is sent to the daemon
+        assert!(daemon.received().is_empty());
+    }
+
+    #[tokio::test]
+    async fn build_fails_on_stream_error() {
+        let daemon = StubDaemon::start(|_| {
+            respond_messages(&[
+                json!({ "stream": "Step 1/2 : RUN false\n" }),
+                json!({
+                    "errorDetail": { "code": 1, "message": "returned a non-zero code: 1" },
+                    "error": "The command '/bin/sh -c false' returned a non-zero code: 1",
+                }),
+            ])
+        });
+        let (_dir, options) = build_context();
+
+        let err = daemon
+            .runtime
+            .build(&options, &BuildContext::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<EngineError>(),
+            Some(&EngineError::Stream(
+                "The command '/bin/sh -c false' returned a non-zero code: 1".to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn error_status_is_read_from_json_body() {
+        let daemon = StubDaemon::start(|_| {
+            respond_json(404, json!({ "message": "No such image: missing:latest" }))
+        });
+
+        let err = daemon
+            .runtime
+            .inspect_image("missing:latest")
+            .await
+            .unwrap_err();
+
+        let err = err.downcast_ref::<EngineError>().unwrap();
+        assert!(err.is_not_found());
+        assert_eq!(
+            err,
+            &EngineError::Api {
+                status: 404,
+                message: "No such image: missing:latest".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn error_status_without_json_body_keeps_text() {
+        let daemon =
+            StubDaemon::start(|_| respond(500, vec![Bytes::from_static(b"page not found\n")]));
+
+        let err = daemon.runtime.remove("app").await.unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<EngineError>(),
+            Some(&EngineError::Api {
+                status: 500,
+                message: "page not found".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn logs_are_demultiplexed_into_lines() {
+        let daemon = StubDaemon::start(|_| {
+            let mut stream = Vec::new();
+            stream.extend(log_frame(1, "2024-01-15T10:30:00.000000000Z first\n"));
+            // A line split across frames, with stderr in between
+            stream.extend(log_frame(1, "2024-01-15T10:30:01.000000000Z sec"));
+            stream.extend(log_frame(2, "2024-01-15T10:30:01.500000000Z oops\n"));
+            stream.extend(log_frame(1, "ond\n"));
+            stream.extend(log_frame(1, "2024-01-15T10:30:02.000000000Z unfinished"));
+            // Frames split across chunks of the response
+            let chunks = stream.chunks(5).map(Bytes::copy_from_slice).collect();
+            respond(200, chunks)
+        });
+
+        let mut logs = daemon
+            .runtime
+            .logs("app", &LogOptions::default())
+            .await
+            .unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = logs.recv().await {
+            lines.push((line.timestamp.unwrap(), line.message));
+        }
+
+        assert_eq!(
+            lines,
+            [
+                (
+                    "2024-01-15T10:30:00.000000000Z".to_string(),
+                    "first".to_string()
+                ),
+                (
+                    "2024-01-15T10:30:01.500000000Z".to_string(),
+                    "oops".to_string()
+                ),
+                (
+                    "2024-01-15T10:30:01.000000000Z".to_string(),
+                    "second".to_string()
+                ),
+                (
+                    "2024-01-15T10:30:02.000000000Z".to_string(),
+                    "unfinished".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            daemon.received()[0].path,
+            "/containers/app/logs?stdout=1&stderr=1&timestamps=1"
+        );
+    }
+
+    #[tokio::test]
+    async fn push_returns_digest_and_sends_credentials() {
+        let daemon = StubDaemon::start(|_| {
+            respond_messages(&[
+                json!({ "status": "The push refers to repository [registry.example.com/app]" }),
+                json!({ "status": "Pushing", "id": "abc", "progress": "[=>   ]" }),
+                json!({ "status": "Pushed", "id": "abc" }),
+                json!({ "aux": { "Tag": "v1", "Digest": "sha256:pushed", "Size": 42 } }),
+            ])
+        });
+        let auth = RegistryAuth {
+            server: "registry.example.com".to_string(),
+            username: "deploy".to_string(),
+            password: "hunter2".to_string(),
+        };
+
+        let digest = daemon
+            .runtime
+            .push(
+                "registry.example.com/app:v1",
+                Some(&auth),
+                &BuildContext::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(digest, "sha256:pushed");
+        let push = &daemon.received()[0];
+        assert_eq!(push.path, "/images/registry.example.com%2Fapp/push?tag=v1");
+        let credentials = URL_SAFE.decode(&push.headers["x-registry-auth"]).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&credentials).unwrap(),
+            json!({
+                "username": "deploy",
+                "password": "hunter2",
+                "serveraddress": "registry.example.com",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn push_without_digest_fails() {
+        let daemon = StubDaemon::start(|_| respond_messages(&[json!({ "status": "Pushed" })]));
+
+        let err = daemon
+            .runtime
+            .push("app:v1", None, &BuildContext::default())
+            .await
+            .unwrap_err();
+
+        assert!(
+            err.to_string().contains("didn't report its digest"),
+            "{err}"
+        );
+    }
+}
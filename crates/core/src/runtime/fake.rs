@@ -0,0 +1,404 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::RangeInclusive,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, Receiver};
+
+use crate::{
+    builders::{BuildContext, Image},
+    config::DeployConfig,
+    deployers::{Container, LogLine, LogOptions, PublishedPort},
+    runtime::{BuildOptions, ContainerRuntime, ImageInfo, RegistryAuth},
+};
+
+/// Host ports handed out for container ports without a fixed one, like
+/// Docker's ephemeral port range
+const HOST_PORTS: RangeInclusive<u16> = 32768..=60999;
+
+/// Size recorded for every fake image
+const IMAGE_SIZE: u64 = 1024 * 1024;
+
+/// An in-memory container runtime, for testing the agent without a container
+/// engine.
+///
+/// Builds succeed as long as the Dockerfile exists, and record an image
+/// without running anything, and pushes are recorded without reaching a
+/// registry. Containers started from those images are only recorded too:
+/// nothing listens on their ports, and their logs are just the lines added
+/// with [`FakeRuntime::push_log`].
+#[derive(Default)]
+pub struct FakeRuntime {
+    state: Mutex<FakeState>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    images: HashMap<String, ImageInfo>,
+    containers: HashMap<String, FakeContainer>,
+    /// Digests of pushed images, by reference
+    pushed: HashMap<String, String>,
+    next_id: u64,
+    /// Offset of the next host port to hand out in [`HOST_PORTS`]
+    next_host_port: u16,
+    /// How long builds take
+    build_time: Duration,
+}
+
+impl FakeState {
+    /// Returns a new unique ID, formatted like a sha256 digest.
+    fn new_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:064x}", self.next_id)
+    }
+}
+
+/// A container recorded by the fake runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeContainer {
+    pub name: String,
+    /// Reference of the image the container was started from
+    pub image: String,
+    pub running: bool,
+    pub ports: Vec<PublishedPort>,
+    pub env: BTreeMap<String, String>,
+    pub logs: Vec<LogLine>,
+}
+
+/// Contents of an archive written by [`FakeRuntime::save`].
+#[derive(Serialize, Deserialize)]
+struct SavedImage {
+    reference: String,
+    image: ImageInfo,
+}
+
+impl FakeRuntime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the image with the given reference, if one has been built or
+    /// loaded.
+    pub fn image(&self, image_ref: &str) -> Option<ImageInfo> {
+        self.lock().images.get(image_ref).cloned()
+    }
+
+    /// Makes builds take this long, so that they can be cancelled while
+    /// they run.
+    pub fn set_build_time(&self, build_time: Duration) {
+        self.lock().build_time = build_time;
+    }
+
+    /// Returns the digest an image was pushed with, if it has been pushed.
+    pub fn pushed(&self, image_ref: &str) -> Option<String> {
+        self.lock().pushed.get(image_ref).cloned()
+    }
+
+    /// Returns the container with the given ID, if it hasn't been removed.
+    pub fn container(&self, container_id: &str) -> Option<FakeContainer> {
+        self.lock().containers.get(container_id).cloned()
+    }
+
+    /// Adds a line to a container's logs, as if the container wrote it.
+    pub fn push_log(&self, container_id: &str, message: &str) -> anyhow::Result<()> {
+        let mut state = self.lock();
+        let container = state
+            .containers
+            .get_mut(container_id)
+            .ok_or_else(|| anyhow::anyhow!("No such container: {container_id}"))?;
+        container.logs.push(LogLine {
+            timestamp: None,
+            message: message.to_string(),
+        });
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FakeState> {
+        // The state is always left consistent, so a panic elsewhere while
+        // holding the lock doesn't matter
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl ContainerRuntime for FakeRuntime {
+    async fn build(&self, options: &BuildOptions, ctx: &BuildContext) -> anyhow::Result<Image> {
+        if !options.dockerfile.is_file() {
+            anyhow::bail!("Dockerfile not found: {}", options.dockerfile.display());
+        }
+        ctx.log
+            .line(&format!(
+                "Building {} from {} (fake runtime)",
+                options.tag,
+                options.dockerfile.display()
+            ))
+            .await;
+
+        let build_time = self.lock().build_time;
+        tokio::select! {
+            _ = tokio::time::sleep(build_time) => {}
+            _ = ctx.cancel.cancelled() => anyhow::bail!("build cancelled"),
+        }
+
+        let mut state = self.lock();
+        let id = format!("sha256:{}", state.new_id());
+        state.images.insert(
+            options.tag.clone(),
+            ImageInfo {
+                id: id.clone(),
+                digest: None,
+                size: IMAGE_SIZE,
+            },
+        );
+
+        Ok(Image::with_digest(&options.tag, id))
+    }
+
+    async fn inspect_image(&self, image_ref: &str) -> anyhow::Result<ImageInfo> {
+        self.image(image_ref)
+            .ok_or_else(|| anyhow::anyhow!("No such image: {image_ref}"))
+    }
+
+    async fn tag(&self, image_ref: &str, target: &str) -> anyhow::Result<()> {
+        let image = self.inspect_image(image_ref).await?;
+        self.lock().images.insert(target.to_string(), image);
+        Ok(())
+    }
+
+    async fn push(
+        &self,
+        image_ref: &str,
+        _auth: Option<&RegistryAuth>,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<String> {
+        self.inspect_image(image_ref).await?;
+        ctx.log
+            .line(&format!("Pushing {image_ref} (fake runtime)"))
+            .await;
+
+        let mut state = self.lock();
+        let digest = format!("sha256:{}", state.new_id());
+        state.pushed.insert(image_ref.to_string(), digest.clone());
+        if let Some(image) = state.images.get_mut(image_ref) {
+            image.digest = Some(digest.clone());
+        }
+        Ok(digest)
+    }
+
+    async fn run(
+        &self,
+        image_ref: &str,
+        container_name: &str,
+        config: &DeployConfig,
+    ) -> anyhow::Result<Container> {
+        let mut state = self.lock();
+        if !state.images.contains_key(image_ref) {
+            anyhow::bail!("No such image: {image_ref}");
+        }
+        if state.containers.values().any(|c| c.name == container_name) {
+            anyhow::bail!("Container name {container_name} is already in use");
+        }
+
+        let mut ports = Vec::new();
+        for port in &config.ports {
+            let host = match port.host {
+                Some(host) => {
+                    let taken = state.containers.values().any(|c| {
+                        c.running
+                            && c.ports
+                                .iter()
+                                .any(|p| p.host == host && p.protocol == port.protocol)
+                    });
+                    if taken {
+                        anyhow::bail!("Host port {host} is already allocated");
+                    }
+                    host
+                }
+                None => {
+                    let host = HOST_PORTS.start() + state.next_host_port;
+                    // Start over at the beginning of the range once it's used up
+                    state.next_host_port =
+                        (state.next_host_port + 1) % (HOST_PORTS.end() - HOST_PORTS.start() + 1);
+                    host
+                }
+            };
+            ports.push(PublishedPort {
+                container: port.container,
+                host,
+                protocol: port.protocol,
+            });
+        }
+
+        let id = state.new_id();
+        state.containers.insert(
+            id.clone(),
+            FakeContainer {
+                name: container_name.to_string(),
+                image: image_ref.to_string(),
+                running: true,
+                ports: ports.clone(),
+                env: config.env.clone(),
+                logs: Vec::new(),
+            },
+        );
+
+        Ok(Container {
+            id,
+            name: container_name.to_string(),
+            ports,
+        })
+    }
+
+    async fn stop(&self, container_id: &str) -> anyhow::Result<()> {
+        let mut state = self.lock();
+        let container = state
+            .containers
+            .get_mut(container_id)
+            .ok_or_else(|| anyhow::anyhow!("No such container: {container_id}"))?;
+        container.running = false;
+        Ok(())
+    }
+
+    async fn start(&self, container_id: &str) -> anyhow::Result<Vec<PublishedPort>> {
+        let mut state = self.lock();
+        let container = state
+            .containers
+            .get_mut(container_id)
+            .ok_or_else(|| anyhow::anyhow!("No such container: {container_id}"))?;
+        container.running = true;
+        Ok(container.ports.clone())
+    }
+
+    async fn remove(&self, container_id: &str) -> anyhow::Result<()> {
+        let mut state = self.lock();
+        match state.containers.get(container_id) {
+            None => anyhow::bail!("No such container: {container_id}"),
+            Some(container) if container.running => {
+                anyhow::bail!("Container {container_id} is running: stop it first")
+            }
+            Some(_) => {
+                state.containers.remove(container_id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends the lines added so far. Following doesn't wait for more.
+    async fn logs(
+        &self,
+        container_id: &str,
+        options: &LogOptions,
+    ) -> anyhow::Result<Receiver<LogLine>> {
+        let container = self
+            .container(container_id)
+            .ok_or_else(|| anyhow::anyhow!("No such container: {container_id}"))?;
+
+        let skip = options
+            .tail
+            .map_or(0, |tail| container.logs.len().saturating_sub(tail as usize));
+        let lines = container.logs.into_iter().skip(skip).collect::<Vec<_>>();
+
+        let (tx, rx) = mpsc::channel(lines.len().max(1));
+        for line in lines {
+            // The channel has room for every line
+            let _ = tx.try_send(line);
+        }
+
+        Ok(rx)
+    }
+
+    async fn save(&self, image_ref: &str, path: &Path) -> anyhow::Result<()> {
+        let image = self.inspect_image(image_ref).await?;
+        let saved = serde_json::to_vec(&SavedImage {
+            reference: image_ref.to_string(),
+            image,
+        })?;
+        tokio::fs::write(path, saved)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to write image archive: {e}"))
+    }
+
+    async fn load(&self, path: &Path) -> anyhow::Result<String> {
+        let saved = tokio::fs::read(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read image archive: {e}"))?;
+        let saved: SavedImage = serde_json::from_slice(&saved)
+            .map_err(|e| anyhow::anyhow!("Invalid image archive: {e}"))?;
+
+        self.lock()
+            .images
+            .insert(saved.reference.clone(), saved.image);
+        Ok(saved.reference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::config::{DeployTarget, PortMapping};
+
+    fn runtime_with_image() -> FakeRuntime {
+        let runtime = FakeRuntime::new();
+        runtime.lock().images.insert(
+            "app:latest".to_string(),
+            ImageInfo {
+                id: "sha256:1".to_string(),
+                digest: None,
+                size: IMAGE_SIZE,
+            },
+        );
+        runtime
+    }
+
+    fn publishing(port: &str) -> DeployConfig {
+        let mut config = DeployConfig::new(DeployTarget::LocalDocker);
+        config.ports = vec![PortMapping::from_str(port).unwrap()];
+        config
+    }
+
+    #[tokio::test]
+    async fn host_ports_wrap_around_at_end_of_range() {
+        let runtime = runtime_with_image();
+        runtime.lock().next_host_port = HOST_PORTS.end() - HOST_PORTS.start();
+
+        let last = runtime
+            .run("app:latest", "last", &publishing("8080"))
+            .await
+            .unwrap();
+        let first = runtime
+            .run("app:latest", "first", &publishing("8080"))
+            .await
+            .unwrap();
+
+        assert_eq!(last.ports[0].host, *HOST_PORTS.end());
+        assert_eq!(first.ports[0].host, *HOST_PORTS.start());
+    }
+
+    #[tokio::test]
+    async fn fixed_host_port_can_only_be_bound_once() {
+        let runtime = runtime_with_image();
+        let first = runtime
+            .run("app:latest", "first", &publishing("8080:80"))
+            .await
+            .unwrap();
+
+        runtime
+            .run("app:latest", "second", &publishing("8080:80"))
+            .await
+            .unwrap_err();
+
+        // Stopping the first container frees the port
+        runtime.stop(&first.id).await.unwrap();
+        runtime
+            .run("app:latest", "second", &publishing("8080:80"))
+            .await
+            .unwrap();
+    }
+}
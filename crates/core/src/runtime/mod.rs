@@ -0,0 +1,315 @@
+pub mod cli;
+pub(crate) mod context;
+pub mod engine;
+#[cfg(any(test, feature = "testing"))]
+pub mod fake;
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Receiver;
+
+use crate::{
+    builders::{BuildContext, Image},
+    config::DeployConfig,
+    deployers::{Container, LogLine, LogOptions, PublishedPort},
+    runtime::{
+        cli::CliRuntime,
+        engine::{EngineError, EngineRuntime},
+    },
+};
+
+/// The container engine images are built and run with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuntimeKind {
+    /// The Docker Engine API, over the daemon socket named by `DOCKER_HOST`
+    /// (default `/var/run/docker.sock`). Builds use the classic builder.
+    #[default]
+    #[serde(alias = "docker-api")]
+    Docker,
+    /// The `docker` CLI, which builds with BuildKit
+    #[serde(rename = "docker-cli")]
+    DockerCli,
+    /// The `podman` CLI, which also works rootless
+    Podman,
+    /// An in-memory runtime that runs nothing, only available to tests
+    #[cfg(any(test, feature = "testing"))]
+    Fake,
+}
+
+impl RuntimeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuntimeKind::Docker => "docker",
+            RuntimeKind::DockerCli => "docker-cli",
+            RuntimeKind::Podman => "podman",
+            #[cfg(any(test, feature = "testing"))]
+            RuntimeKind::Fake => "fake",
+        }
+    }
+}
+
+impl fmt::Display for RuntimeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RuntimeKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            // `docker-api` was the Engine API's name while it was opt-in
+            "docker" | "docker-api" => Ok(RuntimeKind::Docker),
+            "docker-cli" => Ok(RuntimeKind::DockerCli),
+            "podman" => Ok(RuntimeKind::Podman),
+            #[cfg(any(test, feature = "testing"))]
+            "fake" => Ok(RuntimeKind::Fake),
+            _ => Err(format!(
+                "Unknown container runtime: {s}. Valid options: docker, docker-cli, podman"
+            )),
+        }
+    }
+}
+
+/// What to build an image from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildOptions {
+    /// Directory sent as the build context
+    pub context: PathBuf,
+    /// Dockerfile to build, which doesn't have to be in the context
+    pub dockerfile: PathBuf,
+    /// Reference to tag the built image with (e.g. "myapp:latest")
+    pub tag: String,
+    /// Build without using cached layers, pulling base images afresh
+    pub no_cache: bool,
+    /// Stage of a multi-stage Dockerfile to build, instead of the last one
+    pub target: Option<String>,
+    /// Values for the Dockerfile's `ARG`s
+    pub build_args: BTreeMap<String, String>,
+    /// Secrets mounted into `RUN --mount=type=secret` steps. They're kept
+    /// out of the build context and the image.
+    pub secrets: Vec<BuildSecret>,
+}
+
+/// A secret made available to a build.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BuildSecret {
+    /// ID the Dockerfile mounts the secret by
+    pub id: String,
+    pub value: String,
+}
+
+impl fmt::Debug for BuildSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BuildSecret")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Credentials for pushing to a registry.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RegistryAuth {
+    /// Registry host the credentials are for (e.g. "registry.example.com")
+    pub server: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl RegistryAuth {
+    /// Parses credentials written as `<username>:<password>`.
+    pub fn parse(server: &str, credentials: &str) -> Option<Self> {
+        let (username, password) = credentials.trim().split_once(':')?;
+        if username.is_empty() {
+            return None;
+        }
+        Some(RegistryAuth {
+            server: server.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+impl fmt::Debug for RegistryAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryAuth")
+            .field("server", &self.server)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Splits an image reference into its repository and tag, e.g.
+/// `localhost:5000/app:v1` into `localhost:5000/app` and `v1`. The tag
+/// defaults to `latest`.
+pub fn split_reference(image_ref: &str) -> (&str, &str) {
+    match image_ref.rsplit_once(':') {
+        // A colon before the last slash is a registry port
+        Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+        _ => (image_ref, "latest"),
+    }
+}
+
+/// Details of an image in the runtime's image store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageInfo {
+    /// Content-addressable image ID (e.g. "sha256:abc123...")
+    pub id: String,
+    /// Registry digest, if the image has been pushed or pulled
+    pub digest: Option<String>,
+    /// Size of the image in bytes
+    pub size: u64,
+}
+
+/// Trait for the container engine that builds images and runs containers.
+#[async_trait::async_trait]
+pub trait ContainerRuntime: Send + Sync {
+    /// Builds an image, writing the build's output to the context's log.
+    ///
+    /// If the build is cancelled through the context, it's stopped and an
+    /// error is returned.
+    async fn build(&self, options: &BuildOptions, ctx: &BuildContext) -> anyhow::Result<Image>;
+
+    /// Looks up an image by reference or ID.
+    async fn inspect_image(&self, image_ref: &str) -> anyhow::Result<ImageInfo>;
+
+    /// Adds another reference to an image.
+    async fn tag(&self, image_ref: &str, target: &str) -> anyhow::Result<()>;
+
+    /// Pushes an image to the registry named in its reference, writing
+    /// progress to the context's log, and returns the digest the registry
+    /// stored it under.
+    ///
+    /// If the push is cancelled through the context, it's stopped and an
+    /// error is returned.
+    async fn push(
+        &self,
+        image_ref: &str,
+        auth: Option<&RegistryAuth>,
+        ctx: &BuildContext,
+    ) -> anyhow::Result<String>;
+
+    /// Starts a new container from an image.
+    ///
+    /// # Arguments
+    ///
+    /// * `image_ref` - Reference of the image to run (e.g., "myapp:latest")
+    /// * `container_name` - Name to give the new container
+    /// * `config` - Deploy settings from nimble.yaml (ports, env, command, etc.)
+    ///
+    /// # Returns
+    ///
+    /// Returns the started `Container`, with the host ports its ports were
+    /// published on.
+    async fn run(
+        &self,
+        image_ref: &str,
+        container_name: &str,
+        config: &DeployConfig,
+    ) -> anyhow::Result<Container>;
+
+    /// Stops a running container, giving it time to shut down gracefully.
+    async fn stop(&self, container_id: &str) -> anyhow::Result<()>;
+
+    /// Starts a stopped container again, returning the host ports its ports
+    /// are now published on.
+    async fn start(&self, container_id: &str) -> anyhow::Result<Vec<PublishedPort>>;
+
+    /// Removes a stopped container.
+    async fn remove(&self, container_id: &str) -> anyhow::Result<()>;
+
+    /// Reads a container's logs.
+    ///
+    /// Lines are sent to the returned receiver as they are read. The channel
+    /// closes once all requested lines have been sent or, when following,
+    /// once the container stops. Dropping the receiver stops reading.
+    async fn logs(
+        &self,
+        container_id: &str,
+        options: &LogOptions,
+    ) -> anyhow::Result<Receiver<LogLine>>;
+
+    /// Writes an image to a tar archive.
+    async fn save(&self, image_ref: &str, path: &Path) -> anyhow::Result<()>;
+
+    /// Loads an image from a tar archive written by `save`, returning its
+    /// reference.
+    async fn load(&self, path: &Path) -> anyhow::Result<String>;
+}
+
+/// Returns a runtime of the given kind. Each call to this makes a new fake
+/// runtime, so share the returned runtime between builds and deploys.
+///
+/// Fails if the Engine API runtime is selected and `DOCKER_HOST` isn't a
+/// supported address.
+pub fn select_runtime(kind: RuntimeKind) -> Result<Arc<dyn ContainerRuntime>, EngineError> {
+    Ok(match kind {
+        RuntimeKind::Docker => Arc::new(EngineRuntime::from_env()?),
+        RuntimeKind::DockerCli => Arc::new(CliRuntime::docker()),
+        RuntimeKind::Podman => Arc::new(CliRuntime::podman()),
+        #[cfg(any(test, feature = "testing"))]
+        RuntimeKind::Fake => Arc::new(fake::FakeRuntime::new()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn docker_runtime_is_the_engine_api_and_the_cli_is_opt_in() {
+        assert_eq!(RuntimeKind::default(), RuntimeKind::Docker);
+        assert_eq!("docker".parse(), Ok(RuntimeKind::Docker));
+        assert_eq!("Docker-API".parse(), Ok(RuntimeKind::Docker));
+        assert_eq!("docker-cli".parse(), Ok(RuntimeKind::DockerCli));
+        for kind in [
+            RuntimeKind::Docker,
+            RuntimeKind::DockerCli,
+            RuntimeKind::Podman,
+            RuntimeKind::Fake,
+        ] {
+            assert_eq!(kind.as_str().parse(), Ok(kind));
+        }
+        assert!("podmn".parse::<RuntimeKind>().is_err());
+    }
+
+    #[test]
+    fn registry_credentials_are_username_and_password() {
+        let auth = RegistryAuth::parse("localhost:5000", "deploy:s3cr:et\n").unwrap();
+        assert_eq!(auth.server, "localhost:5000");
+        assert_eq!(auth.username, "deploy");
+        // Only the first colon separates them
+        assert_eq!(auth.password, "s3cr:et");
+    }
+
+    #[test]
+    fn malformed_registry_credentials_are_rejected() {
+        for credentials in ["", "deploy", ":password", "\n"] {
+            assert_eq!(RegistryAuth::parse("localhost:5000", credentials), None);
+        }
+    }
+
+    #[test]
+    fn reference_is_split_into_repository_and_tag() {
+        assert_eq!(split_reference("app:v1"), ("app", "v1"));
+        assert_eq!(split_reference("app"), ("app", "latest"));
+        assert_eq!(
+            split_reference("localhost:5000/team/app:v1"),
+            ("localhost:5000/team/app", "v1")
+        );
+        assert_eq!(
+            split_reference("localhost:5000/app"),
+            ("localhost:5000/app", "latest")
+        );
+    }
+}